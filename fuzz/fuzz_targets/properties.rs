@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use particle_affinity_wasm::logging::{set_log_level, LogLevel};
+use particle_affinity_wasm::testing;
+
+fuzz_target!(|data: &[u8]| {
+    set_log_level(LogLevel::Off);
+    if let Err(e) = testing::check_bytes(data) {
+        panic!("{}", e);
+    }
+});
@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use particle_affinity_wasm::logging::{set_log_level, LogLevel};
+use particle_affinity_wasm::testing;
+use particle_affinity_wasm::ParticleGrid;
+
+fuzz_target!(|data: &[u8]| {
+    set_log_level(LogLevel::Off);
+    let (config, steps) = testing::case(data);
+    let result = testing::stepped(&config, steps).and_then(|grid| {
+        testing::check_round_trips(&grid)?;
+        testing::check_patch(&ParticleGrid::from_config(&config), &grid)
+    });
+    if let Err(e) = result {
+        panic!("{}", e);
+    }
+});
@@ -0,0 +1,61 @@
+// ndarray interop for native analysis code (`ndarray` feature). Arrays are
+// indexed [[y, x]], the row-major layout of export_grid, so a grid reads
+// like an image: rows first, (0, 0) at the top left. The type grid itself
+// is stored column by column, so arrays are copies rather than views.
+
+use ndarray::{Array2, ArrayView2};
+
+use crate::config::SimulationConfig;
+use crate::core::Simulation;
+use crate::ParticleGrid;
+
+impl Simulation {
+    // The current cells as a size x size array
+    pub fn to_array(&self) -> Array2<u8> {
+        Array2::from_shape_fn((self.size, self.size), |(y, x)| self.type_grid[x][y])
+    }
+
+    // Overwrite the grid with `cells`, laid out like to_array. Rules,
+    // generation and history are kept. On error the grid is left untouched.
+    pub fn set_array(&mut self, cells: ArrayView2<u8>) -> Result<(), String> {
+        if cells.dim() != (self.size, self.size) {
+            return Err(format!(
+                "array is {}x{} but the grid is {}x{}",
+                cells.nrows(),
+                cells.ncols(),
+                self.size,
+                self.size
+            ));
+        }
+        if let Some(&t) = cells.iter().find(|&&t| t as usize > self.num_types) {
+            return Err(format!("array uses type {} but the grid has {} types", t, self.num_types));
+        }
+        for ((y, x), &t) in cells.indexed_iter() {
+            self.type_grid[x][y] = t;
+        }
+        Ok(())
+    }
+}
+
+impl ParticleGrid {
+    // A grid set up from `config` but starting from `cells` instead of a
+    // random fill. The array must be square; its side overrides
+    // config.size and its occupancy sets the density.
+    pub fn from_array(config: &SimulationConfig, cells: ArrayView2<u8>) -> Result<ParticleGrid, String> {
+        let (rows, cols) = cells.dim();
+        if rows != cols || rows == 0 {
+            return Err(format!("array must be square and non-empty, got {}x{}", rows, cols));
+        }
+        let occupied = cells.iter().filter(|&&t| t != 0).count();
+        let config = SimulationConfig {
+            size: rows,
+            density: occupied as f32 / (rows * cols) as f32,
+            type_densities: None,
+            ..config.clone()
+        };
+        let mut grid = ParticleGrid::from_config(&config);
+        grid.set_array(cells)?;
+        grid.refresh_output();
+        Ok(grid)
+    }
+}
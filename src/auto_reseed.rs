@@ -0,0 +1,108 @@
+// Unattended running. With auto-reseed on, a grid that has stopped doing
+// anything interesting starts over by itself: once it has been stagnant
+// for the configured number of consecutive steps it is reseeded (and,
+// optionally, handed a fresh random affinity matrix). The grid counts as
+// stagnant while only a trickle of cells changes per step, on average over
+// that many steps, or when it is caught in a short loop. Each reseed is
+// recorded as an event the page can pick up after stepping.
+
+use std::collections::VecDeque;
+
+use wasm_bindgen::prelude::*;
+
+use crate::core::{rules, InitPattern};
+use crate::ParticleGrid;
+
+// Mean fraction of cells changing per step at or below which the grid is
+// stagnant
+const STAGNANT_THRESHOLD: f64 = 5e-3;
+// Loop length watched for when cycle detection was not already on
+const CYCLE_PERIOD: usize = 8;
+
+pub(crate) struct AutoReseed {
+    stagnation_steps: usize,
+    randomize_rules: bool,
+    // Cells changed in each of the last `stagnation_steps` steps
+    recent: VecDeque<usize>,
+    // Reseeds done, and those not yet taken as events
+    reseeds: usize,
+    pending: usize,
+}
+
+#[wasm_bindgen]
+impl ParticleGrid {
+    // Reseed automatically once the grid has been stagnant for
+    // `stagnation_steps` steps (at least 1), with a new random affinity matrix as well if
+    // `randomize_rules`. Turns on cycle detection if it is off.
+    #[wasm_bindgen]
+    pub fn enable_auto_reseed(&mut self, stagnation_steps: usize, randomize_rules: bool) {
+        if self.sim.cycle_max_period == 0 {
+            self.sim.enable_cycle_detection(CYCLE_PERIOD);
+        }
+        let (reseeds, pending) = self.auto_reseed.as_ref().map_or((0, 0), |a| (a.reseeds, a.pending));
+        self.auto_reseed = Some(AutoReseed {
+            stagnation_steps: stagnation_steps.max(1),
+            randomize_rules,
+            recent: VecDeque::new(),
+            reseeds,
+            pending,
+        });
+    }
+
+    #[wasm_bindgen]
+    pub fn disable_auto_reseed(&mut self) {
+        self.auto_reseed = None;
+    }
+
+    // Reseeds done since auto-reseed was enabled
+    #[wasm_bindgen(getter)]
+    pub fn auto_reseeds(&self) -> usize {
+        self.auto_reseed.as_ref().map_or(0, |a| a.reseeds)
+    }
+
+    // True once for every auto-reseed, so a page polling after each step
+    // (or frame) sees each one: call it until it returns false
+    #[wasm_bindgen]
+    pub fn take_reseed_event(&mut self) -> bool {
+        match &mut self.auto_reseed {
+            Some(a) if a.pending > 0 => {
+                a.pending -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl ParticleGrid {
+    // Called after every step with the number of cells it changed
+    pub(crate) fn check_auto_reseed(&mut self, changed_cells: usize) {
+        let cells = (self.sim.size * self.sim.size) as f64;
+        let looping = self.sim.cycle_period().is_some();
+        let Some(auto) = &mut self.auto_reseed else {
+            return;
+        };
+        // A loop counts as no change at all
+        auto.recent.push_back(if looping { 0 } else { changed_cells });
+        while auto.recent.len() > auto.stagnation_steps {
+            auto.recent.pop_front();
+        }
+        let total: usize = auto.recent.iter().sum();
+        if auto.recent.len() < auto.stagnation_steps
+            || total as f64 > STAGNANT_THRESHOLD * cells * auto.stagnation_steps as f64
+        {
+            return;
+        }
+        auto.recent.clear();
+        auto.reseeds += 1;
+        auto.pending += 1;
+        let randomize_rules = auto.randomize_rules;
+
+        log_info!("Auto-reseeding after {} stagnant steps", auto.stagnation_steps);
+        if randomize_rules {
+            let sim = &mut self.sim;
+            rules::randomize_affinity(&mut sim.rules.affinity, &mut sim.rng);
+        }
+        self.reseed(InitPattern::Uniform);
+    }
+}
@@ -0,0 +1,50 @@
+// Stream a headless simulation to browsers as MJPEG (see serve.rs).
+//
+//   particle-serve [config.json] [address] [steps per frame]
+//
+// Without a config file the web UI's defaults are used; the address
+// defaults to 127.0.0.1:8080.
+
+use std::process::ExitCode;
+
+use particle_affinity_wasm::config::SimulationConfig;
+use particle_affinity_wasm::serve::serve_with;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = match args.first().filter(|path| path.as_str() != "-") {
+        Some(path) => {
+            let json = match std::fs::read_to_string(path) {
+                Ok(json) => json,
+                Err(e) => {
+                    eprintln!("could not read {}: {}", path, e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            match SimulationConfig::from_json(&json) {
+                Ok(config) => config,
+                Err(e) => {
+                    eprintln!("{}: {}", path, e);
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        None => SimulationConfig::default(),
+    };
+    let addr = args.get(1).map_or("127.0.0.1:8080", String::as_str);
+    let steps_per_frame = match args.get(2).map(|s| s.parse::<usize>()) {
+        None => 1,
+        Some(Ok(steps)) => steps,
+        Some(Err(e)) => {
+            eprintln!("invalid steps per frame: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    eprintln!("serving {}x{} grid on http://{}", config.size, config.size, addr);
+    if let Err(e) = serve_with(&config, addr, steps_per_frame) {
+        eprintln!("could not serve on {}: {}", addr, e);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,93 @@
+// LZ4 compression (lz4_flex, pure Rust, so it builds for wasm too) for the
+// bulky byte forms of a grid: compressed exports, save bodies, lz4 pattern
+// strings and Timeline keyframes. Grids compress well even while busy,
+// since most cells are empty and types repeat; a 1024x1024 grid that is
+// a megabyte raw typically takes a few hundred kilobytes.
+//
+// A compressed blob is the uncompressed length as a little-endian u32
+// followed by an LZ4 block. Readers pass the most they expect, so a corrupt
+// or hostile length can't make them allocate more.
+
+use wasm_bindgen::prelude::*;
+
+use crate::ParticleGrid;
+
+// Leads export_grid_compressed output
+const MAGIC: &[u8; 4] = b"PAGZ";
+
+pub(crate) fn compress(bytes: &[u8]) -> Vec<u8> {
+    lz4_flex::compress_prepend_size(bytes)
+}
+
+pub(crate) fn decompress(bytes: &[u8], max_len: usize) -> Result<Vec<u8>, String> {
+    let len = bytes.get(..4).ok_or("compressed data is truncated")?;
+    let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+    if len > max_len {
+        return Err(format!("compressed data claims {} bytes, more than the {} expected", len, max_len));
+    }
+    lz4_flex::decompress_size_prepended(bytes).map_err(|e| format!("corrupt compressed data: {}", e))
+}
+
+// Row-major cells of a `size` x `size` grid from compress() output
+pub(crate) fn decompress_cells(bytes: &[u8], size: usize) -> Result<Vec<u8>, String> {
+    let cells = decompress(bytes, size * size)?;
+    if cells.len() != size * size {
+        return Err(format!("compressed data holds {} of {} cells", cells.len(), size * size));
+    }
+    Ok(cells)
+}
+
+#[wasm_bindgen]
+impl ParticleGrid {
+    // export_grid, LZ4-compressed behind the magic bytes "PAGZ".
+    // import_grid takes it back as is.
+    #[wasm_bindgen]
+    pub fn export_grid_compressed(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&compress(&self.export_grid()));
+        bytes
+    }
+}
+
+impl ParticleGrid {
+    // Cells from import_grid input: export_grid_compressed output, or raw
+    // cells, one byte each. Compressed input is tried first since on small
+    // grids it can be exactly one byte per cell long too.
+    pub(crate) fn import_cells(&self, bytes: Vec<u8>) -> Option<Vec<u8>> {
+        if let Some(cells) = bytes.strip_prefix(MAGIC).and_then(|b| decompress_cells(b, self.size).ok()) {
+            return Some(cells);
+        }
+        (bytes.len() == self.size * self.size).then_some(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SimulationConfig;
+
+    #[test]
+    fn decompress_checks_lengths() {
+        let packed = compress(&[1, 1, 2, 0]);
+        assert_eq!(decompress_cells(&packed, 2).unwrap(), vec![1, 1, 2, 0]);
+        assert!(decompress(&packed[..2], 4).unwrap_err().contains("truncated"));
+        assert!(decompress(&packed, 3).unwrap_err().contains("more than the 3 expected"));
+        assert!(decompress_cells(&compress(&[1, 2]), 2).unwrap_err().contains("2 of 4 cells"));
+        let mut corrupt = packed.clone();
+        corrupt.truncate(5);
+        assert!(decompress(&corrupt, 4).unwrap_err().contains("corrupt"));
+    }
+
+    #[test]
+    fn import_takes_either_form() {
+        let grid = ParticleGrid::from_config(&SimulationConfig { size: 6, seed: Some(8), ..Default::default() });
+        let cells = grid.export_grid();
+        assert_eq!(grid.import_cells(grid.export_grid_compressed()), Some(cells.clone()));
+        assert_eq!(grid.import_cells(cells.clone()), Some(cells.clone()));
+        assert_eq!(grid.import_cells(cells[1..].to_vec()), None);
+        // Compressed cells for another grid size are not raw cells either
+        let mut other = MAGIC.to_vec();
+        other.extend_from_slice(&compress(&[0; 25]));
+        assert_eq!(grid.import_cells(other), None);
+    }
+}
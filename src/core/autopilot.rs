@@ -0,0 +1,226 @@
+// Density autopilot. Long runs tend to drift to degenerate mixes as
+// reactions convert one type into another; with a target set for a type,
+// a proportional controller nudges its share of the grid back after every
+// step, placing particles of that type on random empty cells or removing
+// random ones. Each step closes GAIN of the gap (at least one particle), so
+// corrections are gradual and the dynamics keep running around them.
+// Pinned cells are left alone. Under a symmetry mode particles are placed
+// and removed a whole orbit (a cell and its images) at a time, each image
+// counting towards the target, so a symmetric grid stays symmetric.
+
+use rand::Rng;
+
+use super::{Simulation, TypeChange};
+
+// Fraction of the gap to the target closed per step
+const GAIN: f32 = 0.05;
+
+#[derive(Clone, Default)]
+pub(crate) struct DensityTargets {
+    // Fraction of all cells, indexed by type; empty when no type has one
+    pub(super) targets: Vec<Option<f32>>,
+}
+
+impl DensityTargets {
+    pub(super) fn retype(&mut self, change: TypeChange) {
+        change.apply(&mut self.targets);
+        if self.targets.iter().all(Option::is_none) {
+            self.targets.clear();
+        }
+    }
+}
+
+impl Simulation {
+    // Hold the share of cells taken by type `t` near `fraction` (0-1).
+    // Returns false for an unknown type or a fraction outside 0-1.
+    pub fn set_target_density(&mut self, t: u8, fraction: f32) -> bool {
+        if t == 0 || t as usize > self.num_types || !(0.0..=1.0).contains(&fraction) {
+            return false;
+        }
+        let targets = &mut self.autopilot.targets;
+        targets.resize(self.num_types + 1, None);
+        targets[t as usize] = Some(fraction);
+        true
+    }
+
+    pub fn clear_target_density(&mut self, t: u8) {
+        let targets = &mut self.autopilot.targets;
+        if let Some(target) = targets.get_mut(t as usize) {
+            *target = None;
+        }
+        if targets.iter().all(Option::is_none) {
+            targets.clear();
+        }
+    }
+
+    pub fn clear_target_densities(&mut self) {
+        self.autopilot.targets.clear();
+    }
+
+    pub fn target_density(&self, t: u8) -> Option<f32> {
+        self.autopilot.targets.get(t as usize).copied().flatten()
+    }
+
+    // Indexed by type; empty when no type has a target
+    pub(crate) fn target_densities(&self) -> &[Option<f32>] {
+        &self.autopilot.targets
+    }
+
+    // One controller step. Returns the number of cells changed.
+    pub(crate) fn hold_densities(&mut self) -> usize {
+        let n = self.size;
+        let symmetry = self.rules.symmetry;
+        let targets = &self.autopilot.targets;
+        // Orbits by their smallest cell, with the number of distinct cells
+        // in them; orbits that are pinned or mixed are left out
+        let mut empty = Vec::new();
+        let mut held: Vec<Vec<((usize, usize), usize)>> = vec![Vec::new(); targets.len()];
+        let mut held_cells = vec![0usize; targets.len()];
+        for x in 0..n {
+            for y in 0..n {
+                let mut orbit = [(0, 0); 8];
+                let mut count = 0;
+                for image in symmetry.images(x, y, n) {
+                    if !orbit[..count].contains(&image) {
+                        orbit[count] = image;
+                        count += 1;
+                    }
+                }
+                let orbit = &orbit[..count];
+                let t = self.type_grid[x][y];
+                if orbit.iter().any(|&cell| cell < (x, y))
+                    || orbit.iter().any(|&(i, j)| self.rules.is_pinned(i, j, n) || self.type_grid[i][j] != t)
+                {
+                    continue;
+                }
+                match t {
+                    0 => empty.push(((x, y), count)),
+                    t if targets.get(t as usize).is_some_and(Option::is_some) => {
+                        held[t as usize].push(((x, y), count));
+                        held_cells[t as usize] += count;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut changed = 0;
+        for (t, target) in targets.iter().enumerate() {
+            let Some(target) = target else {
+                continue;
+            };
+            let gap = (target * (n * n) as f32).round() - held_cells[t] as f32;
+            let amount = (gap.abs() * GAIN).ceil() as usize;
+            // Random orbits of the source pool until `amount` cells are done
+            let (from, mut to, fill) = if gap > 0.0 {
+                (&mut empty, None, t as u8)
+            } else if gap < 0.0 {
+                (&mut held[t], Some(&mut empty), 0)
+            } else {
+                continue;
+            };
+            let mut done = 0;
+            while done < amount && !from.is_empty() {
+                let ((x, y), count) = from.swap_remove(self.rng.gen_range(0..from.len()));
+                for (i, j) in symmetry.images(x, y, n) {
+                    self.type_grid[i][j] = fill;
+                }
+                done += count;
+                if let Some(to) = to.as_deref_mut() {
+                    to.push(((x, y), count));
+                }
+            }
+            changed += done;
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::SimulationConfig;
+    use crate::core::{symmetrize, Symmetry};
+    use crate::ParticleGrid;
+
+    fn grid() -> ParticleGrid {
+        ParticleGrid::from_config(&SimulationConfig { size: 32, num_types: 3, seed: Some(1), ..Default::default() })
+    }
+
+    fn count(grid: &ParticleGrid, t: u8) -> usize {
+        grid.type_grid.iter().flatten().filter(|&&c| c == t).count()
+    }
+
+    #[test]
+    fn converges_on_target() {
+        let mut grid = grid();
+        assert!(grid.set_target_density(1, 0.5));
+        for _ in 0..200 {
+            grid.step();
+        }
+        let held = count(&grid, 1) as f32 / (32 * 32) as f32;
+        assert!((held - 0.5).abs() < 0.05, "type 1 holds {}", held);
+    }
+
+    #[test]
+    fn rejects_bad_targets() {
+        let mut grid = grid();
+        assert!(!grid.set_target_density(0, 0.5));
+        assert!(!grid.set_target_density(4, 0.5));
+        assert!(!grid.set_target_density(1, 1.5));
+        assert_eq!(grid.target_density(1), None);
+    }
+
+    #[test]
+    fn passes_conservation_checks() {
+        let mut grid = grid();
+        grid.set_target_density(2, 0.6);
+        grid.enable_invariant_checks(true);
+        for _ in 0..20 {
+            grid.try_step().unwrap();
+        }
+    }
+
+    #[test]
+    fn keeps_symmetric_grids_symmetric() {
+        for symmetry in [Symmetry::Mirror, Symmetry::Four, Symmetry::Eight] {
+            let mut grid = ParticleGrid::from_config(&SimulationConfig {
+                size: 17,
+                num_types: 3,
+                seed: Some(2),
+                symmetry,
+                ..Default::default()
+            });
+            // One type pushed up, one pulled down
+            grid.set_target_density(1, 0.4);
+            grid.set_target_density(2, 0.01);
+            for _ in 0..30 {
+                grid.step();
+                let mut mirrored = grid.type_grid.clone();
+                symmetrize(&mut mirrored, symmetry);
+                assert!(mirrored == grid.type_grid, "{:?} grid lost its symmetry", symmetry);
+            }
+            assert!(count(&grid, 1) as f32 / (17 * 17) as f32 > 0.2, "{:?}", symmetry);
+        }
+    }
+
+    #[test]
+    fn survives_added_type() {
+        let mut grid = grid();
+        grid.set_target_density(1, 0.2);
+        let t = grid.add_type(Vec::new(), Vec::new(), Vec::new());
+        grid.type_grid[0][0] = t;
+        grid.step();
+        assert_eq!(grid.target_density(t), None);
+    }
+
+    #[test]
+    fn follows_removed_type() {
+        let mut grid = grid();
+        grid.set_target_density(3, 0.3);
+        assert!(grid.remove_type(1, 0));
+        assert_eq!(grid.target_density(2), Some(0.3));
+        assert_eq!(grid.target_density(3), None);
+        grid.remove_type(1, 0);
+        assert_eq!(grid.target_density(1), Some(0.3));
+    }
+}
@@ -0,0 +1,80 @@
+// What happens at the grid edge. Clamp cuts neighborhoods off at the edge
+// (the original behavior); Wrap joins opposite edges into a torus; Reflect
+// mirrors the scoring neighborhood back into the grid, so edge cells see a
+// full window; Absorb scores like Clamp but lets particles step off the
+// grid, where they are deleted.
+
+use serde::{Deserialize, Serialize};
+
+use super::{Rules, Simulation};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BoundaryMode {
+    #[default]
+    Clamp = 0,
+    Wrap = 1,
+    Reflect = 2,
+    Absorb = 3,
+}
+
+impl BoundaryMode {
+    // Map a coordinate on a line of `n` cells (possibly off the grid) back
+    // onto it. Only Wrap and Reflect are ever handed off-grid values.
+    #[inline]
+    fn fold(self, v: isize, n: isize) -> usize {
+        match self {
+            BoundaryMode::Wrap => v.rem_euclid(n) as usize,
+            BoundaryMode::Reflect => {
+                let v = if v < 0 { -v } else if v >= n { 2 * (n - 1) - v } else { v };
+                // Windows wider than the grid can bounce past the far edge
+                v.clamp(0, n - 1) as usize
+            }
+            BoundaryMode::Clamp | BoundaryMode::Absorb => v as usize,
+        }
+    }
+
+    // Cells at most `r` from `c` along a line of `size` cells, in
+    // increasing order, as (offset from c, cell). `c` may lie just off the
+    // grid (Absorb scores the cells a particle would leave through).
+    pub(crate) fn axis(self, c: isize, r: usize, size: usize) -> impl Iterator<Item = (isize, usize)> + Clone {
+        let (r, n) = (r as isize, size as isize);
+        let (lo, hi) = match self {
+            BoundaryMode::Clamp | BoundaryMode::Absorb => ((c - r).max(0), (c + r).min(n - 1)),
+            BoundaryMode::Wrap | BoundaryMode::Reflect => (c - r, c + r),
+        };
+        (lo..=hi).map(move |v| (v - c, self.fold(v, n)))
+    }
+}
+
+// Where a particle at (x, y) would end up stepping by (dx, dy)
+pub(crate) enum Step {
+    To(usize, usize),
+    // Off the grid under Absorb; the position is kept for scoring
+    Off(isize, isize),
+    Blocked,
+}
+
+impl Rules {
+    pub(crate) fn step_target(&self, x: usize, y: usize, dx: isize, dy: isize, size: usize) -> Step {
+        let (nx, ny) = (x as isize + dx, y as isize + dy);
+        let n = size as isize;
+        if (0..n).contains(&nx) && (0..n).contains(&ny) {
+            return Step::To(nx as usize, ny as usize);
+        }
+        match self.boundary {
+            BoundaryMode::Wrap => Step::To(nx.rem_euclid(n) as usize, ny.rem_euclid(n) as usize),
+            BoundaryMode::Absorb => Step::Off(nx, ny),
+            BoundaryMode::Clamp | BoundaryMode::Reflect => Step::Blocked,
+        }
+    }
+}
+
+impl Simulation {
+    pub fn set_boundary_mode(&mut self, mode: BoundaryMode) {
+        self.rules.boundary = mode;
+    }
+
+    pub fn boundary_mode(&self) -> BoundaryMode {
+        self.rules.boundary
+    }
+}
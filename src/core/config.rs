@@ -0,0 +1,140 @@
+// Typed simulation setup: everything needed to build a grid (dimensions,
+// starting populations, rule tables, palette), round-tripping through JSON.
+// See config.rs for the constructors built on it.
+
+use serde::{Deserialize, Serialize};
+
+use super::{BoundaryMode, EnergyConfig, MovementMode, Symmetry};
+
+// Missing fields take their defaults, and empty rule or palette tables are
+// filled in the same way the positional constructor fills them (random
+// rules, default colors and names)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimulationConfig {
+    pub size: usize,
+    pub num_types: usize,
+    pub density: f32,
+    // Per-type starting densities indexed by type (entry 0 ignored);
+    // overrides `density` when set
+    pub type_densities: Option<Vec<f32>>,
+    pub radius: usize,
+    // None draws a fresh seed
+    pub seed: Option<u64>,
+    // Affinity rows indexed [from][to], types 0..=num_types
+    pub affinity: Vec<Vec<i8>>,
+    pub copy_types: Vec<u8>,
+    pub replace_types: Vec<u8>,
+    pub replace_radius: usize,
+    pub replace_probability: f32,
+    pub max_conversions: usize,
+    pub attraction_scale: f32,
+    pub repulsion_scale: f32,
+    // Scoring weights of horizontal and vertical neighbors
+    pub anisotropy: [f32; 2],
+    // Crowding penalty weight; 0 leaves it off
+    pub crowding: f32,
+    // Particle updates per step as a fraction of the starting particles
+    pub update_fraction: f32,
+    // Per-type random move probability indexed by type (entry 0 ignored);
+    // missing entries are 0
+    pub type_noise: Vec<f32>,
+    // Energy model; None leaves it off
+    pub energy: Option<EnergyConfig>,
+    pub symmetry: Symmetry,
+    pub boundary: BoundaryMode,
+    pub movement: MovementMode,
+    // Margin a move must win by in Threshold mode
+    pub move_threshold: f32,
+    // Softmax destination sharpness; None takes the best cell
+    pub softmax_sharpness: Option<f32>,
+    // Tile size when stepping in tiles (see Simulation::enable_tiling);
+    // None steps sequentially
+    pub tile_size: Option<usize>,
+    pub colors: Vec<[u8; 3]>,
+    pub type_names: Vec<String>,
+}
+
+impl Default for SimulationConfig {
+    // Matches the web UI's starting values
+    fn default() -> Self {
+        SimulationConfig {
+            size: 400,
+            num_types: 6,
+            density: 0.15,
+            type_densities: None,
+            radius: 3,
+            seed: None,
+            affinity: Vec::new(),
+            copy_types: Vec::new(),
+            replace_types: Vec::new(),
+            replace_radius: 1,
+            replace_probability: 1.0,
+            max_conversions: 0,
+            attraction_scale: 1.0,
+            repulsion_scale: 1.0,
+            anisotropy: [1.0, 1.0],
+            crowding: 0.0,
+            update_fraction: 0.2,
+            type_noise: Vec::new(),
+            energy: None,
+            symmetry: Symmetry::None,
+            boundary: BoundaryMode::Clamp,
+            movement: MovementMode::Greedy,
+            move_threshold: 0.0,
+            softmax_sharpness: None,
+            tile_size: None,
+            colors: Vec::new(),
+            type_names: Vec::new(),
+        }
+    }
+}
+
+impl SimulationConfig {
+    pub fn from_json(json: &str) -> Result<SimulationConfig, String> {
+        let config: SimulationConfig = serde_json::from_str(json).map_err(|e| format!("invalid config: {}", e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Dimensions a grid can be built with. Simulation::from_config clamps
+    // rather than fail, so this is for configs coming from outside.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.size == 0 {
+            return Err("invalid config: size must be at least 1".to_string());
+        }
+        if self.num_types == 0 || self.num_types > u8::MAX as usize {
+            return Err(format!("invalid config: num_types must be 1 to 255, got {}", self.num_types));
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Simulation;
+
+    #[test]
+    fn json_round_trip_and_errors() {
+        let config = SimulationConfig { size: 12, num_types: 4, seed: Some(u64::MAX), ..Default::default() };
+        assert_eq!(SimulationConfig::from_json(&config.to_json()).unwrap(), config);
+        assert!(SimulationConfig::from_json("{").unwrap_err().starts_with("invalid config"));
+        assert!(SimulationConfig::from_json(r#"{"size": -1}"#).is_err());
+    }
+
+    #[test]
+    fn rejects_grids_that_cannot_be_built() {
+        for json in [r#"{"size":8,"num_types":256}"#, r#"{"size":8,"num_types":0}"#, r#"{"size":0}"#] {
+            assert!(SimulationConfig::from_json(json).unwrap_err().starts_with("invalid config"), "{}", json);
+        }
+        assert!(SimulationConfig::from_json(r#"{"size":8,"num_types":255}"#).is_ok());
+        // Built directly, the extra types are dropped instead
+        let sim = Simulation::from_config(&SimulationConfig { size: 8, num_types: 256, ..Default::default() });
+        assert_eq!(sim.num_types, 255);
+    }
+}
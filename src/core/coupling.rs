@@ -0,0 +1,88 @@
+// Coupling to a second grid. Before each step the partner grid's local
+// particle density is sampled into a field, and every type adds
+// `weight * density` at a cell to its score there, so positive weights
+// chase the partner's particles and negative ones flee them.
+
+use super::{Rules, Simulation, TypeChange};
+
+#[derive(Clone, Default)]
+pub(crate) struct Coupling {
+    // Indexed by type; missing entries are 0
+    weights: Vec<f32>,
+    // Partner density per cell, indexed [x * size + y]; empty when
+    // uncoupled
+    field: Vec<f32>,
+}
+
+impl Coupling {
+    pub(super) fn retype(&mut self, change: TypeChange) {
+        change.apply(&mut self.weights);
+    }
+}
+
+impl Rules {
+    // Coupling term for a particle of `p_type` at (i, j), which may lie
+    // just off the grid (the nearest edge cell is sampled then)
+    #[inline]
+    pub(crate) fn coupling_bonus(&self, p_type: u8, i: isize, j: isize, size: usize) -> f32 {
+        let coupling = &self.coupling;
+        let weight = coupling.weights.get(p_type as usize).copied().unwrap_or(0.0);
+        if weight == 0.0 || coupling.field.len() != size * size {
+            return 0.0;
+        }
+        let last = size as isize - 1;
+        let (x, y) = (i.clamp(0, last) as usize, j.clamp(0, last) as usize);
+        weight * coupling.field[x * size + y]
+    }
+}
+
+impl Simulation {
+    // How strongly particles of `p_type` are drawn to (positive) or driven
+    // from (negative) the partner grid's particles
+    pub fn set_coupling(&mut self, p_type: u8, weight: f32) {
+        if p_type == 0 || p_type as usize > self.num_types || !weight.is_finite() {
+            return;
+        }
+        let weights = &mut self.rules.coupling.weights;
+        if weights.len() <= p_type as usize {
+            weights.resize(p_type as usize + 1, 0.0);
+        }
+        weights[p_type as usize] = weight;
+    }
+
+    pub(crate) fn is_coupled(&self) -> bool {
+        self.rules.coupling.weights.iter().any(|&w| w != 0.0)
+    }
+
+    pub(crate) fn set_coupling_field(&mut self, field: Vec<f32>) {
+        self.rules.coupling.field = field;
+    }
+
+    // Fraction of occupied cells within `radius` of each cell (the window
+    // is cut off at the grid edge), indexed [x * size + y]
+    pub fn occupancy_field(&self, radius: usize) -> Vec<f32> {
+        let n = self.size;
+        // Summed-area table with a zero border row and column
+        let mut sums = vec![0u32; (n + 1) * (n + 1)];
+        for x in 0..n {
+            for y in 0..n {
+                let occupied = (self.type_grid[x][y] != 0) as u32;
+                sums[(x + 1) * (n + 1) + y + 1] =
+                    occupied + sums[x * (n + 1) + y + 1] + sums[(x + 1) * (n + 1) + y] - sums[x * (n + 1) + y];
+            }
+        }
+
+        let mut field = Vec::with_capacity(n * n);
+        for x in 0..n {
+            let (x0, x1) = (x.saturating_sub(radius), (x + radius).min(n - 1) + 1);
+            for y in 0..n {
+                let (y0, y1) = (y.saturating_sub(radius), (y + radius).min(n - 1) + 1);
+                let count = sums[x1 * (n + 1) + y1] + sums[x0 * (n + 1) + y0]
+                    - sums[x0 * (n + 1) + y1]
+                    - sums[x1 * (n + 1) + y0];
+                field.push(count as f32 / ((x1 - x0) * (y1 - y0)) as f32);
+            }
+        }
+        field
+    }
+}
@@ -0,0 +1,224 @@
+// Energy and starvation. With an EnergyConfig set, every particle carries
+// an energy reserve: moving costs energy, every step drains a little more,
+// and converting a neighbor (predation) pays out. A particle that runs dry
+// is removed, so populations without prey die back and recover once prey
+// returns instead of grinding on forever. Under a symmetry mode a particle
+// dies together with its images (which run on the same energy budget
+// anyway), so starvation doesn't break the symmetry.
+
+use serde::{Deserialize, Serialize};
+
+use super::{Cells, Move, Region, Simulation, Symmetry};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnergyConfig {
+    // Energy of particles present when energy is switched on, and of any
+    // particle later placed by hand
+    pub initial: f32,
+    // Reserves are capped here
+    pub capacity: f32,
+    // Spent on every move
+    pub move_cost: f32,
+    // Spent by every particle on every step, moving or not
+    pub drain: f32,
+    // Gained every step by each type (indexed by type, missing entries 0),
+    // making that type a producer the others can live off
+    pub income: Vec<f32>,
+    // Gained by a particle for each neighbor it converts
+    pub predation_gain: f32,
+    // Starting energy of a freshly converted particle
+    pub offspring: f32,
+}
+
+impl Default for EnergyConfig {
+    fn default() -> Self {
+        EnergyConfig {
+            initial: 1.0,
+            capacity: 2.0,
+            move_cost: 0.002,
+            drain: 0.0005,
+            income: Vec::new(),
+            predation_gain: 0.5,
+            offspring: 0.5,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Energy {
+    pub(crate) config: EnergyConfig,
+    // Indexed [x][y] like the type grid. Empty cells hold `initial`, ready
+    // for a particle placed there by an edit.
+    pub(crate) grid: Vec<Vec<f32>>,
+}
+
+impl Energy {
+    pub(crate) fn new(config: EnergyConfig, size: usize) -> Energy {
+        let grid = vec![vec![config.initial; size]; size];
+        Energy { config, grid }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        for column in self.grid.iter_mut() {
+            column.fill(self.config.initial);
+        }
+    }
+
+    // Apply the per-step drain inside `region`, removing particles that run
+    // out (and their images) and resetting empty cells. Returns the number
+    // of particles removed.
+    pub(crate) fn drain(&mut self, types: &mut [Vec<u8>], region: Region, symmetry: Symmetry) -> usize {
+        let mut starved = Vec::new();
+        let columns = types.iter().zip(self.grid.iter_mut()).enumerate();
+        for (x, (column, energies)) in columns.take(region.x1 + 1).skip(region.x0) {
+            for y in region.y0..=region.y1 {
+                let e = &mut energies[y];
+                let t = column[y];
+                if t == 0 {
+                    *e = self.config.initial;
+                    continue;
+                }
+                let income = self.config.income.get(t as usize).copied().unwrap_or(0.0);
+                *e = (*e + income - self.config.drain).min(self.config.capacity);
+                if *e <= 0.0 {
+                    starved.push((x, y));
+                }
+            }
+        }
+
+        let size = types.len();
+        let mut removed = 0;
+        for (x, y) in starved {
+            for (i, j) in symmetry.images(x, y, size) {
+                if types[i][j] != 0 {
+                    types[i][j] = 0;
+                    self.grid[i][j] = self.config.initial;
+                    removed += 1;
+                }
+            }
+        }
+        removed
+    }
+}
+
+// The type grid with energies riding along
+pub(crate) struct EnergyCells<'a> {
+    pub(crate) types: &'a mut Vec<Vec<u8>>,
+    pub(crate) energy: &'a mut Energy,
+}
+
+impl Cells for EnergyCells<'_> {
+    #[inline]
+    fn size(&self) -> usize {
+        self.types.len()
+    }
+
+    #[inline]
+    fn get(&self, x: usize, y: usize) -> u8 {
+        self.types[x][y]
+    }
+
+    #[inline]
+    fn set(&mut self, x: usize, y: usize, t: u8) {
+        self.types[x][y] = t;
+    }
+
+    // Moves are simultaneous, so read every source before writing. The
+    // moves of a mirrored particle die together if any of them runs dry.
+    fn carry(&mut self, moves: &[Move]) {
+        let grid = &mut self.energy.grid;
+        let mut carried = [0.0f32; 8];
+        for (e, &((sx, sy), _)) in carried.iter_mut().zip(moves) {
+            *e = grid[sx][sy] - self.energy.config.move_cost;
+        }
+        let dead = carried[..moves.len()].iter().any(|&e| e <= 0.0);
+        for (&e, &(_, (dx, dy))) in carried.iter().zip(moves) {
+            grid[dx][dy] = e;
+            if dead {
+                self.types[dx][dy] = 0;
+            }
+        }
+    }
+
+    fn converted(&mut self, by: (usize, usize), at: (usize, usize)) {
+        let config = &self.energy.config;
+        let grid = &mut self.energy.grid;
+        grid[at.0][at.1] = config.offspring;
+        grid[by.0][by.1] = (grid[by.0][by.1] + config.predation_gain).min(config.capacity);
+    }
+}
+
+impl Simulation {
+    // Switch the energy model on (every particle starts at
+    // `config.initial`) or, with None, off
+    pub fn set_energy(&mut self, config: Option<EnergyConfig>) {
+        self.energy = config.map(|c| Energy::new(c, self.size));
+    }
+
+    pub fn energy_config(&self) -> Option<&EnergyConfig> {
+        self.energy.as_ref().map(|e| &e.config)
+    }
+
+    // Set particle reserves from export_energy's layout; empty cells keep
+    // the initial reserve and values are capped at the capacity. Returns
+    // false if the energy model is off or the length is not size * size.
+    pub(crate) fn restore_energy(&mut self, reserves: &[f32]) -> bool {
+        let n = self.size;
+        let Some(energy) = &mut self.energy else {
+            return false;
+        };
+        if reserves.len() != n * n {
+            return false;
+        }
+        for (i, &e) in reserves.iter().enumerate() {
+            let (x, y) = (i % n, i / n);
+            if self.type_grid[x][y] != 0 && e.is_finite() {
+                energy.grid[x][y] = e.min(energy.config.capacity);
+            }
+        }
+        true
+    }
+
+    // Energy of the particle at (x, y); None if empty, out of range or the
+    // energy model is off
+    pub fn particle_energy(&self, x: usize, y: usize) -> Option<f32> {
+        let energy = self.energy.as_ref()?;
+        (self.cell(x, y) != 0).then(|| energy.grid[x][y])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{symmetrize, SimulationConfig};
+
+    #[test]
+    fn starvation_keeps_symmetric_grids_symmetric() {
+        for (seed, symmetry) in [(55, Symmetry::Eight), (67, Symmetry::Four), (70, Symmetry::Mirror)] {
+            let mut sim = Simulation::from_config(&SimulationConfig {
+                size: 16 + seed % 3,
+                num_types: 3,
+                seed: Some(seed as u64),
+                symmetry,
+                ..Default::default()
+            });
+            // Only type 1 earns anything, so the others live off conversions
+            // and starve in a few steps when they make none
+            sim.set_energy(Some(EnergyConfig {
+                income: vec![0.0, 0.2],
+                drain: 0.1,
+                move_cost: 0.05 * (seed % 5) as f32,
+                initial: 0.5,
+                predation_gain: 0.3,
+                ..Default::default()
+            }));
+            for _ in 0..40 {
+                sim.step();
+                let mut mirrored = sim.type_grid.clone();
+                symmetrize(&mut mirrored, symmetry);
+                assert!(mirrored == sim.type_grid, "{:?} grid lost its symmetry", symmetry);
+            }
+        }
+    }
+}
@@ -0,0 +1,127 @@
+// Particle identities. Cells are anonymous, so to follow one particle from
+// step to step every particle gets a number that rides along with its
+// moves. A converted particle counts as a new particle and gets a new
+// number; so do particles that turn up any other way (edits, imports, a
+// reseed), numbered when the layer next syncs. Numbers count up and are
+// not reused until the u32 counter wraps, after about four billion
+// particles, when numbering starts again from 1.
+
+use super::{Cells, Move, Simulation};
+
+#[derive(Clone)]
+pub(crate) struct Identities {
+    // Indexed [x][y] like the type grid, 0 for empty cells
+    pub(crate) grid: Vec<Vec<u32>>,
+    next: u32,
+}
+
+impl Identities {
+    pub(crate) fn new(types: &[Vec<u8>]) -> Identities {
+        let mut ids = Identities { grid: vec![vec![0; types.len()]; types.len()], next: 1 };
+        ids.sync(types);
+        ids
+    }
+
+    // Number every unnumbered particle and clear the ids of empty cells
+    pub(crate) fn sync(&mut self, types: &[Vec<u8>]) {
+        if self.grid.len() != types.len() {
+            self.grid = vec![vec![0; types.len()]; types.len()];
+        }
+        for (column, ids) in types.iter().zip(self.grid.iter_mut()) {
+            for (&t, id) in column.iter().zip(ids.iter_mut()) {
+                if t == 0 {
+                    *id = 0;
+                } else if *id == 0 {
+                    *id = Self::fresh(&mut self.next);
+                }
+            }
+        }
+    }
+
+    // 0 marks an empty cell, so the counter skips it when it wraps
+    fn fresh(next: &mut u32) -> u32 {
+        let id = *next;
+        *next = next.wrapping_add(1).max(1);
+        id
+    }
+
+    // Forget every id and number the grid afresh
+    pub(crate) fn reset(&mut self, types: &[Vec<u8>]) {
+        for column in self.grid.iter_mut() {
+            column.fill(0);
+        }
+        self.sync(types);
+    }
+}
+
+// Any Cells with ids riding along
+pub(crate) struct IdCells<'a, C: Cells> {
+    pub(crate) cells: &'a mut C,
+    pub(crate) ids: &'a mut Identities,
+}
+
+impl<C: Cells> Cells for IdCells<'_, C> {
+    #[inline]
+    fn size(&self) -> usize {
+        self.cells.size()
+    }
+
+    #[inline]
+    fn get(&self, x: usize, y: usize) -> u8 {
+        self.cells.get(x, y)
+    }
+
+    #[inline]
+    fn set(&mut self, x: usize, y: usize, t: u8) {
+        self.cells.set(x, y, t);
+    }
+
+    // Sources are cleared so a particle later created there starts unnumbered
+    fn carry(&mut self, moves: &[Move]) {
+        self.cells.carry(moves);
+        let grid = &mut self.ids.grid;
+        let mut carried = [0u32; 8];
+        for (id, &((sx, sy), _)) in carried.iter_mut().zip(moves) {
+            *id = std::mem::take(&mut grid[sx][sy]);
+        }
+        for (&id, &(_, (dx, dy))) in carried.iter().zip(moves) {
+            grid[dx][dy] = id;
+        }
+    }
+
+    fn converted(&mut self, by: (usize, usize), at: (usize, usize)) {
+        self.cells.converted(by, at);
+        self.ids.grid[at.0][at.1] = Identities::fresh(&mut self.ids.next);
+    }
+}
+
+impl Simulation {
+    // Start numbering particles, or with false stop and drop the ids
+    pub(crate) fn track_identities(&mut self, on: bool) {
+        if !on {
+            self.ids = None;
+        } else if self.ids.is_none() {
+            self.ids = Some(Identities::new(&self.type_grid));
+        }
+    }
+
+    // Bring the ids up to date with edits made since the last step
+    pub(crate) fn sync_identities(&mut self) {
+        if let Some(ids) = &mut self.ids {
+            ids.sync(&self.type_grid);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbering_wraps_past_zero() {
+        let mut next = u32::MAX;
+        assert_eq!(Identities::fresh(&mut next), u32::MAX);
+        assert_eq!(Identities::fresh(&mut next), 1);
+        assert_eq!(Identities::fresh(&mut next), 2);
+    }
+}
@@ -0,0 +1,152 @@
+// Initial grid layouts. Uniform random is the classic start; the structured
+// patterns set up interfaces between types for studying how they evolve.
+
+use rand::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InitPattern {
+    // Every cell occupied with probability `density`, random type
+    Uniform = 0,
+    // Vertical bands, one per type
+    Stripes = 1,
+    // Concentric rings around the center, cycling through the types
+    Rings = 2,
+    // One solid disk per type at a random position
+    Blobs = 3,
+    // A single mixed disk in the middle holding all the particles
+    CenterDisk = 4,
+    // Left half drawn from the first half of the types, right half from the rest
+    TwoPhase = 5,
+}
+
+// Clean up user-supplied per-type densities: one entry per type plus the
+// unused entry 0, negatives and NaN as 0, and the total capped at 1
+pub(crate) fn normalize_type_densities(densities: &[f32], num_types: usize) -> Vec<f32> {
+    let mut out: Vec<f32> = (0..=num_types)
+        .map(|t| match densities.get(t) {
+            Some(&d) if t > 0 && d > 0.0 => d,
+            _ => 0.0,
+        })
+        .collect();
+    let total: f32 = out.iter().sum();
+    // Rounding can leave a scaled total a hair over 1; the slack keeps a
+    // second pass (a saved config loaded back) from scaling again
+    if total > 1.0 + 1e-4 {
+        for d in out.iter_mut() {
+            *d /= total;
+        }
+    }
+    out
+}
+
+// Lay out a grid. With `type_densities` the uniform pattern draws each
+// type at its own density; the structured patterns only use the total.
+pub(crate) fn fill_grid<R: Rng>(
+    size: usize,
+    num_types: usize,
+    density: f32,
+    type_densities: Option<&[f32]>,
+    pattern: InitPattern,
+    rng: &mut R,
+) -> Vec<Vec<u8>> {
+    let mut grid = vec![vec![0u8; size]; size];
+    if num_types == 0 || size == 0 {
+        return grid;
+    }
+    let n = num_types as u8;
+    let center = (size as f32 - 1.0) / 2.0;
+    let dist = |x: usize, y: usize| ((x as f32 - center).powi(2) + (y as f32 - center).powi(2)).sqrt();
+
+    if let (InitPattern::Uniform, Some(densities)) = (pattern, type_densities) {
+        for column in grid.iter_mut() {
+            for cell in column.iter_mut() {
+                // Walk the cumulative densities; past the end stays empty
+                let mut r = rng.gen::<f32>();
+                for (t, &d) in densities.iter().enumerate().skip(1) {
+                    if r < d {
+                        *cell = t as u8;
+                        break;
+                    }
+                    r -= d;
+                }
+            }
+        }
+        return grid;
+    }
+
+    match pattern {
+        InitPattern::Uniform => {
+            for column in grid.iter_mut() {
+                for cell in column.iter_mut() {
+                    if rng.gen::<f32>() < density {
+                        *cell = rng.gen_range(1..=n);
+                    }
+                }
+            }
+        }
+        InitPattern::Stripes => {
+            for (x, column) in grid.iter_mut().enumerate() {
+                let t = (x * num_types / size) as u8 + 1;
+                for cell in column.iter_mut() {
+                    if rng.gen::<f32>() < density {
+                        *cell = t;
+                    }
+                }
+            }
+        }
+        InitPattern::Rings => {
+            // Each type appears twice between the center and the edge
+            let ring_width = (size as f32 / (4.0 * num_types as f32)).max(1.0);
+            for (x, column) in grid.iter_mut().enumerate() {
+                for (y, cell) in column.iter_mut().enumerate() {
+                    if rng.gen::<f32>() < density {
+                        *cell = ((dist(x, y) / ring_width) as usize % num_types) as u8 + 1;
+                    }
+                }
+            }
+        }
+        InitPattern::Blobs => {
+            // Each blob gets an equal share of the particle budget
+            let area = density * (size * size) as f32 / num_types as f32;
+            let radius = (area / std::f32::consts::PI).sqrt();
+            for t in 1..=n {
+                let cx = rng.gen_range(0.0..size as f32);
+                let cy = rng.gen_range(0.0..size as f32);
+                let x0 = (cx - radius).floor().max(0.0) as usize;
+                let x1 = ((cx + radius).ceil() as usize).min(size - 1);
+                let y0 = (cy - radius).floor().max(0.0) as usize;
+                let y1 = ((cy + radius).ceil() as usize).min(size - 1);
+                for (x, column) in grid.iter_mut().enumerate().take(x1 + 1).skip(x0) {
+                    for (y, cell) in column.iter_mut().enumerate().take(y1 + 1).skip(y0) {
+                        let d2 = (x as f32 - cx).powi(2) + (y as f32 - cy).powi(2);
+                        if d2 <= radius * radius {
+                            *cell = t;
+                        }
+                    }
+                }
+            }
+        }
+        InitPattern::CenterDisk => {
+            let radius = (density * (size * size) as f32 / std::f32::consts::PI).sqrt();
+            for (x, column) in grid.iter_mut().enumerate() {
+                for (y, cell) in column.iter_mut().enumerate() {
+                    if dist(x, y) <= radius {
+                        *cell = rng.gen_range(1..=n);
+                    }
+                }
+            }
+        }
+        InitPattern::TwoPhase => {
+            let split = n.div_ceil(2);
+            for (x, column) in grid.iter_mut().enumerate() {
+                let (lo, hi) = if x < size / 2 || n == 1 { (1, split) } else { (split + 1, n) };
+                for cell in column.iter_mut() {
+                    if rng.gen::<f32>() < density {
+                        *cell = rng.gen_range(lo..=hi);
+                    }
+                }
+            }
+        }
+    }
+    grid
+}
@@ -0,0 +1,242 @@
+// Debug-mode validation run after every step when enabled. Each check
+// returns a message naming the first violation found, so a rule bug shows
+// up at the generation that introduced it instead of many steps later as
+// a garbled grid.
+
+use std::cell::RefCell;
+
+use rand::Rng;
+
+use super::{BoundaryMode, Cells, Move, Region, Rules, Simulation, StepStats};
+
+// Cells that check every coordinate the rules hand them. Bounds math done
+// in usize that wrapped (0 - 1) shows up here as a coordinate off the grid,
+// or one in range but far from where it should be: a move must go to an
+// adjacent cell and a reaction reach no further than its radius (both
+// measured across the wrapped edge under Wrap). Off-grid reads see empty
+// space and off-grid writes are dropped, so the step finishes and the
+// violation is reported instead of panicking. Reads are recorded through a
+// RefCell, since Cells::get only borrows.
+//
+// The tiled parallel step is not covered: a tile indexes its own buffer,
+// so a coordinate outside the tile already panics there.
+struct CheckedCells<'a, C: Cells> {
+    cells: &'a mut C,
+    wrap: bool,
+    reach: usize,
+    violation: RefCell<Option<String>>,
+}
+
+impl<C: Cells> CheckedCells<'_, C> {
+    fn in_range(&self, x: usize, y: usize, access: &str) -> bool {
+        let size = self.cells.size();
+        let ok = x < size && y < size;
+        if !ok {
+            self.report(|| format!("{} of cell ({}, {}) is off the {}x{} grid", access, x, y, size, size));
+        }
+        ok
+    }
+
+    // Chebyshev distance between two cells
+    fn distance(&self, a: (usize, usize), b: (usize, usize)) -> usize {
+        let size = self.cells.size();
+        let axis = |p: usize, q: usize| {
+            let d = p.abs_diff(q);
+            if self.wrap { d.min(size.saturating_sub(d)) } else { d }
+        };
+        axis(a.0, b.0).max(axis(a.1, b.1))
+    }
+
+    fn report(&self, message: impl FnOnce() -> String) {
+        let mut violation = self.violation.borrow_mut();
+        if violation.is_none() {
+            *violation = Some(message());
+        }
+    }
+}
+
+impl<C: Cells> Cells for CheckedCells<'_, C> {
+    fn size(&self) -> usize {
+        self.cells.size()
+    }
+
+    fn get(&self, x: usize, y: usize) -> u8 {
+        if self.in_range(x, y, "read") { self.cells.get(x, y) } else { 0 }
+    }
+
+    fn set(&mut self, x: usize, y: usize, t: u8) {
+        if self.in_range(x, y, "write") {
+            self.cells.set(x, y, t);
+        }
+    }
+
+    fn carry(&mut self, moves: &[Move]) {
+        for &(from, to) in moves {
+            if self.distance(from, to) > 1 {
+                self.report(|| format!("particle moved from {:?} to {:?}, which are not adjacent", from, to));
+            }
+        }
+        self.cells.carry(moves);
+    }
+
+    fn converted(&mut self, by: (usize, usize), at: (usize, usize)) {
+        if self.distance(by, at) > self.reach {
+            let reach = self.reach;
+            self.report(|| format!("particle at {:?} converted {:?}, beyond its reach of {}", by, at, reach));
+        }
+        self.cells.converted(by, at);
+    }
+}
+
+impl Rules {
+    // update_particles, with every cell access checked when `check` is set
+    pub(crate) fn update_particles_checked<C: Cells, R: Rng>(
+        &self,
+        cells: &mut C,
+        particles: &mut Vec<(usize, usize)>,
+        updates: usize,
+        rng: &mut R,
+        check: bool,
+    ) -> (StepStats, Result<(), String>) {
+        if !check {
+            return (self.update_particles(cells, particles, updates, rng), Ok(()));
+        }
+        let wrap = self.boundary == BoundaryMode::Wrap;
+        let mut checked = CheckedCells { cells, wrap, reach: self.replace_radius, violation: RefCell::new(None) };
+        let stats = self.update_particles(&mut checked, particles, updates, rng);
+        (stats, checked.violation.into_inner().map_or(Ok(()), Err))
+    }
+}
+
+// What a step must leave unchanged in conservation mode
+pub(crate) struct Census {
+    total: usize,
+    per_type: Vec<usize>,
+}
+
+impl Simulation {
+    pub(crate) fn census(&self) -> Census {
+        let mut per_type = vec![0usize; self.num_types + 1];
+        for &t in self.type_grid.iter().flatten() {
+            if let Some(count) = per_type.get_mut(t as usize) {
+                *count += 1;
+            }
+        }
+        Census { total: per_type[1..].iter().sum(), per_type }
+    }
+
+    // Grid shape, cell values and rule tables are consistent with `size`
+    // and `num_types`
+    pub fn check_invariants(&self) -> Result<(), String> {
+        let n = self.num_types + 1;
+
+        if self.type_grid.len() != self.size {
+            return Err(format!("grid has {} columns, expected {}", self.type_grid.len(), self.size));
+        }
+        for (x, column) in self.type_grid.iter().enumerate() {
+            if column.len() != self.size {
+                return Err(format!("column {} has {} cells, expected {}", x, column.len(), self.size));
+            }
+            if let Some((y, &t)) = column.iter().enumerate().find(|(_, &t)| t as usize > self.num_types) {
+                return Err(format!("cell ({}, {}) holds type {} but there are only {} types", x, y, t, self.num_types));
+            }
+        }
+
+        if let Some(region) = self.active_region {
+            let inside = Region::clamped(region.x0, region.y0, region.x1, region.y1, self.size);
+            if inside != Some(region) {
+                return Err(format!("active region {:?} does not fit a {}x{} grid", region, self.size, self.size));
+            }
+        }
+
+        let rules = &self.rules;
+        if rules.affinity.len() != n || rules.affinity.iter().any(|row| row.len() != n) {
+            return Err(format!("affinity table is not {}x{}", n, n));
+        }
+        for (name, table) in [("copy", &rules.copy_type), ("replace", &rules.replace_type)] {
+            if table.len() != n {
+                return Err(format!("{} table has {} entries, expected {}", name, table.len(), n));
+            }
+            if let Some((t, &v)) = table.iter().enumerate().find(|(_, &v)| v as usize > self.num_types) {
+                return Err(format!("{} rule for type {} points at unknown type {}", name, t, v));
+            }
+        }
+        if rules.noise.len() != n {
+            return Err(format!("noise table has {} entries, expected {}", rules.noise.len(), n));
+        }
+        Ok(())
+    }
+
+    // Moves never create or destroy particles, so the total must hold
+    // across a step (except that an absorbing edge or starvation may only
+    // lower it).
+    // Per-type counts only hold while the copy/replace reaction is
+    // switched off (trigger probability 0).
+    pub(crate) fn check_conservation(&self, before: &Census) -> Result<(), String> {
+        let after = self.census();
+        if self.rules.boundary == BoundaryMode::Absorb || self.energy.is_some() {
+            if after.total > before.total {
+                return Err(format!("particle count rose from {} to {} with particle loss on", before.total, after.total));
+            }
+            return Ok(());
+        }
+        if after.total != before.total {
+            return Err(format!("particle count went from {} to {}", before.total, after.total));
+        }
+        if self.rules.replace_probability == 0.0 {
+            for (t, (&b, &a)) in before.per_type.iter().zip(&after.per_type).enumerate().skip(1) {
+                if a != b {
+                    return Err(format!("type {} count went from {} to {} with reactions off", t, b, a));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checked(grid: &mut Vec<Vec<u8>>, wrap: bool) -> CheckedCells<'_, Vec<Vec<u8>>> {
+        CheckedCells { cells: grid, wrap, reach: 1, violation: RefCell::new(None) }
+    }
+
+    #[test]
+    fn off_grid_writes_are_caught() {
+        let mut grid = vec![vec![0; 4]; 4];
+        let mut cells = checked(&mut grid, false);
+        cells.set(0, 0, 1);
+        assert_eq!(cells.get(0, 0), 1);
+        assert!(cells.violation.borrow().is_none());
+        cells.set(0usize.wrapping_sub(1), 2, 1);
+        let violation = cells.violation.into_inner().unwrap();
+        assert!(violation.contains("write of cell") && violation.contains("off the 4x4 grid"));
+    }
+
+    #[test]
+    fn off_grid_reads_are_caught() {
+        let mut grid = vec![vec![0; 4]; 4];
+        let cells = checked(&mut grid, false);
+        assert_eq!(cells.get(4, 1), 0);
+        assert_eq!(cells.violation.into_inner().unwrap(), "read of cell (4, 1) is off the 4x4 grid");
+    }
+
+    #[test]
+    fn moves_and_reactions_must_stay_close() {
+        let mut grid = vec![vec![0; 4]; 4];
+        let mut cells = checked(&mut grid, false);
+        cells.carry(&[((0, 0), (1, 1))]);
+        cells.converted((0, 0), (1, 0));
+        assert!(cells.violation.borrow().is_none());
+        cells.carry(&[((0, 0), (3, 0))]);
+        assert!(cells.violation.into_inner().unwrap().contains("not adjacent"));
+
+        // Across the wrapped edge the same cells are neighbors
+        let mut cells = checked(&mut grid, true);
+        cells.carry(&[((0, 0), (3, 3))]);
+        assert!(cells.violation.borrow().is_none());
+        cells.converted((0, 0), (2, 0));
+        assert!(cells.violation.into_inner().unwrap().contains("beyond its reach"));
+    }
+}
@@ -0,0 +1,30 @@
+// Core diagnostics. The simulation doesn't know where its messages end up:
+// it hands them to a hook, which the crate points at the browser console or
+// stderr (see logging.rs). With no hook set they are dropped unformatted.
+
+use std::fmt;
+use std::sync::RwLock;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+// Gets the message unformatted, so a hook that filters it out costs nothing
+pub type LogHook = fn(Level, fmt::Arguments);
+
+static HOOK: RwLock<Option<LogHook>> = RwLock::new(None);
+
+pub fn set_log_hook(hook: Option<LogHook>) {
+    *HOOK.write().unwrap_or_else(|e| e.into_inner()) = hook;
+}
+
+pub(crate) fn emit(level: Level, message: fmt::Arguments) {
+    let hook = *HOOK.read().unwrap_or_else(|e| e.into_inner());
+    if let Some(hook) = hook {
+        hook(level, message);
+    }
+}
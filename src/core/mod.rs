@@ -0,0 +1,626 @@
+// The simulation itself: grid storage, the step loop, and the bookkeeping
+// that watches it (clock, convergence window, cycle detection).
+// ParticleGrid wraps a Simulation and adds the JS-facing API, palette and
+// export buffers on top.
+//
+// Nothing in here needs a browser or depends on the bindings: a Simulation
+// is built from a SimulationConfig, the modes it runs under are plain enums
+// (their JS mirrors are in wasm.rs), and messages go through the hook in
+// log.rs. The only other ties to the crate are the Philox generator the
+// tiled step draws from (philox.rs) and, with the trace feature, profiling
+// spans (trace.rs). So it runs under plain `cargo test` (see the tests
+// below) or a fuzzer.
+
+// Core code logs through the hook in log.rs; these shadow the crate-wide
+// macros of the same name
+macro_rules! log_warn {
+    ($($t:tt)*) => ($crate::core::log::emit($crate::core::Level::Warn, format_args!($($t)*)))
+}
+
+macro_rules! log_info {
+    ($($t:tt)*) => ($crate::core::log::emit($crate::core::Level::Info, format_args!($($t)*)))
+}
+
+macro_rules! log_debug {
+    ($($t:tt)*) => ($crate::core::log::emit($crate::core::Level::Debug, format_args!($($t)*)))
+}
+
+mod autopilot;
+mod boundary;
+mod config;
+mod coupling;
+mod energy;
+mod identity;
+mod init;
+mod invariants;
+mod log;
+mod modulation;
+mod movement;
+mod pins;
+pub(crate) mod rules;
+mod schedule;
+mod symmetry;
+mod terrain;
+mod tiled;
+mod types;
+
+use std::collections::VecDeque;
+
+use rand::prelude::*;
+
+pub use boundary::BoundaryMode;
+pub(crate) use boundary::Step;
+pub use config::SimulationConfig;
+pub use init::InitPattern;
+pub(crate) use init::fill_grid;
+pub use log::{set_log_hook, Level, LogHook};
+pub use movement::MovementMode;
+pub use symmetry::Symmetry;
+pub(crate) use symmetry::symmetrize;
+pub(crate) use rules::Rules;
+use modulation::Modulation;
+use schedule::Schedule;
+use energy::{Energy, EnergyCells};
+use identity::{IdCells, Identities};
+use autopilot::DensityTargets;
+pub(crate) use types::TypeChange;
+pub use tiled::DEFAULT_TILE_SIZE;
+use tiled::Tiling;
+pub use energy::EnergyConfig;
+
+// Inclusive rectangle of cells
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Region {
+    pub(crate) x0: usize,
+    pub(crate) y0: usize,
+    pub(crate) x1: usize,
+    pub(crate) y1: usize,
+}
+
+impl Region {
+    pub(crate) fn full(size: usize) -> Region {
+        Region { x0: 0, y0: 0, x1: size.saturating_sub(1), y1: size.saturating_sub(1) }
+    }
+
+    // Corners in either order, clipped to the grid; None if entirely outside
+    pub(crate) fn clamped(x0: usize, y0: usize, x1: usize, y1: usize, size: usize) -> Option<Region> {
+        let (x0, x1) = (x0.min(x1), x0.max(x1));
+        let (y0, y1) = (y0.min(y1), y0.max(y1));
+        if size == 0 || x0 >= size || y0 >= size {
+            return None;
+        }
+        Some(Region { x0, y0, x1: x1.min(size - 1), y1: y1.min(size - 1) })
+    }
+
+    pub(crate) fn cell_count(&self) -> usize {
+        (self.x1 - self.x0 + 1) * (self.y1 - self.y0 + 1)
+    }
+}
+
+// What one round of updates did
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StepStats {
+    pub updates: usize,
+    pub changed_cells: usize,
+}
+
+// Largest update fraction: ten updates per particle per step
+const MAX_UPDATE_FRACTION: f32 = 10.0;
+
+pub(crate) fn clamp_update_fraction(fraction: f32) -> f32 {
+    if fraction.is_finite() { fraction.clamp(0.0, MAX_UPDATE_FRACTION) } else { 0.2 }
+}
+
+// Attraction and repulsion scales are non-negative; anything else is 1
+pub(crate) fn clamp_scale(scale: f32) -> f32 {
+    if scale.is_finite() { scale.max(0.0) } else { 1.0 }
+}
+
+// A particle's (from, to) cells
+pub(crate) type Move = ((usize, usize), (usize, usize));
+
+// Cell storage the rules operate on: either the full grid or a tile copied
+// out of it. Coordinates are always in full-grid space.
+pub(crate) trait Cells {
+    fn size(&self) -> usize;
+    fn get(&self, x: usize, y: usize) -> u8;
+    fn set(&mut self, x: usize, y: usize, t: u8);
+
+    // Per-particle state that travels with moves and reactions (energy);
+    // plain grids have none. `moves` happen simultaneously.
+    fn carry(&mut self, _moves: &[Move]) {}
+    // The particle at `by` converted the one at `at`
+    fn converted(&mut self, _by: (usize, usize), _at: (usize, usize)) {}
+}
+
+impl Cells for Vec<Vec<u8>> {
+    #[inline]
+    fn size(&self) -> usize {
+        self.len()
+    }
+
+    #[inline]
+    fn get(&self, x: usize, y: usize) -> u8 {
+        self[x][y]
+    }
+
+    #[inline]
+    fn set(&mut self, x: usize, y: usize, t: u8) {
+        self[x][y] = t;
+    }
+}
+
+#[derive(Clone)]
+pub struct Simulation {
+    pub(crate) size: usize,
+    pub(crate) num_types: usize,
+    pub(crate) density: f32,
+    // Starting density of each type, indexed by type (entry 0 unused).
+    // None spreads `density` evenly over the types.
+    pub(crate) type_densities: Option<Vec<f32>>,
+    pub(crate) type_grid: Vec<Vec<u8>>,
+    pub(crate) rules: Rules,
+    pub(crate) rng: StdRng,
+    // Simulation clock: step() calls and particle updates actually carried out
+    pub(crate) generation: u64,
+    pub(crate) updates_performed: u64,
+    // Cells changed in each of the last `convergence_window` steps
+    pub(crate) change_history: VecDeque<usize>,
+    pub(crate) convergence_window: usize,
+    // Grid hashes of the last `cycle_max_period` steps (0 = detection off)
+    pub(crate) recent_hashes: VecDeque<u64>,
+    pub(crate) cycle_max_period: usize,
+    pub(crate) cycle_period: Option<u32>,
+    // Restricts stepping to a sub-rectangle when set
+    pub(crate) active_region: Option<Region>,
+    // Debug mode: validate the grid after every step, optionally also
+    // checking that particle counts are conserved
+    pub(crate) invariant_checks: bool,
+    pub(crate) conservation_checks: bool,
+    // Keyframed parameter changes, applied before each step
+    schedule: Schedule,
+    // Live 0-1 signals easing the rule gains each step
+    modulation: Modulation,
+    // Per-particle energy reserves when the energy model is on
+    pub(crate) energy: Option<Energy>,
+    // Particle ids, while something needs to follow particles
+    pub(crate) ids: Option<Identities>,
+    // Per-type density targets held after each step
+    autopilot: DensityTargets,
+    // Tile size and stream key while stepping in tiles (see tiled.rs)
+    pub(crate) tiling: Option<Tiling>,
+}
+
+impl Simulation {
+    // Lay out the starting grid and rule tables described by `config`.
+    // Palette entries are not part of the simulation and are ignored here.
+    // Types past the 255 a cell can hold are dropped.
+    pub fn from_config(config: &SimulationConfig) -> Simulation {
+        let size = config.size;
+        let num_types = config.num_types.min(u8::MAX as usize);
+        let n = num_types + 1;
+
+        let type_densities = config
+            .type_densities
+            .as_ref()
+            .map(|d| init::normalize_type_densities(d, num_types));
+        let density = match &type_densities {
+            Some(d) => d.iter().sum(),
+            None => config.density,
+        };
+        log_info!("Creating ParticleGrid: {}x{}, {} types, density {:.2}, radius {}",
+            size, size, num_types, density, config.radius);
+
+        let mut rng = StdRng::seed_from_u64(config.seed.unwrap_or_else(|| thread_rng().gen()));
+
+        // Initialize grid with random particles
+        let mut type_grid = fill_grid(
+            size,
+            num_types,
+            density,
+            type_densities.as_deref(),
+            InitPattern::Uniform,
+            &mut rng,
+        );
+        symmetrize(&mut type_grid, config.symmetry);
+
+        // Initialize affinity matrix
+        let mut affinity = vec![vec![0i8; n]; n];
+        if config.affinity.is_empty() {
+            rules::randomize_affinity(&mut affinity, &mut rng);
+        } else if config.affinity.len() >= n && config.affinity.iter().take(n).all(|row| row.len() >= n) {
+            for (row, values) in affinity.iter_mut().zip(&config.affinity) {
+                row.copy_from_slice(&values[..n]);
+            }
+            log_debug!("Used custom affinity matrix");
+        } else {
+            log_warn!("Custom affinity array too small, using random");
+            rules::randomize_affinity(&mut affinity, &mut rng);
+        }
+
+        // Initialize copy_type and replace_type arrays. Random picks are
+        // drawn even when the config supplies tables so the generator stays
+        // in step either way.
+        let mut copy_type = vec![0u8; n];
+        let mut replace_type = vec![0u8; n];
+        for t in 0..n {
+            copy_type[t] = rules::pick_copy_type(t as u8, num_types, &mut rng);
+            replace_type[t] = rules::pick_replace_type(t as u8, copy_type[t], num_types, &mut rng);
+        }
+        if config.copy_types.len() >= n && config.replace_types.len() >= n {
+            copy_type.copy_from_slice(&config.copy_types[..n]);
+            replace_type.copy_from_slice(&config.replace_types[..n]);
+        }
+
+        let mut noise = vec![0.0f32; n];
+        for (t, &p) in config.type_noise.iter().enumerate().take(n).skip(1) {
+            noise[t] = if p.is_finite() { p.clamp(0.0, 1.0) } else { 0.0 };
+        }
+
+        let mut sim = Simulation {
+            size,
+            num_types,
+            density,
+            type_densities,
+            type_grid,
+            rules: Rules {
+                radius: config.radius,
+                affinity,
+                copy_type,
+                replace_type,
+                replace_radius: config.replace_radius,
+                replace_probability: config.replace_probability.clamp(0.0, 1.0),
+                max_conversions: config.max_conversions,
+                attraction_scale: clamp_scale(config.attraction_scale),
+                repulsion_scale: clamp_scale(config.repulsion_scale),
+                anisotropy: config.anisotropy,
+                crowding: if config.crowding.is_finite() { config.crowding.max(0.0) } else { 0.0 },
+                update_fraction: clamp_update_fraction(config.update_fraction),
+                noise,
+                gains: Default::default(),
+                coupling: Default::default(),
+                terrain: Default::default(),
+                pins: Default::default(),
+                symmetry: config.symmetry,
+                boundary: config.boundary,
+                movement: config.movement,
+                move_threshold: if config.move_threshold.is_finite() { config.move_threshold } else { 0.0 },
+                softmax: config.softmax_sharpness.filter(|s| s.is_finite() && *s >= 0.0),
+            },
+            rng,
+            generation: 0,
+            updates_performed: 0,
+            change_history: VecDeque::new(),
+            convergence_window: 50,
+            recent_hashes: VecDeque::new(),
+            cycle_max_period: 0,
+            cycle_period: None,
+            active_region: None,
+            invariant_checks: false,
+            conservation_checks: false,
+            schedule: Schedule::default(),
+            modulation: Modulation::default(),
+            energy: config.energy.clone().map(|c| Energy::new(c, size)),
+            ids: None,
+            autopilot: Default::default(),
+            tiling: None,
+        };
+        // Drawn last, so turning tiling on leaves the rest of the setup alone
+        if let Some(tile_size) = config.tile_size {
+            sim.enable_tiling(tile_size);
+        }
+        sim
+    }
+
+    // Advance one generation and update the clock, convergence window and
+    // cycle detector. Panics if invariant checks are on and fail.
+    pub fn step(&mut self) -> StepStats {
+        self.try_step().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // step() that reports a failed invariant check instead of panicking.
+    // The step itself has still been applied when it errors.
+    pub fn try_step(&mut self) -> Result<StepStats, String> {
+        if !self.schedule.is_empty() {
+            self.schedule.apply(&mut self.rules, self.generation);
+        }
+        if !self.modulation.is_empty() {
+            self.modulation.advance(&mut self.rules);
+        }
+        let before = (self.invariant_checks && self.conservation_checks).then(|| self.census());
+        let (mut stats, in_bounds) = self.run_updates();
+        // The autopilot adds and removes particles on purpose, so counts
+        // are checked before it runs
+        let conserved = before.map_or(Ok(()), |census| self.check_conservation(&census));
+        if !self.autopilot.targets.is_empty() {
+            stats.changed_cells += self.hold_densities();
+        }
+
+        self.generation += 1;
+        self.updates_performed += stats.updates as u64;
+
+        self.change_history.push_back(stats.changed_cells);
+        while self.change_history.len() > self.convergence_window {
+            self.change_history.pop_front();
+        }
+
+        if self.cycle_max_period > 0 {
+            self.detect_cycle();
+        }
+
+        if self.invariant_checks {
+            self.check_invariants()
+                .and(in_bounds)
+                .and(conserved)
+                .map_err(|e| format!("invariant violated in generation {}: {}", self.generation, e))?;
+        }
+        Ok(stats)
+    }
+
+    // Validate after every step; with `conservation` also require that the
+    // particle count is unchanged (see check_conservation)
+    pub fn enable_invariant_checks(&mut self, conservation: bool) {
+        self.invariant_checks = true;
+        self.conservation_checks = conservation;
+    }
+
+    pub fn disable_invariant_checks(&mut self) {
+        self.invariant_checks = false;
+        self.conservation_checks = false;
+    }
+
+    // The step's updates, and whether they kept to the grid (only checked
+    // with invariant checks on)
+    fn run_updates(&mut self) -> (StepStats, Result<(), String>) {
+        trace_span!("updates");
+        self.prune_pins();
+        let region = self.active_region.unwrap_or_else(|| Region::full(self.size));
+        let fraction = self.rules.update_fraction;
+        let updates = (fraction * self.density * region.cell_count() as f32 * self.terrain_boost()).floor() as usize;
+
+        // Tiles don't carry energy or ids, so those run sequentially
+        if let Some(tiling) = self.tiling.filter(|_| self.energy.is_none() && self.ids.is_none()) {
+            if tiled::supported(&self.rules) {
+                let rate = fraction * self.terrain_boost();
+                let stats = tiled::step_tiled(&mut self.type_grid, &self.rules, region, rate, tiling, self.generation);
+                return (stats, Ok(()));
+            }
+        }
+
+        if updates == 0 {
+            return (StepStats::default(), Ok(()));
+        }
+
+        // Collect current non-empty cells
+        let mut particles: Vec<(usize, usize)> = Vec::with_capacity(region.cell_count() / 2);
+
+        for x in region.x0..=region.x1 {
+            for y in region.y0..=region.y1 {
+                if self.type_grid[x][y] != 0 {
+                    particles.push((x, y));
+                }
+            }
+        }
+
+        let (rng, check) = (&mut self.rng, self.invariant_checks);
+        let rules = &self.rules;
+        let (stats, in_bounds) = match (&mut self.energy, &mut self.ids) {
+            (None, None) => {
+                return rules.update_particles_checked(&mut self.type_grid, &mut particles, updates, rng, check);
+            }
+            (None, Some(ids)) => {
+                ids.sync(&self.type_grid);
+                let mut cells = IdCells { cells: &mut self.type_grid, ids };
+                rules.update_particles_checked(&mut cells, &mut particles, updates, rng, check)
+            }
+            (Some(energy), ids) => {
+                let mut types = EnergyCells { types: &mut self.type_grid, energy };
+                let (mut stats, in_bounds) = match ids {
+                    Some(ids) => {
+                        ids.sync(types.types);
+                        let mut cells = IdCells { cells: &mut types, ids };
+                        rules.update_particles_checked(&mut cells, &mut particles, updates, rng, check)
+                    }
+                    None => rules.update_particles_checked(&mut types, &mut particles, updates, rng, check),
+                };
+                stats.changed_cells += energy.drain(&mut self.type_grid, region, rules.symmetry);
+                (stats, in_bounds)
+            }
+        };
+        // Drop the ids of particles that left the grid or starved
+        self.sync_identities();
+        (stats, in_bounds)
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn num_types(&self) -> usize {
+        self.num_types
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    // Type at (x, y); 0 for empty or out of range
+    pub fn cell(&self, x: usize, y: usize) -> u8 {
+        if x < self.size && y < self.size { self.type_grid[x][y] } else { 0 }
+    }
+
+    pub fn count_particles(&self) -> usize {
+        self.type_grid.iter().flatten().filter(|&&t| t != 0).count()
+    }
+
+    // FNV-1a hash of the grid size and contents. Equal grids hash equally
+    // across runs and platforms, so this doubles as a regression check.
+    pub fn state_hash(&self) -> u64 {
+        const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        let mut hash = FNV_OFFSET;
+        for byte in (self.size as u64).to_le_bytes() {
+            hash = (hash ^ byte as u64).wrapping_mul(FNV_PRIME);
+        }
+        for y in 0..self.size {
+            for x in 0..self.size {
+                hash = (hash ^ self.type_grid[x][y] as u64).wrapping_mul(FNV_PRIME);
+            }
+        }
+        hash
+    }
+
+    pub fn set_active_region(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        self.active_region = Region::clamped(x0, y0, x1, y1, self.size);
+    }
+
+    pub fn clear_active_region(&mut self) {
+        self.active_region = None;
+    }
+
+    pub fn enable_cycle_detection(&mut self, max_period: usize) {
+        self.cycle_max_period = max_period;
+        self.recent_hashes.clear();
+        self.cycle_period = None;
+    }
+
+    pub fn cycle_period(&self) -> Option<u32> {
+        self.cycle_period
+    }
+
+    fn detect_cycle(&mut self) {
+        trace_span!("cycle_detection");
+        let hash = self.state_hash();
+        self.cycle_period = self
+            .recent_hashes
+            .iter()
+            .rev()
+            .position(|&h| h == hash)
+            .map(|i| i as u32 + 1);
+
+        self.recent_hashes.push_back(hash);
+        while self.recent_hashes.len() > self.cycle_max_period {
+            self.recent_hashes.pop_front();
+        }
+    }
+
+    pub fn set_convergence_window(&mut self, steps: usize) {
+        self.convergence_window = steps.max(1);
+        while self.change_history.len() > self.convergence_window {
+            self.change_history.pop_front();
+        }
+    }
+
+    pub fn recent_changes(&self) -> usize {
+        self.change_history.iter().sum()
+    }
+
+    pub fn is_converged(&self, threshold: f64) -> bool {
+        if self.change_history.len() < self.convergence_window {
+            return false;
+        }
+        let cells_per_window = (self.convergence_window * self.size * self.size) as f64;
+        self.recent_changes() as f64 <= threshold * cells_per_window
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sim(seed: u64) -> Simulation {
+        Simulation::from_config(&SimulationConfig { size: 24, num_types: 3, seed: Some(seed), ..Default::default() })
+    }
+
+    #[test]
+    fn same_seed_same_run() {
+        let (mut a, mut b) = (sim(3), sim(3));
+        for _ in 0..10 {
+            a.step();
+            b.step();
+        }
+        assert_eq!(a.state_hash(), b.state_hash());
+        assert_eq!(a.generation(), 10);
+        assert_ne!(a.state_hash(), sim(4).state_hash());
+    }
+
+    #[test]
+    fn steps_keep_invariants() {
+        let mut sim = sim(1);
+        let particles = sim.count_particles();
+        sim.enable_invariant_checks(true);
+        for _ in 0..10 {
+            sim.try_step().unwrap();
+        }
+        // Reactions convert particles but never create or remove them
+        assert_eq!(sim.count_particles(), particles);
+    }
+
+    #[test]
+    fn invariant_checks_catch_bad_cells() {
+        let mut sim = sim(1);
+        assert!(sim.check_invariants().is_ok());
+        sim.type_grid[2][5] = 9;
+        assert!(sim.check_invariants().unwrap_err().contains("(2, 5)"));
+        sim.type_grid[2][5] = 0;
+        sim.rules.copy_type[1] = 7;
+        assert!(sim.check_invariants().unwrap_err().contains("unknown type 7"));
+    }
+
+    #[test]
+    fn active_region_limits_changes() {
+        let mut sim = sim(2);
+        let before = sim.type_grid.clone();
+        sim.set_active_region(4, 4, 11, 11);
+        for _ in 0..5 {
+            sim.step();
+        }
+        // Particles inside may step or convert just past the edge, but
+        // nothing further out is touched
+        let near = 2..=13;
+        for (x, column) in before.iter().enumerate() {
+            for (y, &t) in column.iter().enumerate() {
+                if !near.contains(&x) || !near.contains(&y) {
+                    assert_eq!(sim.cell(x, y), t, "({}, {}) changed", x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn region_clamping() {
+        assert_eq!(Region::clamped(9, 9, 12, 10, 8), None);
+        // Corners in either order, clipped to the grid
+        let region = Region::clamped(6, 9, 2, 3, 8).unwrap();
+        assert_eq!(region, Region { x0: 2, y0: 3, x1: 6, y1: 7 });
+        assert_eq!(region.cell_count(), 25);
+        assert_eq!(Region::clamped(0, 0, 0, 0, 0), None);
+    }
+
+    #[test]
+    fn interaction_scales_stay_non_negative() {
+        let config = SimulationConfig {
+            size: 4,
+            attraction_scale: -2.0,
+            repulsion_scale: f32::NAN,
+            ..Default::default()
+        };
+        let sim = Simulation::from_config(&config);
+        assert_eq!((sim.rules.attraction_scale, sim.rules.repulsion_scale), (0.0, 1.0));
+    }
+
+    #[test]
+    fn frozen_grid_converges_with_period_one() {
+        let config = SimulationConfig { size: 8, density: 0.0, seed: Some(1), ..Default::default() };
+        let mut sim = Simulation::from_config(&config);
+        sim.set_convergence_window(3);
+        sim.enable_cycle_detection(4);
+        assert!(!sim.is_converged(0.0));
+        for _ in 0..3 {
+            sim.step();
+        }
+        assert!(sim.is_converged(0.0));
+        assert_eq!(sim.cycle_period(), Some(1));
+    }
+}
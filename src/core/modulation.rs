@@ -0,0 +1,168 @@
+// Real-time modulation: external 0-1 signals (audio levels, MIDI knobs)
+// that scale parts of the rules without touching the configured values.
+// Each signal maps to a gain of 0-2 (0.5 leaves things as configured) and
+// the gain eases towards it a little every step, so a jittery input drives
+// the simulation smoothly.
+
+use super::{Rules, Simulation, TypeChange};
+
+// Multipliers on top of the configured rules, all 1.0 when unmodulated
+#[derive(Clone, Debug)]
+pub(crate) struct Gains {
+    pub(crate) attraction: f32,
+    pub(crate) repulsion: f32,
+    // Above 1 the far edge of the scoring window counts for more than the
+    // near cells, below 1 for less
+    pub(crate) radial: f32,
+    // Scales every type's noise
+    pub(crate) noise: f32,
+    // Per-pair affinity gains; pairs not listed are 1.0
+    pub(crate) pairs: Vec<(u8, u8, f32)>,
+}
+
+impl Default for Gains {
+    fn default() -> Self {
+        Gains { attraction: 1.0, repulsion: 1.0, radial: 1.0, noise: 1.0, pairs: Vec::new() }
+    }
+}
+
+impl Gains {
+    #[inline]
+    pub(crate) fn pair(&self, from: u8, to: u8) -> f32 {
+        self.pairs
+            .iter()
+            .find(|&&(f, t, _)| f == from && t == to)
+            .map_or(1.0, |&(_, _, g)| g)
+    }
+
+    pub(super) fn retype(&mut self, change: TypeChange) {
+        self.pairs.retain_mut(|(from, to, _)| match (change.remap(*from), change.remap(*to)) {
+            (Some(f), Some(t)) => {
+                (*from, *to) = (f, t);
+                true
+            }
+            _ => false,
+        });
+    }
+
+    fn get_mut(&mut self, channel: Channel) -> &mut f32 {
+        match channel {
+            Channel::Attraction => &mut self.attraction,
+            Channel::Repulsion => &mut self.repulsion,
+            Channel::RadiusWeight => &mut self.radial,
+            Channel::Noise => &mut self.noise,
+            Channel::Affinity(from, to) => {
+                let i = match self.pairs.iter().position(|&(f, t, _)| f == from && t == to) {
+                    Some(i) => i,
+                    None => {
+                        self.pairs.push((from, to, 1.0));
+                        self.pairs.len() - 1
+                    }
+                };
+                &mut self.pairs[i].2
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Channel {
+    Attraction,
+    Repulsion,
+    RadiusWeight,
+    Noise,
+    Affinity(u8, u8),
+}
+
+impl Channel {
+    // Follow `change`; false if the channel's type went
+    fn retype(&mut self, change: TypeChange) -> bool {
+        if let Channel::Affinity(from, to) = self {
+            let (Some(f), Some(t)) = (change.remap(*from), change.remap(*to)) else {
+                return false;
+            };
+            (*from, *to) = (f, t);
+        }
+        true
+    }
+
+    // "attraction", "repulsion", "radius_weight", "noise" or
+    // "affinity:<from>:<to>"
+    fn parse(name: &str) -> Option<Channel> {
+        Some(match name {
+            "attraction" => Channel::Attraction,
+            "repulsion" => Channel::Repulsion,
+            "radius_weight" => Channel::RadiusWeight,
+            "noise" => Channel::Noise,
+            _ => {
+                let mut parts = name.strip_prefix("affinity:")?.split(':');
+                let from = parts.next()?.parse().ok()?;
+                let to = parts.next()?.parse().ok()?;
+                if parts.next().is_some() {
+                    return None;
+                }
+                Channel::Affinity(from, to)
+            }
+        })
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Modulation {
+    targets: Vec<(Channel, f32)>,
+    // Fraction of the remaining distance to the target covered per step
+    smoothing: f32,
+}
+
+impl Default for Modulation {
+    fn default() -> Self {
+        Modulation { targets: Vec::new(), smoothing: 0.2 }
+    }
+}
+
+impl Modulation {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    // Signals on a removed type's affinities go with it
+    pub(super) fn retype(&mut self, change: TypeChange) {
+        self.targets.retain_mut(|(channel, _)| channel.retype(change));
+    }
+
+    pub(crate) fn advance(&self, rules: &mut Rules) {
+        for &(channel, target) in &self.targets {
+            let gain = rules.gains.get_mut(channel);
+            *gain += (target - *gain) * self.smoothing;
+        }
+    }
+}
+
+impl Simulation {
+    // Drive `param` from a 0-1 signal (clamped); see Channel::parse for
+    // the names. Returns false if the parameter is not recognised.
+    pub fn set_modulation(&mut self, param: &str, value: f32) -> bool {
+        let Some(channel) = Channel::parse(param) else {
+            return false;
+        };
+        let target = if value.is_finite() { value.clamp(0.0, 1.0) * 2.0 } else { 1.0 };
+        match self.modulation.targets.iter_mut().find(|(c, _)| *c == channel) {
+            Some(entry) => entry.1 = target,
+            None => self.modulation.targets.push((channel, target)),
+        }
+        true
+    }
+
+    // 1 jumps straight to each new value; smaller is smoother but lags
+    pub fn set_modulation_smoothing(&mut self, smoothing: f32) {
+        if smoothing.is_finite() {
+            self.modulation.smoothing = smoothing.clamp(0.01, 1.0);
+        }
+    }
+
+    // Drop all signals and return the rules to their configured strength
+    pub fn clear_modulation(&mut self) {
+        self.modulation.targets.clear();
+        self.rules.gains = Gains::default();
+    }
+}
@@ -0,0 +1,107 @@
+// How a particle picks its next cell. Greedy always relocates to the best
+// adjacent empty cell, even when that is worse than staying (the original
+// behavior). Threshold only moves when the best neighbor beats the
+// particle's current score by at least the move threshold, so settled
+// particles stay settled. Centroid ignores scores and steps towards the
+// weighted center of the neighbors it is attracted to.
+//
+// Greedy and Threshold normally take the best-scoring option. With softmax
+// selection on they draw one instead, each with probability proportional
+// to exp(sharpness * score): high sharpness approaches the argmax, 0 is a
+// uniform random walk, and in between particles drift uphill without
+// snapping to the lattice. Scores are mean neighbor weights, mostly within
+// +-1, so useful sharpness values run from about 1 to 50.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::{Cells, Rules, Simulation, Step};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MovementMode {
+    #[default]
+    Greedy = 0,
+    Threshold = 1,
+    Centroid = 2,
+}
+
+impl Rules {
+    // Adjacent cell that brings the particle at (x, y) closest to the
+    // centroid of its attractive neighbors within `radius`, or (x, y) if
+    // none gets closer. None means stepping off an absorbing edge.
+    pub(crate) fn centroid_target<C: Cells>(&self, cells: &C, x: usize, y: usize) -> Option<(usize, usize)> {
+        let size = cells.size();
+        let p_type = cells.get(x, y);
+        let (mut cx, mut cy, mut total) = (0.0f32, 0.0f32, 0.0f32);
+        let xs = self.boundary.axis(x as isize, self.radius, size);
+        for (dy, yy) in self.boundary.axis(y as isize, self.radius, size) {
+            for (dx, xx) in xs.clone() {
+                let t = cells.get(xx, yy);
+                if t == 0 || (dx, dy) == (0, 0) {
+                    continue;
+                }
+                let w = self.interaction_weight(self.affinity[p_type as usize][t as usize]);
+                if w > 0.0 {
+                    cx += w * dx as f32;
+                    cy += w * dy as f32;
+                    total += w;
+                }
+            }
+        }
+        if total == 0.0 {
+            return Some((x, y));
+        }
+        let (cx, cy) = (cx / total, cy / total);
+
+        // Offsets are relative to (x, y), so staying put is at distance
+        // |centroid| and each step is compared against that
+        let mut best = (cx * cx + cy * cy, Some((x, y)));
+        for dy in -1..=1isize {
+            for dx in -1..=1isize {
+                let target = match self.step_target(x, y, dx, dy, size) {
+                    Step::To(i, j) if cells.get(i, j) == 0 => Some((i, j)),
+                    Step::Off(..) => None,
+                    _ => continue,
+                };
+                let (ex, ey) = (cx - dx as f32, cy - dy as f32);
+                let d = ex * ex + ey * ey;
+                if d < best.0 {
+                    best = (d, target);
+                }
+            }
+        }
+        best.1
+    }
+}
+
+// Draw one option by softmax over the scores; None if there are none
+pub(crate) fn softmax_choice<T: Copy, R: Rng>(options: &[(T, f32)], sharpness: f32, rng: &mut R) -> Option<T> {
+    let max = options.iter().map(|&(_, s)| s).fold(f32::NEG_INFINITY, f32::max);
+    let weight = |s: f32| (sharpness * (s - max)).exp();
+    let total: f32 = options.iter().map(|&(_, s)| weight(s)).sum();
+    let mut pick = rng.gen::<f32>() * total;
+    for &(option, s) in options {
+        pick -= weight(s);
+        if pick < 0.0 {
+            return Some(option);
+        }
+    }
+    // Rounding can leave a sliver past the last option
+    options.last().map(|&(option, _)| option)
+}
+
+impl Simulation {
+    // Pick the movement policy. `threshold` is the margin a move must win
+    // by in Threshold mode (0 moves on any improvement) and is otherwise
+    // kept for later; non-finite values leave it unchanged.
+    pub fn set_movement_mode(&mut self, mode: MovementMode, threshold: f32) {
+        self.rules.movement = mode;
+        if threshold.is_finite() {
+            self.rules.move_threshold = threshold;
+        }
+    }
+
+    pub fn movement_mode(&self) -> MovementMode {
+        self.rules.movement
+    }
+}
@@ -0,0 +1,82 @@
+// Pinned particles. A pinned particle never moves and cannot be converted,
+// but it still counts in its neighbors' scores and runs its own reaction,
+// so a few pinned seeds act as fixed templates for growth around them.
+// Pins belong to the cell and are dropped once the cell empties (an edit
+// or starvation removed the particle).
+
+use super::{Rules, Simulation};
+
+#[derive(Clone, Default)]
+pub(crate) struct Pins {
+    // Indexed [x * size + y]; empty when nothing is pinned
+    cells: Vec<bool>,
+}
+
+impl Rules {
+    #[inline]
+    pub(crate) fn is_pinned(&self, x: usize, y: usize, size: usize) -> bool {
+        let pins = &self.pins.cells;
+        pins.len() == size * size && pins[x * size + y]
+    }
+}
+
+impl Simulation {
+    // Pin the particle at (x, y). Returns false if the cell is empty or out
+    // of range.
+    pub fn pin_cell(&mut self, x: usize, y: usize) -> bool {
+        let n = self.size;
+        if self.cell(x, y) == 0 {
+            return false;
+        }
+        let pins = &mut self.rules.pins.cells;
+        if pins.len() != n * n {
+            *pins = vec![false; n * n];
+        }
+        pins[x * n + y] = true;
+        true
+    }
+
+    pub fn unpin_cell(&mut self, x: usize, y: usize) {
+        if x < self.size && y < self.size && self.rules.is_pinned(x, y, self.size) {
+            self.rules.pins.cells[x * self.size + y] = false;
+        }
+    }
+
+    pub fn clear_pins(&mut self) {
+        self.rules.pins = Pins::default();
+    }
+
+    pub fn is_pinned(&self, x: usize, y: usize) -> bool {
+        x < self.size && y < self.size && self.rules.is_pinned(x, y, self.size)
+    }
+
+    // Pinned cells as indices into export_grid's layout
+    pub(crate) fn pinned_cells(&self) -> Vec<usize> {
+        let n = self.size;
+        let pins = &self.rules.pins.cells;
+        if pins.len() != n * n {
+            return Vec::new();
+        }
+        let mut cells: Vec<usize> = (0..n * n).filter(|&i| pins[i]).map(|i| (i % n) * n + i / n).collect();
+        cells.sort_unstable();
+        cells
+    }
+
+    // Drop pins on cells that have emptied since they were set
+    pub(crate) fn prune_pins(&mut self) {
+        let n = self.size;
+        let pins = &mut self.rules.pins.cells;
+        if pins.len() != n * n {
+            pins.clear();
+            return;
+        }
+        for (i, pin) in pins.iter_mut().enumerate() {
+            if *pin && self.type_grid[i / n][i % n] == 0 {
+                *pin = false;
+            }
+        }
+        if !pins.contains(&true) {
+            pins.clear();
+        }
+    }
+}
@@ -0,0 +1,546 @@
+// Interaction rules and the per-particle update: the score a particle
+// gives each cell from its neighbors' affinities, the move to an adjacent
+// empty cell (see movement.rs for the policies), and the copy/replace
+// reaction.
+
+use rand::prelude::*;
+
+use super::coupling::Coupling;
+use super::modulation::Gains;
+use super::pins::Pins;
+use super::terrain::Terrain;
+use super::movement::softmax_choice;
+use super::{BoundaryMode, Cells, MovementMode, Region, StepStats, Step, Symmetry};
+
+// Kept apart from the grid so they can be borrowed alongside it (and shared
+// across worker threads when stepping in parallel)
+#[derive(Clone)]
+pub(crate) struct Rules {
+    pub(crate) radius: usize,
+    pub(crate) affinity: Vec<Vec<i8>>,
+    // Reaction of each type: near a copy-type particle it turns the
+    // replace-type particles around it into the copy type. Equal copy and
+    // replace types mean no reaction.
+    pub(crate) copy_type: Vec<u8>,
+    pub(crate) replace_type: Vec<u8>,
+    // Reach and strength of the copy/replace reaction. max_conversions of
+    // 0 means every replace-type cell in range is converted.
+    pub(crate) replace_radius: usize,
+    pub(crate) replace_probability: f32,
+    pub(crate) max_conversions: usize,
+    // Multipliers applied to attracting and repelling affinities
+    pub(crate) attraction_scale: f32,
+    pub(crate) repulsion_scale: f32,
+    // Scoring weight of horizontal and vertical neighbors; [1.0, 1.0] is
+    // the isotropic kernel
+    pub(crate) anisotropy: [f32; 2],
+    // Weight of the crowding penalty (see window_score); 0 leaves it off
+    pub(crate) crowding: f32,
+    // Particle updates per step as a fraction of the starting particle
+    // count
+    pub(crate) update_fraction: f32,
+    // Per-type probability of a random move instead of the best one,
+    // indexed by type like copy_type
+    pub(crate) noise: Vec<f32>,
+    // Live modulation on top of the values above
+    pub(crate) gains: Gains,
+    // Pull towards or away from a partner grid (see CoupledGrids)
+    pub(crate) coupling: Coupling,
+    // Per-cell update speed
+    pub(crate) terrain: Terrain,
+    // Particles that hold their cell and type
+    pub(crate) pins: Pins,
+    pub(crate) symmetry: Symmetry,
+    pub(crate) boundary: BoundaryMode,
+    pub(crate) movement: MovementMode,
+    // Margin the best neighbor must beat the current cell by in Threshold
+    // mode
+    pub(crate) move_threshold: f32,
+    // Sharpness of softmax destination choice; None picks the best cell
+    pub(crate) softmax: Option<f32>,
+}
+
+impl Rules {
+    // Run `updates` random particle updates, sampling from `particles`.
+    // Entries whose cell has since emptied are dropped as they come up.
+    pub(crate) fn update_particles<C: Cells, R: Rng>(
+        &self,
+        cells: &mut C,
+        particles: &mut Vec<(usize, usize)>,
+        updates: usize,
+        rng: &mut R,
+    ) -> StepStats {
+        let mut stats = StepStats::default();
+        for _ in 0..updates {
+            if particles.is_empty() {
+                break;
+            }
+            let idx = rng.gen_range(0..particles.len());
+            let (x, y) = particles[idx];
+
+            // Check if particle still exists
+            if cells.get(x, y) == 0 {
+                particles.swap_remove(idx);
+                continue;
+            }
+            if !self.on_the_move(x, y, cells.size(), rng) {
+                continue;
+            }
+
+            stats.changed_cells += self.try_replace_particle(cells, x, y, rng);
+            stats.changed_cells += self.move_particle(cells, x, y, rng);
+            stats.updates += 1;
+        }
+        stats
+    }
+
+    // Update attempts spread over the cells of `tile`, `rate` per cell. Each
+    // picks a cell at random and updates it if it holds a particle, the
+    // same draws gpu/tiled.wgsl makes.
+    pub(crate) fn update_tile<C: Cells, R: Rng>(
+        &self,
+        cells: &mut C,
+        tile: Region,
+        rate: f32,
+        rng: &mut R,
+    ) -> StepStats {
+        let size = cells.size();
+        let (w, h) = (tile.x1 - tile.x0 + 1, tile.y1 - tile.y0 + 1);
+        let attempts = (rate * (w * h) as f32).floor() as usize;
+        let mut stats = StepStats::default();
+        for _ in 0..attempts {
+            let x = tile.x0 + ((rng.gen::<f32>() * w as f32) as usize).min(w - 1);
+            let y = tile.y0 + ((rng.gen::<f32>() * h as f32) as usize).min(h - 1);
+            if cells.get(x, y) == 0 || !self.on_the_move(x, y, size, rng) {
+                continue;
+            }
+            stats.changed_cells += self.try_replace_particle(cells, x, y, rng);
+            stats.changed_cells += self.move_particle(cells, x, y, rng);
+            stats.updates += 1;
+        }
+        stats
+    }
+
+    // Returns the number of cells converted
+    fn try_replace_particle<C: Cells, R: Rng>(&self, cells: &mut C, x: usize, y: usize, rng: &mut R) -> usize {
+        let p_type = cells.get(x, y);
+        if p_type == 0 {
+            return 0;
+        }
+
+        if self.replace_probability < 1.0 && !rng.gen_bool(self.replace_probability.max(0.0) as f64) {
+            return 0;
+        }
+
+        let size = cells.size();
+        let ct = self.copy_type[p_type as usize];
+        let rt = self.replace_type[p_type as usize];
+        // Turning a type into itself changes nothing: the type has no reaction
+        if ct == rt {
+            return 0;
+        }
+        // The reaction reaches across a wrapped edge but is otherwise cut
+        // off at the grid edge, reflecting included
+        let mode = if self.boundary == BoundaryMode::Wrap { BoundaryMode::Wrap } else { BoundaryMode::Clamp };
+        let r = self.replace_radius;
+        let neighborhood = || {
+            let xs = mode.axis(x as isize, r, size);
+            mode.axis(y as isize, r, size).flat_map(move |(_, j)| xs.clone().map(move |(_, i)| (i, j)))
+        };
+
+        // Look for copy_type neighbor
+        if !neighborhood().any(|(i, j)| cells.get(i, j) == ct) {
+            return 0;
+        }
+
+        // Replace rt with ct in neighborhood, all of them unless capped
+        let mut converted = 0;
+        if self.max_conversions == 0 {
+            for (i, j) in neighborhood() {
+                if cells.get(i, j) == rt {
+                    converted += self.convert(cells, (x, y), (i, j), rt, ct);
+                }
+            }
+            return converted;
+        }
+
+        let targets: Vec<(usize, usize)> = neighborhood().filter(|&(i, j)| cells.get(i, j) == rt).collect();
+        for &(i, j) in targets.choose_multiple(rng, self.max_conversions) {
+            converted += self.convert(cells, (x, y), (i, j), rt, ct);
+        }
+        converted
+    }
+
+    // Have the particle at `by` turn the rt particle at `at` into ct, along
+    // with any rt particles on its symmetric images. Returns the number of
+    // cells changed.
+    fn convert<C: Cells>(&self, cells: &mut C, by: (usize, usize), at: (usize, usize), rt: u8, ct: u8) -> usize {
+        let size = cells.size();
+        let mut changed = 0;
+        let images = self.symmetry.images(by.0, by.1, size).zip(self.symmetry.images(at.0, at.1, size));
+        for (by, (gi, gj)) in images {
+            if cells.get(gi, gj) == rt && !self.is_pinned(gi, gj, size) {
+                cells.set(gi, gj, ct);
+                cells.converted(by, (gi, gj));
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    // Normalized affinity score a particle of type `p_type` would have at
+    // (i, j): summed interaction weights over the cells within `radius`,
+    // divided by the number of cells in that window
+    pub(crate) fn cell_score<C: Cells>(&self, cells: &C, p_type: u8, i: usize, j: usize) -> f32 {
+        self.window_score(cells, p_type, i as isize, j as isize)
+    }
+
+    // cell_score for a position that may lie just off the grid. With a
+    // crowding weight, the squared fraction of the window that is occupied
+    // is taken off times that weight whatever the types, so packed
+    // neighborhoods lose their pull and clusters keep some open structure.
+    fn window_score<C: Cells>(&self, cells: &C, p_type: u8, i: isize, j: isize) -> f32 {
+        let size = cells.size();
+        let mut score = 0.0f32;
+        let mut cell_count = 0i32;
+        let mut occupied = 0i32;
+
+        let shaped = self.anisotropy != [1.0, 1.0] || self.gains.radial != 1.0;
+        let pair_gains = !self.gains.pairs.is_empty();
+        let xs = self.boundary.axis(i, self.radius, size);
+        for (dy, yy) in self.boundary.axis(j, self.radius, size) {
+            for (dx, xx) in xs.clone() {
+                cell_count += 1;
+                let ct = cells.get(xx, yy);
+                if ct != 0 {
+                    occupied += 1;
+                    let mut w = self.interaction_weight(self.affinity[p_type as usize][ct as usize]);
+                    if pair_gains {
+                        w *= self.gains.pair(p_type, ct);
+                    }
+                    score += if shaped { w * self.kernel_weight(dx, dy) } else { w };
+                }
+            }
+        }
+
+        let cell_count = (cell_count as f32).max(1.0);
+        let mut score = score / cell_count + self.coupling_bonus(p_type, i, j, size);
+        if self.crowding != 0.0 {
+            let fill = occupied as f32 / cell_count;
+            score -= self.crowding * fill * fill;
+        }
+        score
+    }
+
+    // Weight of a neighbor at offset (dx, dy). Anisotropy gives wx along
+    // rows and wy along columns, blended by direction in between; the
+    // radial gain ramps linearly from 1 at the center to its value at the
+    // edge of the window.
+    #[inline]
+    fn kernel_weight(&self, dx: isize, dy: isize) -> f32 {
+        let (ax, ay) = (dx.unsigned_abs() as f32, dy.unsigned_abs() as f32);
+        if ax + ay == 0.0 {
+            return 1.0;
+        }
+        let [wx, wy] = self.anisotropy;
+        let direction = (wx * ax + wy * ay) / (ax + ay);
+        let reach = ax.max(ay) / (self.radius.max(1) as f32);
+        direction * (1.0 + (self.gains.radial - 1.0) * reach)
+    }
+
+    // Chance that a particle of `p_type` moves at random this update
+    #[inline]
+    fn noise_level(&self, p_type: u8) -> f32 {
+        match self.noise.get(p_type as usize) {
+            Some(&p) if p > 0.0 => (p * self.gains.noise).min(1.0),
+            _ => 0.0,
+        }
+    }
+
+    // Contribution of one neighbor with affinity `a`. Positive affinities
+    // attract with their magnitude; anything else repels with its magnitude
+    // (0 counts as -1, as it always has). Each side has its own scale.
+    #[inline]
+    pub(crate) fn interaction_weight(&self, a: i8) -> f32 {
+        if a > 0 {
+            a as f32 * self.attraction_scale * self.gains.attraction
+        } else {
+            -(a.unsigned_abs().max(1) as f32) * self.repulsion_scale * self.gains.repulsion
+        }
+    }
+
+    // Adjacent empty cell the particle at (x, y) moves to under the
+    // movement mode ((x, y) itself to stay), or None when the move is off
+    // the grid (Absorb only)
+    fn score_within_radius<C: Cells, R: Rng>(
+        &self,
+        cells: &C,
+        x: usize,
+        y: usize,
+        rng: &mut R,
+    ) -> Option<(usize, usize)> {
+        let size = cells.size();
+        let p_type = cells.get(x, y);
+        let mut best: f32 = -1_000_000.0;
+        let mut tiebreak: Vec<Option<(usize, usize)>> = vec![Some((x, y))];
+        // Every scored candidate, when choosing by softmax
+        let mut candidates = self.softmax.map(|_| Vec::with_capacity(9));
+
+        // A noisy particle ignores the scores and steps to any free
+        // neighbor (staying put only when boxed in)
+        let noise = self.noise_level(p_type);
+        let jitter = noise > 0.0 && rng.gen::<f32>() < noise;
+        if jitter {
+            tiebreak.clear();
+        } else if self.movement == MovementMode::Centroid {
+            return self.centroid_target(cells, x, y);
+        }
+
+        // Check adjacent empty cells
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let (target, norm) = match self.step_target(x, y, dx, dy, size) {
+                    Step::To(i, j) if cells.get(i, j) == 0 => {
+                        (Some((i, j)), (!jitter).then(|| self.cell_score(cells, p_type, i, j)))
+                    }
+                    Step::Off(i, j) => (None, (!jitter).then(|| self.window_score(cells, p_type, i, j))),
+                    _ => continue,
+                };
+                let Some(norm) = norm else {
+                    tiebreak.push(target);
+                    continue;
+                };
+                if let Some(candidates) = candidates.as_mut() {
+                    candidates.push((target, norm));
+                    continue;
+                }
+
+                if norm > best {
+                    best = norm;
+                    tiebreak.clear();
+                    tiebreak.push(target);
+                } else if (norm - best).abs() < f32::EPSILON {
+                    tiebreak.push(target);
+                }
+            }
+        }
+
+        // Staying scores like any other cell: the particle itself sits in
+        // both its own window and those of its neighbors. Under softmax,
+        // Threshold's stay option joins the draw carrying the margin.
+        if let (Some(mut candidates), Some(sharpness)) = (candidates.filter(|_| !jitter), self.softmax) {
+            if self.movement == MovementMode::Threshold {
+                candidates.push((Some((x, y)), self.cell_score(cells, p_type, x, y) + self.move_threshold));
+            }
+            return softmax_choice(&candidates, sharpness, rng).unwrap_or(Some((x, y)));
+        }
+        let stay = !jitter
+            && self.movement == MovementMode::Threshold
+            && best <= self.cell_score(cells, p_type, x, y) + self.move_threshold;
+        if stay || tiebreak.is_empty() {
+            Some((x, y))
+        } else {
+            *tiebreak.choose(rng).unwrap()
+        }
+    }
+
+    // Returns the number of cells changed (two per particle moved, more
+    // when the move is mirrored, one per particle absorbed)
+    fn move_particle<C: Cells, R: Rng>(&self, cells: &mut C, x: usize, y: usize, rng: &mut R) -> usize {
+        let p_type = cells.get(x, y);
+        if p_type == 0 || self.is_pinned(x, y, cells.size()) {
+            return 0;
+        }
+
+        let Some((bx, by)) = self.score_within_radius(cells, x, y, rng) else {
+            // Stepped off an absorbing edge, taking its images with it
+            let mut removed = 0;
+            for (ix, iy) in self.symmetry.images(x, y, cells.size()) {
+                if cells.get(ix, iy) == p_type {
+                    cells.set(ix, iy, 0);
+                    removed += 1;
+                }
+            }
+            return removed;
+        };
+        if bx == x && by == y {
+            return 0;
+        }
+
+        if self.symmetry != Symmetry::None {
+            return self.move_mirrored(cells, (x, y), (bx, by), p_type);
+        }
+
+        cells.set(bx, by, p_type);
+        cells.set(x, y, 0);
+        cells.carry(&[((x, y), (bx, by))]);
+        2
+    }
+}
+
+pub(crate) fn randomize_affinity<R: Rng>(affinity: &mut [Vec<i8>], rng: &mut R) {
+    for row in affinity.iter_mut() {
+        for a in row.iter_mut() {
+            *a = if rng.gen_bool(0.5) { 1 } else { -1 };
+        }
+    }
+}
+
+// Random copy type for `t`: any type other than itself
+pub(crate) fn pick_copy_type<R: Rng>(t: u8, num_types: usize, rng: &mut R) -> u8 {
+    let choices: Vec<u8> = (1..=num_types as u8)
+        .filter(|&c| c != t)
+        .collect();
+    // Falls back to the next type round, or to empty space with no types
+    choices
+        .choose(rng)
+        .copied()
+        .unwrap_or(if num_types == 0 { 0 } else { (t % num_types as u8) + 1 })
+}
+
+// Random replace type for `t`: neither itself nor its copy type
+pub(crate) fn pick_replace_type<R: Rng>(t: u8, copy: u8, num_types: usize, rng: &mut R) -> u8 {
+    let choices: Vec<u8> = (1..=num_types as u8)
+        .filter(|&c| c != t && c != copy)
+        .collect();
+    if choices.is_empty() {
+        if num_types >= 1 { 1 } else { 0 }
+    } else {
+        *choices.choose(rng).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Simulation, SimulationConfig};
+
+    // Three types on an empty 8x8 grid. Type 1 likes type 2 and dislikes
+    // everything else; type 1's reaction turns type 3 into type 2.
+    fn rules(radius: usize) -> Rules {
+        let mut affinity = vec![vec![-1i8; 4]; 4];
+        affinity[1][2] = 2;
+        Simulation::from_config(&SimulationConfig {
+            size: 8,
+            num_types: 3,
+            density: 0.0,
+            radius,
+            seed: Some(1),
+            affinity,
+            copy_types: vec![0, 2, 3, 1],
+            replace_types: vec![0, 3, 1, 2],
+            ..Default::default()
+        })
+        .rules
+    }
+
+    fn grid(particles: &[(usize, usize, u8)]) -> Vec<Vec<u8>> {
+        let mut grid = vec![vec![0u8; 8]; 8];
+        for &(x, y, t) in particles {
+            grid[x][y] = t;
+        }
+        grid
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn window_score_averages_the_window() {
+        let mut rules = rules(1);
+        let cells = grid(&[(3, 3, 1), (4, 3, 2), (2, 2, 3)]);
+        // Itself (-1), the type 2 (+2) and the type 3 (-1) over 9 cells
+        assert!(close(rules.cell_score(&cells, 1, 3, 3), 0.0));
+        assert!(close(rules.cell_score(&cells, 1, 4, 4), 1.0 / 9.0));
+        // Clamped windows only count the cells on the grid
+        let cells = grid(&[(0, 1, 2)]);
+        assert!(close(rules.cell_score(&cells, 1, 0, 0), 2.0 / 4.0));
+        // Repulsion has its own scale, and crowding takes off the squared fill
+        rules.repulsion_scale = 0.5;
+        rules.crowding = 1.0;
+        let cells = grid(&[(3, 3, 1), (4, 3, 2), (2, 2, 3)]);
+        let fill = 3.0 / 9.0;
+        assert!(close(rules.cell_score(&cells, 1, 3, 3), 1.0 / 9.0 - fill * fill));
+    }
+
+    #[test]
+    fn greedy_moves_take_the_best_cell() {
+        let rules = rules(1);
+        let mut rng = StdRng::seed_from_u64(2);
+        let cells = grid(&[(3, 3, 1), (5, 3, 2)]);
+        for _ in 0..20 {
+            let (x, _) = rules.score_within_radius(&cells, 3, 3, &mut rng).unwrap();
+            assert_eq!(x, 4);
+        }
+    }
+
+    #[test]
+    fn threshold_moves_need_a_margin() {
+        let mut rules = rules(1);
+        let mut rng = StdRng::seed_from_u64(3);
+        let cells = grid(&[(3, 3, 1), (5, 3, 2)]);
+        rules.movement = MovementMode::Threshold;
+        // The best cells beat staying by 2 / 9
+        rules.move_threshold = 0.2;
+        assert_ne!(rules.score_within_radius(&cells, 3, 3, &mut rng), Some((3, 3)));
+        rules.move_threshold = 0.25;
+        assert_eq!(rules.score_within_radius(&cells, 3, 3, &mut rng), Some((3, 3)));
+    }
+
+    #[test]
+    fn centroid_moves_head_for_attractive_neighbors() {
+        let mut rules = rules(3);
+        rules.movement = MovementMode::Centroid;
+        let mut rng = StdRng::seed_from_u64(4);
+        let cells = grid(&[(3, 3, 1), (6, 3, 2), (0, 3, 3)]);
+        assert_eq!(rules.score_within_radius(&cells, 3, 3, &mut rng), Some((4, 3)));
+        // Nothing attractive in reach
+        let cells = grid(&[(3, 3, 1), (0, 3, 3)]);
+        assert_eq!(rules.score_within_radius(&cells, 3, 3, &mut rng), Some((3, 3)));
+    }
+
+    #[test]
+    fn softmax_sharpness_sets_the_spread() {
+        let mut rules = rules(1);
+        let mut rng = StdRng::seed_from_u64(5);
+        let cells = grid(&[(3, 3, 1), (5, 3, 2)]);
+        let mut picks = |rules: &Rules| -> Vec<usize> {
+            (0..200).map(|_| rules.score_within_radius(&cells, 3, 3, &mut rng).unwrap().0).collect()
+        };
+        rules.softmax = Some(1000.0);
+        assert!(picks(&rules).iter().all(|&x| x == 4));
+        // Flat weights draw every free neighbor
+        rules.softmax = Some(0.0);
+        let xs = picks(&rules);
+        assert!(xs.contains(&2) && xs.contains(&3) && xs.contains(&4));
+    }
+
+    #[test]
+    fn reactions_convert_neighbors_of_a_copy_type() {
+        let mut rules = rules(1);
+        let mut rng = StdRng::seed_from_u64(6);
+        let particles = [(3, 3, 1), (4, 3, 2), (2, 3, 3), (3, 4, 3), (5, 5, 3)];
+        let mut cells = grid(&particles);
+        assert_eq!(rules.try_replace_particle(&mut cells, 3, 3, &mut rng), 2);
+        assert_eq!((cells[2][3], cells[3][4], cells[5][5]), (2, 2, 3));
+
+        // No copy type in reach, no reaction
+        let mut cells = grid(&[(3, 3, 1), (2, 3, 3)]);
+        assert_eq!(rules.try_replace_particle(&mut cells, 3, 3, &mut rng), 0);
+
+        rules.max_conversions = 1;
+        let mut cells = grid(&particles);
+        assert_eq!(rules.try_replace_particle(&mut cells, 3, 3, &mut rng), 1);
+        assert_eq!(cells.iter().flatten().filter(|&&t| t == 3).count(), 2);
+
+        rules.replace_radius = 2;
+        rules.max_conversions = 0;
+        let mut cells = grid(&particles);
+        assert_eq!(rules.try_replace_particle(&mut cells, 3, 3, &mut rng), 3);
+
+        rules.replace_probability = 0.0;
+        let mut cells = grid(&particles);
+        assert_eq!(rules.try_replace_particle(&mut cells, 3, 3, &mut rng), 0);
+    }
+}
@@ -0,0 +1,179 @@
+// Keyframed parameter schedules. Each parameter gets its own track of
+// (generation, value) keys; before every step the track is sampled at the
+// generation about to run, interpolating linearly between keys. A track
+// leaves its parameter alone before its first key and lets go of it again
+// after its last, so manual changes outside the scheduled span stick.
+
+use super::{Rules, Simulation, TypeChange};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Param {
+    Radius,
+    AttractionScale,
+    RepulsionScale,
+    ReplaceProbability,
+    AnisotropyX,
+    AnisotropyY,
+    Crowding,
+    UpdateFraction,
+    // Schedules softmax selection on as it takes effect
+    SoftmaxSharpness,
+    Noise(u8),
+    // Affinity of the first type towards the second
+    Affinity(u8, u8),
+}
+
+impl Param {
+    // Follow `change`; false if the parameter's type went
+    fn retype(&mut self, change: TypeChange) -> bool {
+        match self {
+            Param::Noise(t) => match change.remap(*t) {
+                Some(u) => *t = u,
+                None => return false,
+            },
+            Param::Affinity(from, to) => match (change.remap(*from), change.remap(*to)) {
+                (Some(f), Some(t)) => (*from, *to) = (f, t),
+                _ => return false,
+            },
+            _ => {}
+        }
+        true
+    }
+
+    // "radius", "attraction_scale", "repulsion_scale", "replace_probability",
+    // "anisotropy_x", "anisotropy_y", "crowding", "update_fraction",
+    // "softmax_sharpness", "noise:<type>" or "affinity:<from>:<to>"
+    pub(crate) fn parse(name: &str) -> Option<Param> {
+        if let Some(t) = name.strip_prefix("noise:") {
+            return t.parse().ok().map(Param::Noise);
+        }
+        Some(match name {
+            "radius" => Param::Radius,
+            "attraction_scale" => Param::AttractionScale,
+            "repulsion_scale" => Param::RepulsionScale,
+            "replace_probability" => Param::ReplaceProbability,
+            "anisotropy_x" => Param::AnisotropyX,
+            "anisotropy_y" => Param::AnisotropyY,
+            "crowding" => Param::Crowding,
+            "update_fraction" => Param::UpdateFraction,
+            "softmax_sharpness" => Param::SoftmaxSharpness,
+            _ => {
+                let mut parts = name.strip_prefix("affinity:")?.split(':');
+                let from = parts.next()?.parse().ok()?;
+                let to = parts.next()?.parse().ok()?;
+                if parts.next().is_some() {
+                    return None;
+                }
+                Param::Affinity(from, to)
+            }
+        })
+    }
+
+    // Set the parameter, rounding and clamping to what it can hold.
+    // Entries for types that no longer exist are skipped.
+    pub(crate) fn apply(self, rules: &mut Rules, value: f32) {
+        match self {
+            Param::Radius => rules.radius = value.round().max(0.0) as usize,
+            Param::AttractionScale => rules.attraction_scale = super::clamp_scale(value),
+            Param::RepulsionScale => rules.repulsion_scale = super::clamp_scale(value),
+            Param::ReplaceProbability => rules.replace_probability = value.clamp(0.0, 1.0),
+            Param::AnisotropyX => rules.anisotropy[0] = value.max(0.0),
+            Param::AnisotropyY => rules.anisotropy[1] = value.max(0.0),
+            Param::Crowding => rules.crowding = value.max(0.0),
+            Param::UpdateFraction => rules.update_fraction = super::clamp_update_fraction(value),
+            Param::SoftmaxSharpness => rules.softmax = Some(value.max(0.0)),
+            Param::Noise(t) => {
+                if let Some(p) = rules.noise.get_mut(t as usize).filter(|_| t != 0) {
+                    *p = value.clamp(0.0, 1.0);
+                }
+            }
+            Param::Affinity(from, to) => {
+                if let Some(a) = rules.affinity.get_mut(from as usize).and_then(|row| row.get_mut(to as usize)) {
+                    *a = value.round().clamp(i8::MIN as f32, i8::MAX as f32) as i8;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Track {
+    param: Param,
+    // Sorted by generation, at most one key per generation
+    keys: Vec<(u64, f32)>,
+}
+
+impl Track {
+    fn sample(&self, generation: u64) -> Option<f32> {
+        let first = self.keys.first()?;
+        let last = self.keys.last()?;
+        if generation < first.0 || generation > last.0 {
+            return None;
+        }
+        let next = self.keys.partition_point(|&(g, _)| g < generation);
+        let (g1, v1) = self.keys[next];
+        if g1 == generation {
+            return Some(v1);
+        }
+        let (g0, v0) = self.keys[next - 1];
+        let t = (generation - g0) as f32 / (g1 - g0) as f32;
+        Some(v0 + (v1 - v0) * t)
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct Schedule {
+    tracks: Vec<Track>,
+}
+
+impl Schedule {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    // Adding a key at a generation that already has one replaces it
+    pub(crate) fn add(&mut self, generation: u64, param: Param, value: f32) {
+        let track = match self.tracks.iter().position(|t| t.param == param) {
+            Some(i) => &mut self.tracks[i],
+            None => {
+                self.tracks.push(Track { param, keys: Vec::new() });
+                self.tracks.last_mut().unwrap()
+            }
+        };
+        match track.keys.binary_search_by_key(&generation, |&(g, _)| g) {
+            Ok(i) => track.keys[i].1 = value,
+            Err(i) => track.keys.insert(i, (generation, value)),
+        }
+    }
+
+    // Keyframes on a removed type's parameters go with it
+    pub(super) fn retype(&mut self, change: TypeChange) {
+        self.tracks.retain_mut(|track| track.param.retype(change));
+    }
+
+    pub(crate) fn apply(&self, rules: &mut Rules, generation: u64) {
+        for track in &self.tracks {
+            if let Some(value) = track.sample(generation) {
+                track.param.apply(rules, value);
+            }
+        }
+    }
+}
+
+impl Simulation {
+    // Schedule `param` (see Param::parse) to reach `value` at generation
+    // `step`. Returns false for an unknown parameter or a non-finite value.
+    pub fn add_keyframe(&mut self, step: u64, param: &str, value: f32) -> bool {
+        match Param::parse(param) {
+            Some(p) if value.is_finite() => {
+                self.schedule.add(step, p, value);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn clear_keyframes(&mut self) {
+        self.schedule = Schedule::default();
+    }
+}
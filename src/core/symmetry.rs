@@ -0,0 +1,148 @@
+// Mirror and rotational symmetry. With a symmetry mode set, every move,
+// conversion and edit is repeated on the symmetric images of the cells it
+// touched, so a symmetric grid stays symmetric as it evolves.
+
+use serde::{Deserialize, Serialize};
+
+use super::{Cells, Rules, Simulation};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Symmetry {
+    None = 0,
+    // Left/right mirror
+    Mirror = 1,
+    // Quarter turns about the center
+    Four = 2,
+    // Quarter turns plus the mirrors and diagonals (the full symmetry of
+    // the square)
+    Eight = 3,
+}
+
+impl Symmetry {
+    // Images of (x, y) under the symmetry group, starting with (x, y)
+    // itself. Images can repeat for cells on a mirror axis or at the center.
+    pub(crate) fn images(self, x: usize, y: usize, size: usize) -> impl Iterator<Item = (usize, usize)> {
+        let s = size - 1;
+        // The quarter turns first, then their mirror images
+        let all = [
+            (x, y),
+            (s - y, x),
+            (s - x, s - y),
+            (y, s - x),
+            (s - x, y),
+            (x, s - y),
+            (y, x),
+            (s - y, s - x),
+        ];
+        let picks: &[usize] = match self {
+            Symmetry::None => &[0],
+            Symmetry::Mirror => &[0, 4],
+            Symmetry::Four => &[0, 1, 2, 3],
+            Symmetry::Eight => &[0, 1, 2, 3, 4, 5, 6, 7],
+        };
+        picks.iter().map(move |&i| all[i])
+    }
+}
+
+impl Rules {
+    // Move the particle at `from` to `to` together with all its images.
+    // The move happens only if it can happen everywhere at once, otherwise
+    // images that share cells could undo each other, or merge or split
+    // particles; a pinned image holds them all. Returns the number of cells changed.
+    pub(crate) fn move_mirrored<C: Cells>(
+        &self,
+        cells: &mut C,
+        from: (usize, usize),
+        to: (usize, usize),
+        p_type: u8,
+    ) -> usize {
+        let size = cells.size();
+        let mut moves = [((0, 0), (0, 0)); 8];
+        let mut count = 0;
+        for pair in self.symmetry.images(from.0, from.1, size).zip(self.symmetry.images(to.0, to.1, size)) {
+            if !moves[..count].contains(&pair) {
+                moves[count] = pair;
+                count += 1;
+            }
+        }
+        let moves = &moves[..count];
+
+        let is_source = |cell: (usize, usize)| moves.iter().any(|&(s, _)| s == cell);
+        for (i, &(s, d)) in moves.iter().enumerate() {
+            let dest_free = cells.get(d.0, d.1) == 0 || is_source(d);
+            let shared = moves[..i].iter().any(|&(os, od)| os == s || od == d);
+            let pinned = self.is_pinned(s.0, s.1, size);
+            if cells.get(s.0, s.1) != p_type || !dest_free || shared || pinned {
+                return 0;
+            }
+        }
+
+        for &(s, _) in moves {
+            cells.set(s.0, s.1, 0);
+        }
+        for &(_, d) in moves {
+            cells.set(d.0, d.1, p_type);
+        }
+        cells.carry(moves);
+        // Cells that were both a source and a destination end up unchanged
+        moves.iter().filter(|&&(s, _)| !moves.iter().any(|&(_, d)| d == s)).count() * 2
+    }
+}
+
+impl Simulation {
+    pub fn symmetry(&self) -> Symmetry {
+        self.rules.symmetry
+    }
+}
+
+// Give every cell the value of the smallest (x, y) in its orbit
+pub(crate) fn symmetrize(grid: &mut [Vec<u8>], symmetry: Symmetry) {
+    if symmetry == Symmetry::None {
+        return;
+    }
+    let size = grid.len();
+    for x in 0..size {
+        for y in 0..size {
+            let canonical = symmetry.images(x, y, size).min().unwrap_or((x, y));
+            grid[x][y] = grid[canonical.0][canonical.1];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::SimulationConfig;
+
+    #[test]
+    fn four_turns_about_the_center() {
+        let images: Vec<_> = Symmetry::Four.images(1, 2, 8).collect();
+        assert_eq!(images, vec![(1, 2), (5, 1), (6, 5), (2, 6)]);
+        // No mirror images
+        assert!(!images.contains(&(6, 2)) && !images.contains(&(1, 5)));
+        let mut orbit: Vec<_> = Symmetry::Eight.images(1, 2, 8).collect();
+        orbit.sort();
+        assert_eq!(orbit, vec![(1, 2), (1, 5), (2, 1), (2, 6), (5, 1), (5, 6), (6, 2), (6, 5)]);
+    }
+
+    #[test]
+    fn symmetric_seeds_stay_symmetric() {
+        for symmetry in [Symmetry::Mirror, Symmetry::Four, Symmetry::Eight] {
+            for size in [16, 17] {
+                let mut sim = Simulation::from_config(&SimulationConfig {
+                    size,
+                    num_types: 4,
+                    seed: Some(3),
+                    symmetry,
+                    ..Default::default()
+                });
+                for _ in 0..50 {
+                    sim.step();
+                }
+                let mut mirrored = sim.type_grid.clone();
+                symmetrize(&mut mirrored, symmetry);
+                assert!(mirrored == sim.type_grid, "{:?} grid of size {} lost its symmetry", symmetry, size);
+            }
+        }
+    }
+}
@@ -0,0 +1,72 @@
+// Terrain: a per-cell speed that scales how often a particle standing on
+// that cell gets updated. 1 is ordinary ground, below 1 is mud (0 pins
+// particles in place) and above 1 is ice. The step draws `max` times its
+// usual number of updates and each one goes ahead with probability
+// speed / max, so ordinary cells keep their old rate.
+
+use rand::Rng;
+
+use super::{Rules, Simulation};
+
+// Fastest speed a cell can have
+pub(crate) const MAX_SPEED: f32 = 8.0;
+
+#[derive(Clone, Default)]
+pub(crate) struct Terrain {
+    // Indexed [x * size + y]; empty when the ground is flat
+    pub(crate) speed: Vec<f32>,
+    // Largest entry of `speed`
+    pub(crate) max: f32,
+}
+
+impl Rules {
+    // Whether a drawn update of the particle at (x, y) goes ahead. Only
+    // draws from `rng` when terrain is set.
+    #[inline]
+    pub(crate) fn on_the_move<R: Rng>(&self, x: usize, y: usize, size: usize, rng: &mut R) -> bool {
+        let terrain = &self.terrain;
+        if terrain.speed.len() != size * size {
+            return true;
+        }
+        let speed = terrain.speed[x * size + y];
+        speed >= terrain.max || rng.gen::<f32>() * terrain.max < speed
+    }
+}
+
+impl Simulation {
+    // Speeds in export_grid's layout (row-major); negative and non-finite
+    // entries count as 0 and speeds are capped at MAX_SPEED. Returns false,
+    // leaving the terrain as it was, unless there is one entry per cell.
+    pub fn set_terrain(&mut self, speeds: &[f32]) -> bool {
+        let n = self.size;
+        if speeds.len() != n * n {
+            return false;
+        }
+        let mut speed = vec![0.0; n * n];
+        for (i, &s) in speeds.iter().enumerate() {
+            speed[(i % n) * n + i / n] = if s.is_finite() { s.clamp(0.0, MAX_SPEED) } else { 0.0 };
+        }
+        let max = speed.iter().copied().fold(0.0, f32::max);
+        self.rules.terrain = Terrain { speed, max };
+        true
+    }
+
+    // Speeds in export_grid's layout; empty when the ground is flat
+    pub(crate) fn terrain_speeds(&self) -> Vec<f32> {
+        let n = self.size;
+        let speed = &self.rules.terrain.speed;
+        if speed.len() != n * n {
+            return Vec::new();
+        }
+        (0..n * n).map(|i| speed[(i % n) * n + i / n]).collect()
+    }
+
+    pub fn clear_terrain(&mut self) {
+        self.rules.terrain = Terrain::default();
+    }
+
+    // Factor the number of updates per step is scaled by
+    pub(crate) fn terrain_boost(&self) -> f32 {
+        if self.rules.terrain.speed.is_empty() { 1.0 } else { self.rules.terrain.max }
+    }
+}
@@ -0,0 +1,405 @@
+// Tiled stepping: the update scheme shared by the rayon worker pool and
+// GpuStepper (gpu/tiled.wgsl), so a tiled run gives the same grid on one
+// thread, many threads or the GPU.
+//
+// The grid is cut into square tiles and updated in four passes, one per
+// (x parity, y parity) class, so that tiles running concurrently are always
+// separated by a full tile. An update only reads and writes within a fixed
+// reach of the particle (read_reach / write_reach below), so with tiles
+// at least the sum of the two wide no two concurrent tiles touch the same
+// cell. Within its tile an update picks random cells, skipping empty ones,
+// and changes them in place. The CPU works on a copy of the cells a tile
+// can see and writes back the part it may have changed, which comes to the
+// same thing since nothing else touches those cells during the pass.
+//
+// Every tile of every step draws from its own Philox stream: the key is
+// drawn once when tiling is switched on and the counter holds the tile's
+// number and the step (see philox.rs). Nothing depends on the thread count,
+// the order tiles finish in, or the platform's word size. Whether a build
+// has the `parallel` feature only decides whether tiles of a pass run
+// concurrently.
+
+use rand::Rng;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use super::{BoundaryMode, Cells, MovementMode, Region, Rules, Simulation, StepStats, Symmetry};
+use crate::philox::Philox;
+
+// Tile side used unless asked for another, by the CPU and GpuStepper alike
+pub const DEFAULT_TILE_SIZE: usize = 32;
+
+// Tile size and the key of the tile streams, while tiled stepping is on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Tiling {
+    pub(crate) tile: usize,
+    pub(crate) key: u64,
+}
+
+impl Rules {
+    // How far from a particle an update can read and write
+    fn read_reach(&self) -> usize {
+        (self.radius + 1).max(self.replace_radius)
+    }
+
+    fn write_reach(&self) -> usize {
+        self.replace_radius.max(1)
+    }
+
+    // Smallest tile two updates a tile apart can't collide in
+    pub(crate) fn min_tile_size(&self) -> usize {
+        self.read_reach() + self.write_reach()
+    }
+}
+
+impl Region {
+    fn intersect(&self, other: &Region) -> Option<Region> {
+        let r = Region {
+            x0: self.x0.max(other.x0),
+            y0: self.y0.max(other.y0),
+            x1: self.x1.min(other.x1),
+            y1: self.y1.min(other.y1),
+        };
+        (r.x0 <= r.x1 && r.y0 <= r.y1).then_some(r)
+    }
+}
+
+// Mirrored updates write far from the particle, and wrapped ones reach
+// across the grid, so symmetric and wrapping runs always step sequentially
+pub(crate) fn supported(rules: &Rules) -> bool {
+    rules.symmetry == Symmetry::None && rules.boundary != BoundaryMode::Wrap
+}
+
+// A rectangle of cells copied out of the grid, addressed in grid coordinates
+struct Tile {
+    size: usize,
+    x0: usize,
+    y0: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl Tile {
+    fn copy_from(grid: &[Vec<u8>], x0: usize, x1: usize, y0: usize, y1: usize) -> Tile {
+        let height = y1 - y0 + 1;
+        let mut data = Vec::with_capacity((x1 - x0 + 1) * height);
+        for column in &grid[x0..=x1] {
+            data.extend_from_slice(&column[y0..=y1]);
+        }
+        Tile {
+            size: grid.len(),
+            x0,
+            y0,
+            height,
+            data,
+        }
+    }
+}
+
+impl Cells for Tile {
+    #[inline]
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    // A row past the tile would land in the next column, so check that
+    // while testing; anything else off the tile is out of bounds anyway
+    #[inline]
+    fn get(&self, x: usize, y: usize) -> u8 {
+        debug_assert!(y - self.y0 < self.height, "read of ({}, {}) is off the tile", x, y);
+        self.data[(x - self.x0) * self.height + (y - self.y0)]
+    }
+
+    #[inline]
+    fn set(&mut self, x: usize, y: usize, t: u8) {
+        debug_assert!(y - self.y0 < self.height, "write of ({}, {}) is off the tile", x, y);
+        self.data[(x - self.x0) * self.height + (y - self.y0)] = t;
+    }
+}
+
+// Number of the stream tile (tx, ty) draws from in step `step`: the upper
+// half of the Philox counter is (tile, step), as in tiled.wgsl
+fn stream(tx: usize, ty: usize, tiles_per_side: usize, step: u64) -> u64 {
+    (ty * tiles_per_side + tx) as u64 | (step as u32 as u64) << 32
+}
+
+// One step over the tiles clipped to `region`, so only particles inside it
+// are updated. `rate` is the number of update attempts per tile cell.
+pub(crate) fn step_tiled(
+    grid: &mut [Vec<u8>],
+    rules: &Rules,
+    region: Region,
+    rate: f32,
+    tiling: Tiling,
+    step: u64,
+) -> StepStats {
+    let size = grid.len();
+    let tile = tiling.tile.max(rules.min_tile_size());
+    let tiles_per_side = size.div_ceil(tile);
+    let halo = rules.read_reach();
+    let margin = rules.write_reach();
+
+    let mut stats = StepStats::default();
+
+    for phase in 0..4 {
+        trace_span!("tile_phase");
+        let jobs: Vec<(Region, u64)> = (0..tiles_per_side)
+            .flat_map(|tx| (0..tiles_per_side).map(move |ty| (tx, ty)))
+            .filter(|&(tx, ty)| (tx % 2) + 2 * (ty % 2) == phase)
+            .filter_map(|(tx, ty)| {
+                let bounds = Region {
+                    x0: tx * tile,
+                    y0: ty * tile,
+                    x1: ((tx + 1) * tile).min(size) - 1,
+                    y1: ((ty + 1) * tile).min(size) - 1,
+                };
+                let stream = stream(tx, ty, tiles_per_side, step);
+                bounds.intersect(&region).map(|bounds| (bounds, stream))
+            })
+            .collect();
+
+        let run = |(bounds, stream): (Region, u64)| {
+            let mut cells = Tile::copy_from(
+                grid,
+                bounds.x0.saturating_sub(halo),
+                (bounds.x1 + halo).min(size - 1),
+                bounds.y0.saturating_sub(halo),
+                (bounds.y1 + halo).min(size - 1),
+            );
+            let done = rules.update_tile(&mut cells, bounds, rate, &mut Philox::new(tiling.key, stream));
+            (bounds, cells, done)
+        };
+        #[cfg(feature = "parallel")]
+        let results: Vec<(Region, Tile, StepStats)> = jobs.into_par_iter().map(run).collect();
+        #[cfg(not(feature = "parallel"))]
+        let results: Vec<(Region, Tile, StepStats)> = jobs.into_iter().map(run).collect();
+
+        // Write back everything each tile may have touched
+        for (bounds, cells, done) in results {
+            stats.updates += done.updates;
+            stats.changed_cells += done.changed_cells;
+            let (wx0, wx1) = (bounds.x0.saturating_sub(margin), (bounds.x1 + margin).min(size - 1));
+            let (wy0, wy1) = (bounds.y0.saturating_sub(margin), (bounds.y1 + margin).min(size - 1));
+            for (x, column) in (wx0..=wx1).zip(&mut grid[wx0..=wx1]) {
+                let start = (x - cells.x0) * cells.height + (wy0 - cells.y0);
+                column[wy0..=wy1].copy_from_slice(&cells.data[start..=start + (wy1 - wy0)]);
+            }
+        }
+    }
+
+    stats
+}
+
+impl Simulation {
+    // Step in tiles of `tile_size` cells (raised to the minimum the rules
+    // need) from now on. The tile streams' key is drawn from the grid's
+    // generator, so a run built from the same config and seed tiles the
+    // same way. Symmetric, wrapping and energy or id carrying grids keep
+    // stepping sequentially.
+    pub fn enable_tiling(&mut self, tile_size: usize) {
+        let key = match self.tiling {
+            Some(tiling) => tiling.key,
+            None => self.rng.gen(),
+        };
+        self.tiling = Some(Tiling { tile: tile_size.max(1), key });
+    }
+
+    pub fn disable_tiling(&mut self) {
+        self.tiling = None;
+    }
+
+    // Requested tile size, None when stepping sequentially
+    pub fn tile_size(&self) -> Option<usize> {
+        self.tiling.map(|tiling| tiling.tile)
+    }
+
+    // Key of the tile streams, for handing to GpuStepper
+    pub fn tile_key(&self) -> Option<u64> {
+        self.tiling.map(|tiling| tiling.key)
+    }
+
+    // GpuStepper's shader (gpu/tiled.wgsl) covers the affinity score, the
+    // greedy move and uncapped reactions on a clamped grid. Names the first
+    // rule in force beyond those, which the GPU would silently step wrong.
+    pub fn gpu_tiled_support(&self) -> Result<(), String> {
+        let rules = &self.rules;
+        let gains = &rules.gains;
+        let modulated = gains.attraction != 1.0 || gains.repulsion != 1.0 || gains.radial != 1.0;
+        let unsupported = [
+            (rules.boundary != BoundaryMode::Clamp, "boundary modes other than Clamp"),
+            (rules.symmetry != Symmetry::None, "symmetry"),
+            (rules.movement != MovementMode::Greedy, "movement modes other than Greedy"),
+            (rules.softmax.is_some(), "softmax moves"),
+            (rules.noise.iter().any(|&p| p > 0.0), "type noise"),
+            (rules.max_conversions != 0, "capped conversions"),
+            (rules.anisotropy != [1.0, 1.0], "anisotropy"),
+            (rules.crowding != 0.0, "crowding"),
+            (modulated || !gains.pairs.is_empty(), "modulated gains"),
+            (self.is_coupled(), "coupling"),
+            (!self.terrain_speeds().is_empty(), "terrain"),
+            (!self.pinned_cells().is_empty(), "pins"),
+            (self.energy.is_some(), "energy"),
+            (self.ids.is_some(), "particle ids"),
+        ];
+        match unsupported.iter().find(|&&(used, _)| used) {
+            Some((_, rule)) => Err(format!("the GPU stepper doesn't implement {}", rule)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::SimulationConfig;
+
+    fn tiled(seed: u64) -> Simulation {
+        Simulation::from_config(&SimulationConfig {
+            size: 96,
+            num_types: 4,
+            density: 0.3,
+            radius: 2,
+            seed: Some(seed),
+            tile_size: Some(8),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn tiled_runs_replay() {
+        let (mut a, mut b) = (tiled(5), tiled(5));
+        for _ in 0..5 {
+            a.step();
+            b.step();
+        }
+        assert_eq!(a.state_hash(), b.state_hash());
+        assert_eq!(a.tile_size(), Some(8));
+        assert_ne!(a.state_hash(), tiled(5).state_hash());
+    }
+
+    fn counts(sim: &Simulation) -> Vec<usize> {
+        let mut counts = vec![0; sim.num_types() + 1];
+        for &t in sim.type_grid.iter().flatten() {
+            counts[t as usize] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn min_tile_keeps_concurrent_tiles_apart() {
+        let reach = |r: &Region, by: usize| Region {
+            x0: r.x0.saturating_sub(by),
+            y0: r.y0.saturating_sub(by),
+            x1: r.x1 + by,
+            y1: r.y1 + by,
+        };
+        let tile = |tx: usize, ty: usize, side: usize| Region {
+            x0: tx * side,
+            y0: ty * side,
+            x1: (tx + 1) * side - 1,
+            y1: (ty + 1) * side - 1,
+        };
+        for radius in 0..5 {
+            for replace_radius in 0..5 {
+                let rules = Rules { radius, replace_radius, ..tiled(1).rules };
+                let side = rules.min_tile_size();
+                // Neighbors in a pass sit two tiles along, straight or diagonal
+                for (tx, ty) in [(2, 0), (0, 2), (2, 2)] {
+                    let writes = reach(&tile(0, 0, side), rules.write_reach());
+                    let reads = reach(&tile(tx, ty, side), rules.read_reach());
+                    assert_eq!(writes.intersect(&reads), None, "radius {} reaction {}", radius, replace_radius);
+                    // One cell less and they collide
+                    let writes = reach(&tile(0, 0, side - 1), rules.write_reach());
+                    let reads = reach(&tile(tx, ty, side - 1), rules.read_reach());
+                    assert!(writes.intersect(&reads).is_some());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn gpu_support_names_missing_rules() {
+        let mut sim = tiled(2);
+        assert_eq!(sim.gpu_tiled_support(), Ok(()));
+        sim.set_boundary_mode(BoundaryMode::Reflect);
+        assert!(sim.gpu_tiled_support().unwrap_err().contains("boundary"));
+        sim.set_boundary_mode(BoundaryMode::Clamp);
+        let (x, y) = (0..96 * 96).map(|i| (i / 96, i % 96)).find(|&(x, y)| sim.cell(x, y) != 0).unwrap();
+        sim.pin_cell(x, y);
+        assert!(sim.gpu_tiled_support().unwrap_err().contains("pins"));
+        sim.clear_pins();
+        sim.rules.max_conversions = 2;
+        assert!(sim.gpu_tiled_support().is_err());
+    }
+
+    #[test]
+    fn tiles_write_only_within_write_reach() {
+        let sim = tiled(3);
+        let size = sim.size();
+        let modes = [BoundaryMode::Clamp, BoundaryMode::Reflect, BoundaryMode::Absorb];
+        let movements = [MovementMode::Greedy, MovementMode::Threshold, MovementMode::Centroid];
+        for (radius, replace_radius) in [(1, 0), (2, 1), (1, 3), (3, 2)] {
+            for (boundary, movement) in modes.into_iter().zip(movements) {
+                let rules = Rules { radius, replace_radius, boundary, movement, ..sim.rules.clone() };
+                let (halo, margin) = (rules.read_reach(), rules.write_reach());
+                // Tiles in a corner, on an edge and inside
+                for (x0, y0) in [(0, 0), (40, 0), (40, 40), (88, 60)] {
+                    let bounds = Region { x0, y0, x1: (x0 + 7).min(size - 1), y1: (y0 + 7).min(size - 1) };
+                    let (cx0, cy0) = (x0.saturating_sub(halo), y0.saturating_sub(halo));
+                    let (cx1, cy1) = ((bounds.x1 + halo).min(size - 1), (bounds.y1 + halo).min(size - 1));
+                    let mut cells = Tile::copy_from(&sim.type_grid, cx0, cx1, cy0, cy1);
+                    rules.update_tile(&mut cells, bounds, 4.0, &mut Philox::new(1, (x0 * size + y0) as u64));
+                    for x in cx0..=cx1 {
+                        for y in cy0..=cy1 {
+                            if cells.get(x, y) == sim.type_grid[x][y] {
+                                continue;
+                            }
+                            let near = x + margin >= x0 && x <= bounds.x1 + margin;
+                            let near = near && y + margin >= y0 && y <= bounds.y1 + margin;
+                            assert!(near, "radius {} reaction {} wrote ({}, {})", radius, replace_radius, x, y);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tiled_steps_conserve_counts() {
+        let mut sim = tiled(4);
+        let before = counts(&sim);
+        // Reactions only trade one type for another
+        for _ in 0..10 {
+            sim.step();
+        }
+        let after = counts(&sim);
+        assert_eq!(after[1..].iter().sum::<usize>(), before[1..].iter().sum::<usize>());
+
+        // Without them every type keeps its count
+        let mut sim = tiled(4);
+        sim.rules.replace_type = sim.rules.copy_type.clone();
+        sim.set_boundary_mode(BoundaryMode::Reflect);
+        for _ in 0..10 {
+            assert!(sim.step().updates > 0);
+            assert_eq!(counts(&sim), before);
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn thread_count_does_not_change_the_run() {
+        let run = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            pool.install(|| {
+                let mut sim = tiled(9);
+                for _ in 0..10 {
+                    sim.step();
+                }
+                sim.state_hash()
+            })
+        };
+        let one = run(1);
+        assert_eq!(run(2), one);
+        assert_eq!(run(7), one);
+    }
+}
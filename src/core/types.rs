@@ -0,0 +1,121 @@
+// Keeping the per-type tables in step when a type is added or removed.
+// Tables indexed by type (coupling weights, energy income, density
+// targets) and tables keyed by type (modulated and scheduled per-type
+// parameters) all read a missing entry as "unset", so a new type starts
+// with no entries and a removed type's entries go while later types
+// shift down one.
+
+use super::Simulation;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TypeChange {
+    Added(u8),
+    Removed(u8),
+}
+
+impl TypeChange {
+    // New index of an entry for type `u`, None if the entry goes
+    pub(crate) fn remap(self, u: u8) -> Option<u8> {
+        match self {
+            TypeChange::Added(t) | TypeChange::Removed(t) if u == t => None,
+            TypeChange::Removed(t) if u > t => Some(u - 1),
+            _ => Some(u),
+        }
+    }
+
+    // Update a table indexed by type
+    pub(crate) fn apply<T: Default>(self, table: &mut Vec<T>) {
+        match self {
+            TypeChange::Added(t) => {
+                if let Some(entry) = table.get_mut(t as usize) {
+                    *entry = T::default();
+                }
+            }
+            TypeChange::Removed(t) => {
+                if (t as usize) < table.len() {
+                    table.remove(t as usize);
+                }
+            }
+        }
+    }
+}
+
+impl Simulation {
+    // Follow `change` in every per-type table outside the rule core
+    // (affinity, reactions, noise and starting densities are the caller's)
+    pub(crate) fn retype_tables(&mut self, change: TypeChange) {
+        self.rules.coupling.retype(change);
+        self.rules.gains.retype(change);
+        self.modulation.retype(change);
+        self.schedule.retype(change);
+        self.autopilot.retype(change);
+        if let Some(energy) = &mut self.energy {
+            change.apply(&mut energy.config.income);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SimulationConfig;
+    use crate::core::EnergyConfig;
+    use crate::ParticleGrid;
+
+    fn grid() -> ParticleGrid {
+        let mut grid = ParticleGrid::from_config(&SimulationConfig {
+            size: 8,
+            num_types: 3,
+            seed: Some(2),
+            ..Default::default()
+        });
+        grid.set_energy(Some(EnergyConfig { income: vec![0.0, 0.1, 0.2, 0.3], ..Default::default() }));
+        grid.set_coupling(3, 0.5);
+        grid.set_target_density(3, 0.25);
+        grid.set_modulation("affinity:3:1", 1.0);
+        grid.set_modulation("affinity:2:1", 1.0);
+        grid.add_keyframe(1.0, "noise:3", 0.75);
+        grid.add_keyframe(1.0, "noise:2", 0.5);
+        // Modulation reaches the gains on the first step; the keyframes
+        // land on the second
+        grid.step();
+        grid
+    }
+
+    #[test]
+    fn remap_shifts_later_types() {
+        let change = TypeChange::Removed(2);
+        assert_eq!([1, 2, 3].map(|u| change.remap(u)), [Some(1), None, Some(2)]);
+        assert_eq!(TypeChange::Added(4).remap(4), None);
+        assert_eq!(TypeChange::Added(4).remap(3), Some(3));
+    }
+
+    #[test]
+    fn removed_type_takes_its_entries() {
+        let mut grid = grid();
+        assert!(grid.remove_type(2, 0));
+        assert_eq!(grid.energy_config().unwrap().income, vec![0.0, 0.1, 0.3]);
+        assert_eq!(grid.target_density(2), Some(0.25));
+        assert!(grid.is_coupled());
+        assert_eq!(grid.rules.gains.pairs.iter().map(|p| (p.0, p.1)).collect::<Vec<_>>(), vec![(2, 1)]);
+        grid.step();
+        assert_eq!(grid.rules.noise[2], 0.75);
+        grid.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn added_type_starts_unset() {
+        let mut grid = grid();
+        grid.remove_type(3, 0);
+        assert!(!grid.is_coupled());
+        // Entries past the last type must not land on a new one
+        grid.energy.as_mut().unwrap().config.income.push(9.0);
+        grid.add_keyframe(1.0, "noise:3", 0.75);
+        let t = grid.add_type(Vec::new(), Vec::new(), Vec::new());
+        assert_eq!(t, 3);
+        assert_eq!(grid.energy_config().unwrap().income[3], 0.0);
+        assert_eq!(grid.target_density(3), None);
+        grid.step();
+        assert_eq!(grid.rules.noise[3], 0.0);
+    }
+}
@@ -0,0 +1,109 @@
+// Two grids stepped side by side, each scoring cells partly by the other's
+// local particle density. With prey fleeing predators (negative coupling)
+// and predators chasing prey (positive), this gives the travelling
+// predator/prey waves that one shared grid can't express cleanly.
+
+use wasm_bindgen::prelude::*;
+
+use crate::ParticleGrid;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GridSide {
+    A = 0,
+    B = 1,
+}
+
+#[wasm_bindgen]
+pub struct CoupledGrids {
+    a: ParticleGrid,
+    b: ParticleGrid,
+}
+
+#[wasm_bindgen]
+impl CoupledGrids {
+    // Couple two grids of the same size. Both start with zero coupling.
+    #[wasm_bindgen(constructor)]
+    pub fn new(a: ParticleGrid, b: ParticleGrid) -> Result<CoupledGrids, String> {
+        if a.size != b.size {
+            return Err(format!("grid sizes differ: {} and {}", a.size, b.size));
+        }
+        Ok(CoupledGrids { a, b })
+    }
+
+    // Step A against B's current density, then B against A's new one
+    #[wasm_bindgen]
+    pub fn step(&mut self) {
+        Self::couple(&mut self.a, &self.b);
+        self.a.step();
+        Self::couple(&mut self.b, &self.a);
+        self.b.step();
+    }
+
+    // How strongly particles of `p_type` on `side` are drawn to (positive)
+    // or driven from (negative) the other grid's particles
+    #[wasm_bindgen]
+    pub fn set_coupling(&mut self, side: GridSide, p_type: u8, weight: f32) {
+        self.grid_mut(side).sim.set_coupling(p_type, weight);
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn size(&self) -> usize {
+        self.a.size
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn generation(&self) -> f64 {
+        self.a.generation as f64
+    }
+
+    #[wasm_bindgen]
+    pub fn export_grid(&self, side: GridSide) -> Vec<u8> {
+        self.grid(side).export_grid()
+    }
+
+    #[wasm_bindgen]
+    pub fn export_region_rgba(&self, side: GridSide, x0: usize, y0: usize, w: usize, h: usize) -> Vec<u8> {
+        self.grid(side).export_region_rgba(x0, y0, w, h)
+    }
+
+    #[wasm_bindgen]
+    pub fn debug_info(&self, side: GridSide) -> String {
+        self.grid(side).debug_info()
+    }
+
+    // Hand one grid back (its coupling field is dropped), consuming the pair
+    #[wasm_bindgen]
+    pub fn take(self, side: GridSide) -> ParticleGrid {
+        let mut grid = match side {
+            GridSide::A => self.a,
+            GridSide::B => self.b,
+        };
+        grid.sim.set_coupling_field(Vec::new());
+        grid
+    }
+}
+
+impl CoupledGrids {
+    fn grid(&self, side: GridSide) -> &ParticleGrid {
+        match side {
+            GridSide::A => &self.a,
+            GridSide::B => &self.b,
+        }
+    }
+
+    fn grid_mut(&mut self, side: GridSide) -> &mut ParticleGrid {
+        match side {
+            GridSide::A => &mut self.a,
+            GridSide::B => &mut self.b,
+        }
+    }
+
+    // Sample `partner`'s density over `grid`'s scoring radius
+    fn couple(grid: &mut ParticleGrid, partner: &ParticleGrid) {
+        if grid.sim.is_coupled() {
+            let field = partner.sim.occupancy_field(grid.rules.radius);
+            grid.sim.set_coupling_field(field);
+        }
+    }
+}
@@ -0,0 +1,443 @@
+// Grid editing primitives for selection tools. Each call reads the source
+// rectangle up front and then writes, so overlapping source and destination
+// behave as if the selection were lifted off the grid and dropped in place.
+//
+// Drawing tools batch their cell writes, strokes and fills into a list of
+// Edits and hand it to apply_edits in one call between steps, so a stroke
+// is never seen half drawn. Every edit call leaves one entry on the undo
+// stack.
+
+use std::collections::HashSet;
+use std::mem;
+
+use wasm_bindgen::prelude::*;
+
+use crate::core::Region;
+use crate::selection::flood_fill;
+use crate::ParticleGrid;
+
+// Undo entries kept; older ones are dropped
+const MAX_UNDO: usize = 64;
+
+// Cells written during an edit call, each with the type it held before its
+// first write, so undo and mirroring only look at what the edit touched
+#[derive(Default)]
+pub(crate) struct Journal {
+    cells: Vec<(usize, usize, u8)>,
+    seen: HashSet<(usize, usize)>,
+}
+
+impl Journal {
+    fn record(&mut self, x: usize, y: usize, old: u8) {
+        if self.seen.insert((x, y)) {
+            self.cells.push((x, y, old));
+        }
+    }
+}
+
+// What to do when a particle lands on an occupied cell
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    // The incoming particle replaces whatever was there
+    Overwrite = 0,
+    // The occupied cell wins; a moved particle stays where it was
+    Skip = 1,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Shape {
+    Cell(usize, usize),
+    // Polyline through the points with the given brush radius
+    Stroke(Vec<(f32, f32)>, f32),
+    Rect(Region),
+    // The 8-connected same-type region containing the cell
+    Fill(usize, usize),
+}
+
+// One drawing operation for apply_edits. Type 0 erases.
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edit {
+    shape: Shape,
+    t: u8,
+}
+
+#[wasm_bindgen]
+impl Edit {
+    #[wasm_bindgen]
+    pub fn cell(x: usize, y: usize, t: u8) -> Edit {
+        Edit { shape: Shape::Cell(x, y), t }
+    }
+
+    // Brush stroke through flat [x, y, x, y, ...] points in cell units,
+    // covering every cell whose center is within `radius` of the line (a
+    // radius of 0 draws a one-cell-wide line, a single point a dot)
+    #[wasm_bindgen]
+    pub fn stroke(points: Vec<f32>, radius: f32, t: u8) -> Edit {
+        let points = points.chunks_exact(2).map(|p| (p[0], p[1])).filter(|p| p.0.is_finite() && p.1.is_finite());
+        let radius = if radius.is_finite() { radius.max(0.0) } else { 0.0 };
+        Edit { shape: Shape::Stroke(points.collect(), radius), t }
+    }
+
+    // Rectangle, corners inclusive and in either order
+    #[wasm_bindgen]
+    pub fn rect(x0: usize, y0: usize, x1: usize, y1: usize, t: u8) -> Edit {
+        Edit { shape: Shape::Rect(Region { x0, y0, x1, y1 }), t }
+    }
+
+    // Paint-bucket fill of the same-type region containing (x, y)
+    #[wasm_bindgen]
+    pub fn fill(x: usize, y: usize, t: u8) -> Edit {
+        Edit { shape: Shape::Fill(x, y), t }
+    }
+}
+
+#[wasm_bindgen]
+impl ParticleGrid {
+    // Apply the edits in order as a single operation with one undo entry.
+    // Edits with an unknown type or entirely off the grid are skipped.
+    // Returns how many cells changed.
+    #[wasm_bindgen]
+    pub fn apply_edits(&mut self, edits: Vec<Edit>) -> usize {
+        let ((), changed) = self.undoable(|grid| {
+            grid.mirrored_edit(|grid| {
+                for edit in &edits {
+                    grid.apply_edit(edit);
+                }
+            })
+        });
+        self.refresh_output();
+        changed
+    }
+
+    // Revert the most recent edit call, restoring the cells it changed to
+    // what they held before it (particles that moved there since are
+    // overwritten). Returns false if there is nothing to undo.
+    #[wasm_bindgen]
+    pub fn undo_edit(&mut self) -> bool {
+        let Some(entry) = self.undo.pop() else {
+            return false;
+        };
+        // Skip cells that no longer fit the grid or its types
+        let (size, num_types) = (self.size, self.num_types);
+        let fits = |&(x, y, t): &(usize, usize, u8)| x < size && y < size && t as usize <= num_types;
+        let entry: Vec<_> = entry.into_iter().filter(fits).collect();
+        for &(x, y, t) in &entry {
+            self.sim.type_grid[x][y] = t;
+        }
+        self.record_sync_edits(entry.iter().map(|&(x, y, _)| (x, y)));
+        self.refresh_output();
+        true
+    }
+
+    // Number of edit calls that can be undone
+    #[wasm_bindgen(getter)]
+    pub fn undo_depth(&self) -> usize {
+        self.undo.len()
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_undo(&mut self) {
+        self.undo.clear();
+    }
+
+    // Move every particle in the rectangle by (dx, dy). Particles pushed
+    // past the grid edge are removed. Returns how many particles landed on
+    // the grid.
+    #[wasm_bindgen]
+    #[allow(clippy::too_many_arguments)]
+    pub fn translate_region(
+        &mut self,
+        x0: usize,
+        y0: usize,
+        x1: usize,
+        y1: usize,
+        dx: i32,
+        dy: i32,
+        policy: CollisionPolicy,
+    ) -> usize {
+        let Some(region) = Region::clamped(x0, y0, x1, y1, self.size) else {
+            return 0;
+        };
+        self.undoable(|grid| grid.mirrored_edit(|grid| grid.translate(&region, dx, dy, policy))).0
+    }
+
+    // Stamp a copy of the rectangle's particles offset by (dx, dy), leaving
+    // the originals in place. Returns how many copies were placed.
+    #[wasm_bindgen]
+    #[allow(clippy::too_many_arguments)]
+    pub fn copy_region(
+        &mut self,
+        x0: usize,
+        y0: usize,
+        x1: usize,
+        y1: usize,
+        dx: i32,
+        dy: i32,
+        policy: CollisionPolicy,
+    ) -> usize {
+        let Some(region) = Region::clamped(x0, y0, x1, y1, self.size) else {
+            return 0;
+        };
+        self.undoable(|grid| grid.mirrored_edit(|grid| grid.copy(&region, dx, dy, policy))).0
+    }
+
+    // Remove every particle in the rectangle. Returns how many were removed.
+    #[wasm_bindgen]
+    pub fn delete_region(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) -> usize {
+        match Region::clamped(x0, y0, x1, y1, self.size) {
+            Some(region) => self.undoable(|grid| grid.mirrored_edit(|grid| grid.lift_region(&region).len())).0,
+            None => 0,
+        }
+    }
+}
+
+impl ParticleGrid {
+    // Run `edit`, then push the old values of the cells it changed as one
+    // undo entry. Returns `edit`'s result and the number of changed cells.
+    fn undoable<T>(&mut self, edit: impl FnOnce(&mut Self) -> T) -> (T, usize) {
+        let (result, written) = self.journaled(edit);
+        let entry: Vec<_> = written.into_iter().filter(|&(x, y, old)| self.type_grid[x][y] != old).collect();
+        let changed = entry.len();
+        self.record_sync_edits(entry.iter().map(|&(x, y, _)| (x, y)));
+        if !entry.is_empty() {
+            if self.undo.len() == MAX_UNDO {
+                self.undo.remove(0);
+            }
+            self.undo.push(entry);
+        }
+        (result, changed)
+    }
+
+    // Run `edit` and return its result with the cells it wrote. Writes also
+    // land in the journal of an enclosing call.
+    pub(crate) fn journaled<T>(&mut self, edit: impl FnOnce(&mut Self) -> T) -> (T, Vec<(usize, usize, u8)>) {
+        let outer = self.journal.replace(Journal::default());
+        let result = edit(self);
+        let written = mem::replace(&mut self.journal, outer).map_or_else(Vec::new, |j| j.cells);
+        if let Some(outer) = &mut self.journal {
+            for &(x, y, old) in &written {
+                outer.record(x, y, old);
+            }
+        }
+        (result, written)
+    }
+
+    // Set a cell, journaling its old type during an edit call
+    pub(crate) fn write_cell(&mut self, x: usize, y: usize, t: u8) {
+        let old = mem::replace(&mut self.sim.type_grid[x][y], t);
+        if let Some(journal) = &mut self.journal {
+            journal.record(x, y, old);
+        }
+    }
+
+    fn apply_edit(&mut self, edit: &Edit) {
+        let t = edit.t;
+        if t as usize > self.num_types {
+            return;
+        }
+        let size = self.size;
+        match &edit.shape {
+            &Shape::Cell(x, y) => {
+                if x < size && y < size {
+                    self.write_cell(x, y, t);
+                }
+            }
+            Shape::Stroke(points, radius) => {
+                let Some(&first) = points.first() else {
+                    return;
+                };
+                // A lone point is a zero-length segment
+                let segments = points.windows(2).map(|w| (w[0], w[1]));
+                let segments: Vec<_> = if points.len() == 1 { vec![(first, first)] } else { segments.collect() };
+                // Half a cell more so a zero radius still covers the cells
+                // the line passes through
+                let r = radius + 0.5;
+                // Cells whose centers fall in the segment's box grown by r
+                let span = |a: f32, b: f32| {
+                    let lo = ((a.min(b) - r).ceil() as isize).max(0);
+                    let hi = ((a.max(b) + r).floor() as isize).min(size as isize - 1);
+                    lo..=hi
+                };
+                for ((ax, ay), (bx, by)) in segments {
+                    for x in span(ax, bx) {
+                        for y in span(ay, by) {
+                            let (x, y) = (x as usize, y as usize);
+                            if segment_distance((x as f32, y as f32), (ax, ay), (bx, by)) <= r {
+                                self.write_cell(x, y, t);
+                            }
+                        }
+                    }
+                }
+            }
+            Shape::Rect(r) => {
+                if let Some(region) = Region::clamped(r.x0, r.y0, r.x1, r.y1, size) {
+                    for x in region.x0..=region.x1 {
+                        for y in region.y0..=region.y1 {
+                            self.write_cell(x, y, t);
+                        }
+                    }
+                }
+            }
+            &Shape::Fill(x, y) => {
+                if x < size && y < size && self.type_grid[x][y] != t {
+                    let mut visited = vec![false; size * size];
+                    for (cx, cy) in flood_fill(&self.type_grid, x, y, &mut visited) {
+                        self.write_cell(cx, cy, t);
+                    }
+                }
+            }
+        }
+    }
+
+    fn translate(&mut self, region: &Region, dx: i32, dy: i32, policy: CollisionPolicy) -> usize {
+        let movers = self.lift_region(region);
+
+        let mut blocked = vec![false; movers.len()];
+        if policy == CollisionPolicy::Skip {
+            // A mover is blocked by a particle outside the selection or by
+            // the origin of another blocked mover; repeat until stable
+            let mut blocked_origins = HashSet::new();
+            loop {
+                let mut changed = false;
+                for (i, &(x, y, _)) in movers.iter().enumerate() {
+                    if blocked[i] {
+                        continue;
+                    }
+                    let Some((nx, ny)) = self.offset(x, y, dx, dy) else {
+                        continue;
+                    };
+                    if self.type_grid[nx][ny] != 0 || blocked_origins.contains(&(nx, ny)) {
+                        blocked[i] = true;
+                        blocked_origins.insert((x, y));
+                        changed = true;
+                    }
+                }
+                if !changed {
+                    break;
+                }
+            }
+            for (&(x, y, t), _) in movers.iter().zip(&blocked).filter(|(_, &b)| b) {
+                self.write_cell(x, y, t);
+            }
+        }
+
+        let mut moved = 0;
+        for (&(x, y, t), _) in movers.iter().zip(&blocked).filter(|(_, &b)| !b) {
+            if let Some((nx, ny)) = self.offset(x, y, dx, dy) {
+                self.write_cell(nx, ny, t);
+                moved += 1;
+            }
+        }
+        moved
+    }
+
+    fn copy(&mut self, region: &Region, dx: i32, dy: i32, policy: CollisionPolicy) -> usize {
+        let mut sources = Vec::new();
+        for x in region.x0..=region.x1 {
+            for y in region.y0..=region.y1 {
+                let t = self.type_grid[x][y];
+                if t != 0 {
+                    sources.push((x, y, t));
+                }
+            }
+        }
+
+        let mut placed = 0;
+        for (x, y, t) in sources {
+            let Some((nx, ny)) = self.offset(x, y, dx, dy) else {
+                continue;
+            };
+            if policy == CollisionPolicy::Skip && self.type_grid[nx][ny] != 0 {
+                continue;
+            }
+            self.write_cell(nx, ny, t);
+            placed += 1;
+        }
+        placed
+    }
+
+    // Clear the particles in `region` and return them as (x, y, type)
+    fn lift_region(&mut self, region: &Region) -> Vec<(usize, usize, u8)> {
+        let mut lifted = Vec::new();
+        for x in region.x0..=region.x1 {
+            for y in region.y0..=region.y1 {
+                let t = self.type_grid[x][y];
+                if t != 0 {
+                    lifted.push((x, y, t));
+                    self.write_cell(x, y, 0);
+                }
+            }
+        }
+        lifted
+    }
+
+    fn offset(&self, x: usize, y: usize, dx: i32, dy: i32) -> Option<(usize, usize)> {
+        let nx = x as i64 + dx as i64;
+        let ny = y as i64 + dy as i64;
+        let size = self.size as i64;
+        (nx >= 0 && ny >= 0 && nx < size && ny < size).then_some((nx as usize, ny as usize))
+    }
+}
+
+// Distance from `p` to the segment a-b
+fn segment_distance(p: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len2 = dx * dx + dy * dy;
+    let s = if len2 > 0.0 { (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / len2).clamp(0.0, 1.0) } else { 0.0 };
+    let (cx, cy) = (a.0 + s * dx - p.0, a.1 + s * dy - p.1);
+    (cx * cx + cy * cy).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SimulationConfig;
+    use crate::core::Symmetry;
+
+    fn grid(symmetry: Symmetry) -> ParticleGrid {
+        ParticleGrid::from_config(&SimulationConfig {
+            size: 8,
+            num_types: 2,
+            density: 0.0,
+            seed: Some(4),
+            symmetry,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn undo_restores_touched_cells() {
+        let mut grid = grid(Symmetry::None);
+        grid.apply_edits(vec![Edit::rect(1, 1, 3, 3, 1)]);
+        let before = grid.export_grid();
+        // Writing a cell twice in one call still records its first type
+        let changed = grid.apply_edits(vec![Edit::cell(2, 2, 2), Edit::cell(2, 2, 0), Edit::cell(5, 5, 1)]);
+        assert_eq!(changed, 2);
+        // Cells written with their own type are not changes
+        assert_eq!(grid.apply_edits(vec![Edit::rect(1, 1, 3, 3, 1)]), 1);
+        assert!(grid.undo_edit() && grid.undo_edit());
+        assert_eq!(grid.export_grid(), before);
+        assert_eq!(grid.undo_depth(), 1);
+    }
+
+    #[test]
+    fn mirrored_edits_undo_as_one() {
+        let mut grid = grid(Symmetry::Four);
+        assert_eq!(grid.apply_edits(vec![Edit::cell(1, 2, 1)]), 4);
+        assert_eq!(grid.type_grid[6][5], 1);
+        grid.undo_edit();
+        assert!(grid.type_grid.iter().flatten().all(|&t| t == 0));
+    }
+
+    #[test]
+    fn translate_counts_landed_particles() {
+        let mut grid = grid(Symmetry::None);
+        grid.apply_edits(vec![Edit::rect(5, 0, 7, 0, 1)]);
+        assert_eq!(grid.translate_region(5, 0, 7, 0, 2, 0, CollisionPolicy::Overwrite), 1);
+        assert_eq!(grid.type_grid.iter().flatten().filter(|&&t| t == 1).count(), 1);
+        grid.undo_edit();
+        assert_eq!(grid.type_grid.iter().flatten().filter(|&&t| t == 1).count(), 3);
+    }
+}
@@ -0,0 +1,32 @@
+// Which optional subsystems this build was compiled with (see the feature
+// list in Cargo.toml), so an embedding page can check for an API before
+// reaching for it instead of hitting an undefined export.
+
+use wasm_bindgen::prelude::*;
+
+// Every optional feature, with whether it is compiled in
+const FEATURES: &[(&str, bool)] = &[
+    ("images", cfg!(feature = "images")),
+    ("analysis", cfg!(feature = "analysis")),
+    ("parallel", cfg!(feature = "parallel")),
+    ("trace", cfg!(feature = "trace")),
+    ("webgpu", cfg!(feature = "webgpu")),
+    ("webgl", cfg!(feature = "webgl")),
+    ("canvas", cfg!(feature = "canvas")),
+    ("native", cfg!(feature = "native")),
+    ("ndarray", cfg!(feature = "ndarray")),
+    ("python", cfg!(feature = "python")),
+];
+
+// Names of the optional features in this build, e.g. ["images", "analysis"]
+// for a default wasm-pack build and [] for --no-default-features
+#[wasm_bindgen]
+pub fn features() -> Vec<String> {
+    FEATURES.iter().filter(|(_, on)| *on).map(|(name, _)| name.to_string()).collect()
+}
+
+// Whether `name` is one of features()
+#[wasm_bindgen]
+pub fn has_feature(name: &str) -> bool {
+    FEATURES.iter().any(|&(feature, on)| on && feature == name)
+}
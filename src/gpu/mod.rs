@@ -0,0 +1,278 @@
+// GPU-resident stepping for the WebGPU path (`webgpu` feature, wasm only).
+//
+// The grid lives in a pair of storage buffers on the GPU and is stepped
+// there by the compute shader in step.wgsl, ping-ponging between the two,
+// so nothing crosses the bus per step. read_back_grid() copies the current
+// buffer to a staging buffer and maps it asynchronously when the CPU does
+// want the cells; current_buffer() hands the buffer itself to a renderer.
+//
+// The shader covers the core rules: affinity scoring, greedy moves and the
+// copy/replace reaction (converting every replace-type cell in range, with
+// edges clamped). Boundary modes, symmetry, noise, energy and the other
+// CPU-side extensions are not applied on the GPU. Particles are updated
+// in parallel phases rather than one at a time, so runs differ in detail
+// from the CPU stepper.
+//
+// GpuStepper (stepper.rs) covers the same rules with the CPU's tiled
+// scheduling instead: in-place updates, one invocation per tile, four
+// conflict-free passes per step.
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    gpu_buffer_usage, gpu_map_mode, GpuAutoLayoutMode, GpuBindGroup, GpuBindGroupDescriptor, GpuBindGroupEntry,
+    GpuBuffer, GpuBufferDescriptor, GpuComputePipeline, GpuComputePipelineDescriptor, GpuDevice,
+    GpuProgrammableStage, GpuQueue, GpuShaderModuleDescriptor,
+};
+
+use crate::ParticleGrid;
+
+mod stepper;
+
+pub use stepper::GpuStepper;
+
+const SHADER: &str = include_str!("step.wgsl");
+const WORKGROUP_SIZE: u32 = 8;
+
+#[wasm_bindgen]
+pub struct GpuSimulation {
+    device: GpuDevice,
+    queue: GpuQueue,
+    pipeline: GpuComputePipeline,
+    // Ping-pong cell buffers (one u32 per cell, row-major); `current`
+    // holds the latest state
+    cells: [GpuBuffer; 2],
+    current: usize,
+    params: GpuBuffer,
+    rules: GpuBuffer,
+    // Per phase, bind groups for reading from either cell buffer
+    bind_groups: Vec<[GpuBindGroup; 2]>,
+    size: usize,
+    num_types: usize,
+    spacing: u32,
+    seed: u32,
+    generation: u64,
+    // Rule values from the last upload, written into the params uniform
+    radius: u32,
+    replace_radius: u32,
+    replace_probability: f32,
+    // Chance of each particle being updated per step: the grid's update
+    // fraction, capped at 1
+    update_probability: f32,
+    attraction_scale: f32,
+    repulsion_scale: f32,
+}
+
+#[wasm_bindgen]
+impl GpuSimulation {
+    // Upload `grid`'s cells and rules to `device` (a GPUDevice from
+    // navigator.gpu) and get ready to step them there
+    #[wasm_bindgen(constructor)]
+    pub fn new(device: GpuDevice, grid: &ParticleGrid) -> Result<GpuSimulation, JsValue> {
+        let size = grid.size;
+        let module = device.create_shader_module(&GpuShaderModuleDescriptor::new(SHADER));
+        let stage = GpuProgrammableStage::new(&module);
+        stage.set_entry_point("step_phase");
+        let pipeline = device.create_compute_pipeline(&GpuComputePipelineDescriptor::new_with_gpu_auto_layout_mode(
+            GpuAutoLayoutMode::Auto,
+            &stage,
+        ));
+
+        let cell_bytes = (size * size * 4) as u32;
+        let storage = gpu_buffer_usage::STORAGE | gpu_buffer_usage::COPY_SRC | gpu_buffer_usage::COPY_DST;
+        let cells = [
+            create_buffer(&device, cell_bytes, storage)?,
+            create_buffer(&device, cell_bytes, storage)?,
+        ];
+        let uniform = gpu_buffer_usage::UNIFORM | gpu_buffer_usage::COPY_DST;
+        let params = create_buffer(&device, 48, uniform)?;
+        let n = grid.num_types + 1;
+        let rules = create_buffer(&device, ((n * n + 2 * n) * 4) as u32, storage)?;
+
+        let mut sim = GpuSimulation {
+            queue: device.queue(),
+            pipeline,
+            cells,
+            current: 0,
+            params,
+            rules,
+            bind_groups: Vec::new(),
+            size,
+            num_types: grid.num_types,
+            spacing: 0,
+            seed: js_sys::Math::random().to_bits() as u32,
+            generation: 0,
+            radius: 0,
+            replace_radius: 0,
+            replace_probability: 0.0,
+            update_probability: 0.0,
+            attraction_scale: 0.0,
+            repulsion_scale: 0.0,
+            device,
+        };
+        sim.upload(grid)?;
+        Ok(sim)
+    }
+
+    // Replace the GPU state with `grid`'s cells and rules, e.g. after
+    // editing it on the CPU. The grid must have the same size and number
+    // of types as the one this was built from.
+    #[wasm_bindgen]
+    pub fn upload(&mut self, grid: &ParticleGrid) -> Result<(), JsValue> {
+        if grid.size != self.size || grid.num_types != self.num_types {
+            return Err(JsValue::from_str("grid size or type count differs from the GPU simulation"));
+        }
+        let rules = &grid.rules;
+        self.radius = rules.radius as u32;
+        self.replace_radius = rules.replace_radius as u32;
+        self.replace_probability = rules.replace_probability;
+        self.update_probability = rules.update_fraction.min(1.0);
+        self.attraction_scale = rules.attraction_scale;
+        self.repulsion_scale = rules.repulsion_scale;
+
+        let mut table: Vec<i32> = rules.affinity.iter().flatten().map(|&a| a as i32).collect();
+        table.extend(rules.copy_type.iter().map(|&t| t as i32));
+        table.extend(rules.replace_type.iter().map(|&t| t as i32));
+        self.queue.write_buffer_with_u32_and_u8_slice(&self.rules, 0, &words_to_bytes(&table))?;
+
+        let mut cells = vec![0i32; self.size * self.size];
+        for (x, column) in grid.type_grid.iter().enumerate() {
+            for (y, &t) in column.iter().enumerate() {
+                cells[y * self.size + x] = t as i32;
+            }
+        }
+        self.queue
+            .write_buffer_with_u32_and_u8_slice(&self.cells[self.current], 0, &words_to_bytes(&cells))?;
+
+        // Each thread writes up to max(replace radius, 1) cells away, so
+        // threads in a phase must be twice that plus one apart
+        let spacing = 2 * self.replace_radius.max(1) + 1;
+        if spacing != self.spacing {
+            self.build_phases(spacing)?;
+        }
+        Ok(())
+    }
+
+    // Run `steps` steps on the GPU. Nothing is read back.
+    #[wasm_bindgen]
+    pub fn step(&mut self, steps: u32) -> Result<(), JsValue> {
+        let threads = (self.size as u32).div_ceil(self.spacing);
+        let workgroups = threads.div_ceil(WORKGROUP_SIZE);
+        let cell_bytes = (self.size * self.size * 4) as u32;
+
+        for _ in 0..steps {
+            self.write_params()?;
+            let encoder = self.device.create_command_encoder();
+            for groups in &self.bind_groups {
+                let (src, dst) = (&self.cells[self.current], &self.cells[1 - self.current]);
+                encoder.copy_buffer_to_buffer_with_u32_and_u32_and_u32(src, 0, dst, 0, cell_bytes)?;
+                let pass = encoder.begin_compute_pass();
+                pass.set_pipeline(&self.pipeline);
+                pass.set_bind_group(0, Some(&groups[self.current]));
+                pass.dispatch_workgroups_with_workgroup_count_y(workgroups, workgroups);
+                pass.end();
+                self.current = 1 - self.current;
+            }
+            self.queue.submit(&[encoder.finish()]);
+            self.generation += 1;
+        }
+        Ok(())
+    }
+
+    // Resolves to the cells as a Uint8Array in export_grid's layout
+    #[wasm_bindgen]
+    pub fn read_back_grid(&self) -> Result<js_sys::Promise, JsValue> {
+        read_back(&self.device, &self.queue, &self.cells[self.current], self.size)
+    }
+
+    // The storage buffer holding the latest cells (one u32 per cell,
+    // row-major), for binding straight into a render pass. Which of the two
+    // buffers this is changes as the simulation steps.
+    #[wasm_bindgen]
+    pub fn current_buffer(&self) -> GpuBuffer {
+        self.cells[self.current].clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn generation(&self) -> f64 {
+        self.generation as f64
+    }
+}
+
+impl GpuSimulation {
+    fn build_phases(&mut self, spacing: u32) -> Result<(), JsValue> {
+        let layout = self.pipeline.get_bind_group_layout(0);
+        let uniform = gpu_buffer_usage::UNIFORM | gpu_buffer_usage::COPY_DST;
+        self.bind_groups.clear();
+        for index in 0..spacing * spacing {
+            let phase = create_buffer(&self.device, 16, uniform)?;
+            let words = [index % spacing, index / spacing, index, 0];
+            self.queue.write_buffer_with_u32_and_u8_slice(&phase, 0, &words_to_bytes(&words))?;
+
+            let group = |src: &GpuBuffer, dst: &GpuBuffer| {
+                let entries = [
+                    GpuBindGroupEntry::new_with_gpu_buffer(0, &self.params),
+                    GpuBindGroupEntry::new_with_gpu_buffer(1, &phase),
+                    GpuBindGroupEntry::new_with_gpu_buffer(2, src),
+                    GpuBindGroupEntry::new_with_gpu_buffer(3, dst),
+                    GpuBindGroupEntry::new_with_gpu_buffer(4, &self.rules),
+                ];
+                self.device.create_bind_group(&GpuBindGroupDescriptor::new(&entries, &layout))
+            };
+            self.bind_groups.push([group(&self.cells[0], &self.cells[1]), group(&self.cells[1], &self.cells[0])]);
+        }
+        self.spacing = spacing;
+        Ok(())
+    }
+
+    fn write_params(&self) -> Result<(), JsValue> {
+        let words = [
+            self.size as u32,
+            self.num_types as u32,
+            self.radius,
+            self.replace_radius,
+            self.spacing,
+            self.generation as u32,
+            self.seed,
+            self.update_probability.to_bits(),
+            self.replace_probability.to_bits(),
+            self.attraction_scale.to_bits(),
+            self.repulsion_scale.to_bits(),
+            0,
+        ];
+        self.queue.write_buffer_with_u32_and_u8_slice(&self.params, 0, &words_to_bytes(&words))
+    }
+}
+
+// Copy `cells` (size x size u32s) to a staging buffer and resolve to them
+// as a Uint8Array once it maps
+fn read_back(device: &GpuDevice, queue: &GpuQueue, cells: &GpuBuffer, size: usize) -> Result<js_sys::Promise, JsValue> {
+    let cell_bytes = (size * size * 4) as u32;
+    let staging = create_buffer(device, cell_bytes, gpu_buffer_usage::MAP_READ | gpu_buffer_usage::COPY_DST)?;
+    let encoder = device.create_command_encoder();
+    encoder.copy_buffer_to_buffer_with_u32_and_u32_and_u32(cells, 0, &staging, 0, cell_bytes)?;
+    queue.submit(&[encoder.finish()]);
+
+    Ok(wasm_bindgen_futures::future_to_promise(async move {
+        JsFuture::from(staging.map_async(gpu_map_mode::READ)).await?;
+        let mapped = staging.get_mapped_range()?;
+        let words = js_sys::Uint32Array::new(&mapped).to_vec();
+        staging.unmap();
+        staging.destroy();
+        let cells: Vec<u8> = words.into_iter().map(|t| t as u8).collect();
+        Ok(js_sys::Uint8Array::from(cells.as_slice()).into())
+    }))
+}
+
+fn create_buffer(device: &GpuDevice, size: u32, usage: u32) -> Result<GpuBuffer, JsValue> {
+    device.create_buffer(&GpuBufferDescriptor::new(size, usage))
+}
+
+fn words_to_bytes<T: Copy + Into<i64>>(words: &[T]) -> Vec<u8> {
+    words.iter().flat_map(|&w| (w.into() as u32).to_le_bytes()).collect()
+}
@@ -0,0 +1,150 @@
+// One phase of a GPU step. Cells are split into spacing x spacing classes
+// and a phase updates the particles of one class: each sits at the center
+// of its own block of cells that no other thread in the phase writes, so
+// moves and conversions never collide. Reads come from `src`, writes go to
+// `dst` (a fresh copy of `src`), and the host swaps the two afterwards.
+
+struct Params {
+    size: u32,
+    num_types: u32,
+    radius: u32,
+    replace_radius: u32,
+    spacing: u32,
+    step: u32,
+    seed: u32,
+    // Chance that a particle is updated in a step
+    update_probability: f32,
+    replace_probability: f32,
+    attraction_scale: f32,
+    repulsion_scale: f32,
+    _pad: u32,
+};
+
+struct Phase {
+    x: u32,
+    y: u32,
+    index: u32,
+    _pad: u32,
+};
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<uniform> phase: Phase;
+@group(0) @binding(2) var<storage, read> src: array<u32>;
+@group(0) @binding(3) var<storage, read_write> dst: array<u32>;
+// Affinity table (n x n, row = from), then copy types, then replace types
+@group(0) @binding(4) var<storage, read> rules: array<i32>;
+
+var<private> rng_state: u32;
+
+fn pcg(v: u32) -> u32 {
+    let state = v * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn rand() -> f32 {
+    rng_state = pcg(rng_state);
+    return f32(rng_state >> 8u) / 16777216.0;
+}
+
+fn cell(x: i32, y: i32) -> u32 {
+    return src[u32(y) * params.size + u32(x)];
+}
+
+fn interaction_weight(a: i32) -> f32 {
+    if (a > 0) {
+        return f32(a) * params.attraction_scale;
+    }
+    return -f32(max(abs(a), 1)) * params.repulsion_scale;
+}
+
+// Mean interaction weight over the window around (x, y), cut off at the
+// grid edge
+fn cell_score(p_type: u32, x: i32, y: i32) -> f32 {
+    let n = params.num_types + 1u;
+    let r = i32(params.radius);
+    let last = i32(params.size) - 1;
+    var score = 0.0;
+    var count = 0.0;
+    for (var yy = max(y - r, 0); yy <= min(y + r, last); yy++) {
+        for (var xx = max(x - r, 0); xx <= min(x + r, last); xx++) {
+            count += 1.0;
+            let t = cell(xx, yy);
+            if (t != 0u) {
+                score += interaction_weight(rules[p_type * n + t]);
+            }
+        }
+    }
+    return score / max(count, 1.0);
+}
+
+@compute @workgroup_size(8, 8)
+fn step_phase(@builtin(global_invocation_id) id: vec3u) {
+    let x = i32(id.x * params.spacing + phase.x);
+    let y = i32(id.y * params.spacing + phase.y);
+    let size = i32(params.size);
+    if (x >= size || y >= size) {
+        return;
+    }
+    let here = u32(y) * params.size + u32(x);
+    let p_type = src[here];
+    if (p_type == 0u) {
+        return;
+    }
+
+    rng_state = pcg(here ^ pcg(params.step ^ pcg(params.seed + phase.index)));
+    if (rand() >= params.update_probability) {
+        return;
+    }
+    let last = size - 1;
+
+    // Copy/replace reaction, reading the grid as it was before the phase
+    let n = params.num_types + 1u;
+    let ct = u32(rules[n * n + p_type]);
+    let rt = u32(rules[n * n + n + p_type]);
+    let rr = i32(params.replace_radius);
+    if (rand() < params.replace_probability) {
+        var has_copy = false;
+        for (var j = max(y - rr, 0); j <= min(y + rr, last); j++) {
+            for (var i = max(x - rr, 0); i <= min(x + rr, last); i++) {
+                has_copy = has_copy || cell(i, j) == ct;
+            }
+        }
+        if (has_copy) {
+            for (var j = max(y - rr, 0); j <= min(y + rr, last); j++) {
+                for (var i = max(x - rr, 0); i <= min(x + rr, last); i++) {
+                    if (cell(i, j) == rt) {
+                        dst[u32(j) * params.size + u32(i)] = ct;
+                    }
+                }
+            }
+        }
+    }
+
+    // Greedy move to the best adjacent empty cell, ties broken uniformly
+    var best = -1000000.0;
+    var dest = vec2i(x, y);
+    var ties = 0.0;
+    for (var j = max(y - 1, 0); j <= min(y + 1, last); j++) {
+        for (var i = max(x - 1, 0); i <= min(x + 1, last); i++) {
+            if (cell(i, j) != 0u) {
+                continue;
+            }
+            let score = cell_score(p_type, i, j);
+            if (score > best + 1.1920929e-7) {
+                best = score;
+                dest = vec2i(i, j);
+                ties = 1.0;
+            } else if (abs(score - best) < 1.1920929e-7) {
+                ties += 1.0;
+                if (rand() * ties < 1.0) {
+                    dest = vec2i(i, j);
+                }
+            }
+        }
+    }
+    if (dest.x != x || dest.y != y) {
+        dst[u32(dest.y) * params.size + u32(dest.x)] = p_type;
+        dst[here] = 0u;
+    }
+}
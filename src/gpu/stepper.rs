@@ -0,0 +1,280 @@
+// Tiled GPU stepping. Where GpuSimulation updates particles in spacing x
+// spacing classes with a fresh copy of the grid per class, GpuStepper
+// follows the CPU's tile-parallel scheme (core/tiled.rs): the grid is cut
+// into square tiles, each step runs four passes over the (x parity,
+// y parity) classes of tiles, and one invocation per tile updates random
+// particles of its tile one at a time, in place.
+//
+// Invocations of a pass own tiles a full tile apart. An update reads up to
+// max(radius + 1, replace radius) cells from its particle and writes up to
+// max(replace radius, 1), so with tiles at least the sum of the two wide
+// nothing one invocation writes is read or written by another. Runs are
+// therefore race-free and deterministic for a given seed, whatever order
+// the GPU schedules the tiles in. Each tile of each step draws from its own
+// Philox stream (see philox.rs), and makes the same draws the CPU's tiled
+// step makes (core/tiled.rs), so a stepper built from a tiled grid carries
+// on exactly as the grid would: same tile size, key and generation. The
+// one place the backends can part is a score that lands within rounding
+// of a tie, since WGSL doesn't promise correctly rounded division.
+//
+// Larger tiles mean fewer, longer invocations (less parallelism); smaller
+// ones more parallelism but more visible tile seams within a step. The
+// tile size is raised to the minimum the rules allow whenever they need it.
+//
+// The shader only has the core rules: affinity scores, greedy moves and
+// uncapped reactions on a clamped grid. Grids using anything more (see
+// Simulation::gpu_tiled_support) are refused rather than stepped wrong.
+
+use wasm_bindgen::prelude::*;
+use web_sys::{
+    gpu_buffer_usage, GpuAutoLayoutMode, GpuBindGroup, GpuBindGroupDescriptor, GpuBindGroupEntry, GpuBuffer,
+    GpuComputePipeline, GpuComputePipelineDescriptor, GpuDevice, GpuProgrammableStage, GpuQueue,
+    GpuShaderModuleDescriptor,
+};
+
+use super::{create_buffer, read_back, words_to_bytes, WORKGROUP_SIZE};
+use crate::core::DEFAULT_TILE_SIZE;
+use crate::ParticleGrid;
+
+const SHADER: &str = include_str!("tiled.wgsl");
+
+#[wasm_bindgen]
+pub struct GpuStepper {
+    device: GpuDevice,
+    queue: GpuQueue,
+    pipeline: GpuComputePipeline,
+    // One u32 per cell, row-major, updated in place
+    cells: GpuBuffer,
+    params: GpuBuffer,
+    rules: GpuBuffer,
+    // One per pass
+    bind_groups: Vec<GpuBindGroup>,
+    size: usize,
+    num_types: usize,
+    // Requested, smallest allowed and effective tile size
+    requested_tile: u32,
+    min_tile: u32,
+    tile: u32,
+    seed: u64,
+    generation: u64,
+    radius: u32,
+    replace_radius: u32,
+    replace_probability: f32,
+    update_fraction: f32,
+    attraction_scale: f32,
+    repulsion_scale: f32,
+}
+
+#[wasm_bindgen]
+impl GpuStepper {
+    // Upload `grid`'s cells and rules to `device` (a GPUDevice from
+    // navigator.gpu). A tiled grid (enable_tiling) hands over its tile size,
+    // stream key and generation, so the GPU continues its run; otherwise the
+    // tile size defaults to 32 cells and the seed is random. `tile_size`
+    // overrides the tile size either way. Fails for grids using rules the
+    // shader lacks.
+    #[wasm_bindgen(constructor)]
+    pub fn new(device: GpuDevice, grid: &ParticleGrid, tile_size: Option<u32>) -> Result<GpuStepper, JsValue> {
+        grid.sim.gpu_tiled_support().map_err(|e| JsValue::from_str(&e))?;
+        let size = grid.size;
+        let module = device.create_shader_module(&GpuShaderModuleDescriptor::new(SHADER));
+        let stage = GpuProgrammableStage::new(&module);
+        stage.set_entry_point("step_pass");
+        let pipeline = device.create_compute_pipeline(&GpuComputePipelineDescriptor::new_with_gpu_auto_layout_mode(
+            GpuAutoLayoutMode::Auto,
+            &stage,
+        ));
+
+        let storage = gpu_buffer_usage::STORAGE | gpu_buffer_usage::COPY_SRC | gpu_buffer_usage::COPY_DST;
+        let cells = create_buffer(&device, (size * size * 4) as u32, storage)?;
+        let params = create_buffer(&device, 64, gpu_buffer_usage::UNIFORM | gpu_buffer_usage::COPY_DST)?;
+        let n = grid.num_types + 1;
+        let rules = create_buffer(&device, ((n * n + 2 * n) * 4) as u32, storage)?;
+
+        let mut stepper = GpuStepper {
+            queue: device.queue(),
+            pipeline,
+            cells,
+            params,
+            rules,
+            bind_groups: Vec::new(),
+            size,
+            num_types: grid.num_types,
+            requested_tile: tile_size.unwrap_or(grid.tile_size().unwrap_or(DEFAULT_TILE_SIZE) as u32),
+            min_tile: 0,
+            tile: 0,
+            seed: grid.tile_key().unwrap_or_else(|| js_sys::Math::random().to_bits()),
+            generation: grid.sim.generation,
+            radius: 0,
+            replace_radius: 0,
+            replace_probability: 0.0,
+            update_fraction: 0.0,
+            attraction_scale: 0.0,
+            repulsion_scale: 0.0,
+            device,
+        };
+        stepper.build_passes()?;
+        stepper.upload(grid)?;
+        Ok(stepper)
+    }
+
+    // Replace the GPU state with `grid`'s cells and rules. The grid must
+    // have the same size and number of types as the one this was built from,
+    // and no rules the shader lacks.
+    #[wasm_bindgen]
+    pub fn upload(&mut self, grid: &ParticleGrid) -> Result<(), JsValue> {
+        if grid.size != self.size || grid.num_types != self.num_types {
+            return Err(JsValue::from_str("grid size or type count differs from the GPU stepper"));
+        }
+        grid.sim.gpu_tiled_support().map_err(|e| JsValue::from_str(&e))?;
+        let rules = &grid.rules;
+        self.radius = rules.radius as u32;
+        self.replace_radius = rules.replace_radius as u32;
+        self.replace_probability = rules.replace_probability;
+        self.update_fraction = rules.update_fraction;
+        self.attraction_scale = rules.attraction_scale;
+        self.repulsion_scale = rules.repulsion_scale;
+        self.min_tile = rules.min_tile_size() as u32;
+        self.fit_tile();
+
+        let mut table: Vec<i32> = rules.affinity.iter().flatten().map(|&a| a as i32).collect();
+        table.extend(rules.copy_type.iter().map(|&t| t as i32));
+        table.extend(rules.replace_type.iter().map(|&t| t as i32));
+        self.queue.write_buffer_with_u32_and_u8_slice(&self.rules, 0, &words_to_bytes(&table))?;
+
+        let mut cells = vec![0i32; self.size * self.size];
+        for (x, column) in grid.type_grid.iter().enumerate() {
+            for (y, &t) in column.iter().enumerate() {
+                cells[y * self.size + x] = t as i32;
+            }
+        }
+        self.queue.write_buffer_with_u32_and_u8_slice(&self.cells, 0, &words_to_bytes(&cells))
+    }
+
+    // Ask for `tile_size`-cell tiles, raised to min_tile_size if smaller
+    #[wasm_bindgen]
+    pub fn set_tile_size(&mut self, tile_size: u32) {
+        self.requested_tile = tile_size;
+        self.fit_tile();
+    }
+
+    // Tile size in use
+    #[wasm_bindgen(getter)]
+    pub fn tile_size(&self) -> u32 {
+        self.tile
+    }
+
+    // Smallest conflict-free tile for the uploaded rules
+    #[wasm_bindgen(getter)]
+    pub fn min_tile_size(&self) -> u32 {
+        self.min_tile
+    }
+
+    // Run `steps` steps (four passes each) on the GPU. Nothing is read back.
+    #[wasm_bindgen]
+    pub fn step(&mut self, steps: u32) -> Result<(), JsValue> {
+        let tiles = (self.size as u32).div_ceil(self.tile);
+        // Invocations per pass along each axis: every other tile
+        let workgroups = tiles.div_ceil(2).div_ceil(WORKGROUP_SIZE);
+        for _ in 0..steps {
+            self.write_params(tiles)?;
+            let encoder = self.device.create_command_encoder();
+            // Separate compute passes, so each sees the previous one's writes
+            for group in &self.bind_groups {
+                let pass = encoder.begin_compute_pass();
+                pass.set_pipeline(&self.pipeline);
+                pass.set_bind_group(0, Some(group));
+                pass.dispatch_workgroups_with_workgroup_count_y(workgroups, workgroups);
+                pass.end();
+            }
+            self.queue.submit(&[encoder.finish()]);
+            self.generation += 1;
+        }
+        Ok(())
+    }
+
+    // Resolves to the cells as a Uint8Array in export_grid's layout
+    #[wasm_bindgen]
+    pub fn read_back_grid(&self) -> Result<js_sys::Promise, JsValue> {
+        read_back(&self.device, &self.queue, &self.cells, self.size)
+    }
+
+    // The storage buffer holding the cells (one u32 per cell, row-major),
+    // for binding straight into a render pass
+    #[wasm_bindgen]
+    pub fn buffer(&self) -> GpuBuffer {
+        self.cells.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn generation(&self) -> f64 {
+        self.generation as f64
+    }
+
+    // Key of the random streams, random unless set. Runs from the same
+    // grid, seed and tile size are identical on any GPU.
+    #[wasm_bindgen(getter)]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    #[wasm_bindgen]
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+}
+
+impl GpuStepper {
+    fn fit_tile(&mut self) {
+        self.tile = self.requested_tile.max(self.min_tile_size());
+    }
+
+    fn build_passes(&mut self) -> Result<(), JsValue> {
+        let layout = self.pipeline.get_bind_group_layout(0);
+        let uniform = gpu_buffer_usage::UNIFORM | gpu_buffer_usage::COPY_DST;
+        self.bind_groups.clear();
+        for index in 0..4u32 {
+            let pass = create_buffer(&self.device, 16, uniform)?;
+            let words = [index % 2, index / 2, index, 0];
+            self.queue.write_buffer_with_u32_and_u8_slice(&pass, 0, &words_to_bytes(&words))?;
+            let entries = [
+                GpuBindGroupEntry::new_with_gpu_buffer(0, &self.params),
+                GpuBindGroupEntry::new_with_gpu_buffer(1, &pass),
+                GpuBindGroupEntry::new_with_gpu_buffer(2, &self.cells),
+                GpuBindGroupEntry::new_with_gpu_buffer(3, &self.rules),
+            ];
+            self.bind_groups.push(self.device.create_bind_group(&GpuBindGroupDescriptor::new(&entries, &layout)));
+        }
+        Ok(())
+    }
+
+    fn write_params(&self, tiles: u32) -> Result<(), JsValue> {
+        // rand's gen_bool threshold, which the shader compares a u64 draw to
+        let p = self.replace_probability.max(0.0);
+        let threshold = (p as f64 * 2f64.powi(64)) as u64;
+        let words = [
+            self.size as u32,
+            self.num_types as u32,
+            self.radius,
+            self.replace_radius,
+            self.tile,
+            tiles,
+            self.generation as u32,
+            self.seed as u32,
+            self.update_fraction.to_bits(),
+            self.replace_probability.to_bits(),
+            self.attraction_scale.to_bits(),
+            self.repulsion_scale.to_bits(),
+            (self.seed >> 32) as u32,
+            threshold as u32,
+            (threshold >> 32) as u32,
+            (p >= 1.0) as u32,
+        ];
+        self.queue.write_buffer_with_u32_and_u8_slice(&self.params, 0, &words_to_bytes(&words))
+    }
+}
@@ -0,0 +1,232 @@
+// One pass of a tiled GPU step (see stepper.rs). The grid is cut into
+// tile x tile squares and every invocation owns one tile of the pass's
+// (x parity, y parity) class, updating random particles in it one after
+// another, in place, like the CPU stepper does. Tiles of a class are a
+// full tile apart and the tile is at least as wide as an update's read
+// reach plus its write reach, so no invocation ever reads a cell another
+// one writes: the result depends only on the seed, not on scheduling.
+//
+// Random numbers come from Philox4x32-10 (philox.rs has the CPU twin),
+// keyed by the 64-bit seed with the counter (block, 0, tile, step), so each
+// tile of each step has its own stream whatever the dispatch order. Every
+// draw below matches one the CPU makes for the same update (core/tiled.rs
+// and Rules::update_tile), so both backends step a grid the same way.
+
+struct Params {
+    size: u32,
+    num_types: u32,
+    radius: u32,
+    replace_radius: u32,
+    tile: u32,
+    tiles_per_side: u32,
+    step: u32,
+    seed_lo: u32,
+    // Expected updates per cell of a tile; each picks a random cell
+    update_rate: f32,
+    replace_probability: f32,
+    attraction_scale: f32,
+    repulsion_scale: f32,
+    seed_hi: u32,
+    // Reactions go ahead when a 64-bit draw is below this (rand's
+    // gen_bool), or always when replace_always is set
+    replace_lo: u32,
+    replace_hi: u32,
+    replace_always: u32,
+};
+
+struct Pass {
+    x: u32,
+    y: u32,
+    index: u32,
+    _pad: u32,
+};
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<uniform> pass_: Pass;
+@group(0) @binding(2) var<storage, read_write> cells: array<u32>;
+// Affinity table (n x n, row = from), then copy types, then replace types
+@group(0) @binding(3) var<storage, read> rules: array<i32>;
+
+// Current Philox stream: its counter, and the block being handed out
+var<private> rng_counter: vec4u;
+var<private> rng_block: vec4u;
+var<private> rng_used: u32;
+
+// High 32 bits of a * b
+fn mul_hi(a: u32, b: u32) -> u32 {
+    let a_lo = a & 0xffffu;
+    let a_hi = a >> 16u;
+    let b_lo = b & 0xffffu;
+    let b_hi = b >> 16u;
+    let cross = ((a_lo * b_lo) >> 16u) + ((a_hi * b_lo) & 0xffffu) + a_lo * b_hi;
+    return a_hi * b_hi + ((a_hi * b_lo) >> 16u) + (cross >> 16u);
+}
+
+fn philox(counter: vec4u, key: vec2u) -> vec4u {
+    var c = counter;
+    var k = key;
+    for (var round = 0u; round < 10u; round++) {
+        if (round > 0u) {
+            k += vec2u(0x9E3779B9u, 0xBB67AE85u);
+        }
+        let lo0 = 0xD2511F53u * c.x;
+        let lo1 = 0xCD9E8D57u * c.z;
+        c = vec4u(mul_hi(0xCD9E8D57u, c.z) ^ c.y ^ k.x, lo1, mul_hi(0xD2511F53u, c.x) ^ c.w ^ k.y, lo0);
+    }
+    return c;
+}
+
+fn next_u32() -> u32 {
+    if (rng_used == 4u) {
+        rng_block = philox(rng_counter, vec2u(params.seed_lo, params.seed_hi));
+        rng_counter.x += 1u;
+        rng_used = 0u;
+    }
+    rng_used += 1u;
+    return rng_block[rng_used - 1u];
+}
+
+// Uniform in [0, 1), as rand's gen::<f32>() makes it from the same word
+fn rand() -> f32 {
+    return f32(next_u32() >> 8u) / 16777216.0;
+}
+
+// Whether the reaction goes ahead, drawing like rand's gen_bool: a u64
+// from two words, low word first
+fn react() -> bool {
+    if (params.replace_always != 0u) {
+        return true;
+    }
+    let lo = next_u32();
+    let hi = next_u32();
+    return hi < params.replace_hi || (hi == params.replace_hi && lo < params.replace_lo);
+}
+
+// Uniform in 0..n (n >= 1), as rand's gen_range on u32 draws it: widening
+// multiply, rejecting the low words past the zone
+fn below(n: u32) -> u32 {
+    let zone = (n << countLeadingZeros(n)) - 1u;
+    loop {
+        let v = next_u32();
+        if (v * n <= zone) {
+            return mul_hi(v, n);
+        }
+    }
+}
+
+fn index(x: i32, y: i32) -> u32 {
+    return u32(y) * params.size + u32(x);
+}
+
+fn interaction_weight(a: i32) -> f32 {
+    if (a > 0) {
+        return f32(a) * params.attraction_scale;
+    }
+    return -f32(max(abs(a), 1)) * params.repulsion_scale;
+}
+
+// Mean interaction weight over the window around (x, y), cut off at the
+// grid edge
+fn cell_score(p_type: u32, x: i32, y: i32) -> f32 {
+    let n = params.num_types + 1u;
+    let r = i32(params.radius);
+    let last = i32(params.size) - 1;
+    var score = 0.0;
+    var count = 0.0;
+    for (var yy = max(y - r, 0); yy <= min(y + r, last); yy++) {
+        for (var xx = max(x - r, 0); xx <= min(x + r, last); xx++) {
+            count += 1.0;
+            let t = cells[index(xx, yy)];
+            if (t != 0u) {
+                score += interaction_weight(rules[p_type * n + t]);
+            }
+        }
+    }
+    return score / max(count, 1.0);
+}
+
+// Copy/replace reaction, then a greedy move with ties broken uniformly.
+// Scores that beat the best so far replace it, ones within f32::EPSILON
+// of it tie, in the CPU's neighbor order.
+fn update(x: i32, y: i32, p_type: u32) {
+    let last = i32(params.size) - 1;
+    let n = params.num_types + 1u;
+    let ct = u32(rules[n * n + p_type]);
+    let rt = u32(rules[n * n + n + p_type]);
+    let rr = i32(params.replace_radius);
+    if (react()) {
+        var has_copy = false;
+        for (var j = max(y - rr, 0); j <= min(y + rr, last); j++) {
+            for (var i = max(x - rr, 0); i <= min(x + rr, last); i++) {
+                has_copy = has_copy || cells[index(i, j)] == ct;
+            }
+        }
+        if (has_copy) {
+            for (var j = max(y - rr, 0); j <= min(y + rr, last); j++) {
+                for (var i = max(x - rr, 0); i <= min(x + rr, last); i++) {
+                    if (cells[index(i, j)] == rt) {
+                        cells[index(i, j)] = ct;
+                    }
+                }
+            }
+        }
+    }
+
+    // The reaction may have converted the particle itself
+    let moving = cells[index(x, y)];
+    if (moving == 0u) {
+        return;
+    }
+    // Staying put is the only option until a free neighbor turns up
+    var best = -1000000.0;
+    var options: array<vec2i, 9>;
+    options[0] = vec2i(x, y);
+    var count = 1u;
+    for (var j = max(y - 1, 0); j <= min(y + 1, last); j++) {
+        for (var i = max(x - 1, 0); i <= min(x + 1, last); i++) {
+            if (cells[index(i, j)] != 0u) {
+                continue;
+            }
+            let score = cell_score(moving, i, j);
+            if (score > best) {
+                best = score;
+                options[0] = vec2i(i, j);
+                count = 1u;
+            } else if (abs(score - best) < 1.1920929e-7) {
+                options[count] = vec2i(i, j);
+                count += 1u;
+            }
+        }
+    }
+    let dest = options[below(count)];
+    if (dest.x != x || dest.y != y) {
+        cells[index(dest.x, dest.y)] = moving;
+        cells[index(x, y)] = 0u;
+    }
+}
+
+@compute @workgroup_size(8, 8)
+fn step_pass(@builtin(global_invocation_id) id: vec3u) {
+    let tx = id.x * 2u + pass_.x;
+    let ty = id.y * 2u + pass_.y;
+    if (tx >= params.tiles_per_side || ty >= params.tiles_per_side) {
+        return;
+    }
+    let x0 = tx * params.tile;
+    let y0 = ty * params.tile;
+    let w = min(params.tile, params.size - x0);
+    let h = min(params.tile, params.size - y0);
+
+    let tile_index = ty * params.tiles_per_side + tx;
+    rng_counter = vec4u(0u, 0u, tile_index, params.step);
+    rng_used = 4u;
+    let attempts = u32(floor(params.update_rate * f32(w * h)));
+    for (var k = 0u; k < attempts; k++) {
+        let x = i32(x0 + min(u32(rand() * f32(w)), w - 1u));
+        let y = i32(y0 + min(u32(rand() * f32(h)), h - 1u));
+        let p_type = cells[index(x, y)];
+        if (p_type != 0u) {
+            update(x, y, p_type);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+// Export of the rule network (affinities plus copy/replace reactions) as a
+// graph, for drawing node-link diagrams next to the simulation.
+
+use std::fmt::Write;
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::ParticleGrid;
+
+#[wasm_bindgen]
+impl ParticleGrid {
+    // Interaction graph as "json" (default) or "dot". Nodes are the particle
+    // types with their names and colors. Edges run from a type to each type
+    // it reacts to: "attract"/"repel" with the effective score weight, plus
+    // "copy" (the type it spreads) and "replace" (the type it converts)
+    // for types with a reaction.
+    #[wasm_bindgen]
+    pub fn export_interaction_graph(&self, format: &str) -> String {
+        match format {
+            "dot" => self.interaction_graph_dot(),
+            _ => self.interaction_graph_json(),
+        }
+    }
+}
+
+impl ParticleGrid {
+    fn graph_edges(&self) -> Vec<(usize, usize, &'static str, f32)> {
+        let mut edges = Vec::new();
+        for t in 1..=self.num_types {
+            for u in 1..=self.num_types {
+                let weight = self.rules.interaction_weight(self.rules.affinity[t][u]);
+                let kind = if weight > 0.0 { "attract" } else { "repel" };
+                edges.push((t, u, kind, weight));
+            }
+            // A type copied and replaced to the same type has no reaction
+            let (ct, rt) = (self.rules.copy_type[t] as usize, self.rules.replace_type[t] as usize);
+            if ct != rt {
+                edges.push((t, ct, "copy", 1.0));
+                edges.push((t, rt, "replace", 1.0));
+            }
+        }
+        edges
+    }
+
+    fn interaction_graph_json(&self) -> String {
+        let nodes: Vec<GraphNode> = (1..=self.num_types)
+            .map(|t| {
+                let [r, g, b] = self.colors[t];
+                GraphNode { id: t, name: &self.type_names[t], color: format!("#{:02x}{:02x}{:02x}", r, g, b) }
+            })
+            .collect();
+        let edges: Vec<GraphEdge> = self
+            .graph_edges()
+            .into_iter()
+            .map(|(from, to, kind, weight)| GraphEdge { from, to, kind, weight })
+            .collect();
+        serde_json::to_string(&InteractionGraph { nodes, edges }).unwrap_or_default()
+    }
+
+    fn interaction_graph_dot(&self) -> String {
+        let mut out = String::from("digraph interactions {\n");
+        for t in 1..=self.num_types {
+            let [r, g, b] = self.colors[t];
+            let _ = writeln!(
+                out,
+                "  t{} [label={}, style=filled, fillcolor=\"#{:02x}{:02x}{:02x}\"];",
+                t,
+                serde_json::to_string(&self.type_names[t]).unwrap_or_default(),
+                r,
+                g,
+                b
+            );
+        }
+        for (from, to, kind, weight) in self.graph_edges() {
+            let style = match kind {
+                "attract" => "color=green",
+                "repel" => "color=red",
+                "copy" => "color=blue, style=dashed",
+                _ => "color=black, style=dotted",
+            };
+            let _ = writeln!(
+                out,
+                "  t{} -> t{} [label=\"{} {}\", {}];",
+                from, to, kind, weight, style
+            );
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+// JSON shape of export_interaction_graph. Non-finite weights come out as
+// null rather than breaking the document.
+#[derive(Serialize)]
+struct InteractionGraph<'a> {
+    nodes: Vec<GraphNode<'a>>,
+    edges: Vec<GraphEdge>,
+}
+
+#[derive(Serialize)]
+struct GraphNode<'a> {
+    id: usize,
+    name: &'a str,
+    color: String,
+}
+
+#[derive(Serialize)]
+struct GraphEdge {
+    from: usize,
+    to: usize,
+    kind: &'static str,
+    weight: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SimulationConfig;
+
+    fn grid() -> ParticleGrid {
+        // Type 1 converts 2 into 1; type 2 has no reaction
+        let config = SimulationConfig {
+            size: 8,
+            num_types: 2,
+            seed: Some(3),
+            affinity: vec![vec![0; 3], vec![0, 100, -1], vec![0, -1, 100]],
+            copy_types: vec![0, 1, 2],
+            replace_types: vec![0, 2, 2],
+            ..Default::default()
+        };
+        ParticleGrid::from_config(&config)
+    }
+
+    #[test]
+    fn skips_types_without_reactions() {
+        let json: serde_json::Value = serde_json::from_str(&grid().export_interaction_graph("json")).unwrap();
+        let edges = json["edges"].as_array().unwrap();
+        let reactions: Vec<_> = edges.iter().filter(|e| e["kind"] == "copy" || e["kind"] == "replace").collect();
+        assert_eq!(reactions.len(), 2);
+        assert!(reactions.iter().all(|e| e["from"] == 1));
+        assert_eq!(edges.len(), 6);
+    }
+
+    #[test]
+    fn json_survives_odd_names_and_weights() {
+        let mut grid = grid();
+        grid.type_names[1] = "say \"hi\"\n".to_string();
+        grid.set_interaction_weights(f32::MAX, 1.0);
+        let json: serde_json::Value = serde_json::from_str(&grid.export_interaction_graph("json")).unwrap();
+        assert_eq!(json["nodes"][0]["name"], "say \"hi\"\n");
+        assert!(json["edges"].as_array().unwrap().iter().any(|e| e["weight"].is_null()));
+    }
+}
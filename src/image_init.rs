@@ -0,0 +1,60 @@
+// Building a starting grid from a picture: decode, scale to the grid size,
+// and snap every pixel to the nearest palette color.
+
+use image::imageops::FilterType;
+use wasm_bindgen::prelude::*;
+
+use crate::{default_color, ParticleGrid};
+
+// Pixels more transparent than this become empty space
+const ALPHA_CUTOFF: u8 = 128;
+
+#[wasm_bindgen]
+impl ParticleGrid {
+    // Decode a PNG or JPEG, stretch it to `size` x `size`, and map each pixel
+    // to the particle type whose default color is closest (black and
+    // transparent pixels become empty). Rules are set up as in the
+    // constructor, and density is taken from the resulting grid.
+    #[wasm_bindgen]
+    pub fn from_image(
+        bytes: &[u8],
+        size: usize,
+        num_types: usize,
+        radius: usize,
+        affinity_array: Option<Vec<i32>>,
+        seed: u64,
+    ) -> Result<ParticleGrid, String> {
+        let decoded = image::load_from_memory(bytes).map_err(|e| format!("could not decode image: {}", e))?;
+        let pixels = image::imageops::resize(&decoded.to_rgba8(), size as u32, size as u32, FilterType::Triangle);
+
+        let palette: Vec<[u8; 3]> = (0..=num_types.min(u8::MAX as usize) as u8).map(default_color).collect();
+        let mut grid = ParticleGrid::with_seed(size, num_types, 0.0, radius, affinity_array, seed, None);
+
+        let mut occupied = 0;
+        for (x, y, pixel) in pixels.enumerate_pixels() {
+            let [r, g, b, a] = pixel.0;
+            let t = if a < ALPHA_CUTOFF { 0 } else { nearest_color(&palette, [r, g, b]) };
+            grid.type_grid[x as usize][y as usize] = t;
+            if t != 0 {
+                occupied += 1;
+            }
+        }
+        grid.density = occupied as f32 / (size * size).max(1) as f32;
+        grid.refresh_output();
+        Ok(grid)
+    }
+}
+
+fn nearest_color(palette: &[[u8; 3]], rgb: [u8; 3]) -> u8 {
+    let dist = |c: &[u8; 3]| {
+        c.iter()
+            .zip(rgb)
+            .map(|(&a, b)| (a as i32 - b as i32).pow(2))
+            .sum::<i32>()
+    };
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, c)| dist(c))
+        .map_or(0, |(t, _)| t as u8)
+}
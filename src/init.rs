@@ -0,0 +1,31 @@
+// Reseeding a running grid with one of the layouts in core/init.rs.
+
+use crate::core::InitPattern;
+use crate::ParticleGrid;
+
+impl ParticleGrid {
+    // Replace the grid with a fresh layout at the current density, keeping
+    // the rules (and symmetry mode). Also restarts the simulation clock and
+    // history.
+    pub fn reseed(&mut self, pattern: InitPattern) {
+        self.fill_symmetric(pattern);
+        self.reset_history();
+    }
+
+    pub(crate) fn reset_history(&mut self) {
+        self.generation = 0;
+        self.updates_performed = 0;
+        self.change_history.clear();
+        self.recent_hashes.clear();
+        self.cycle_period = None;
+        // A reseeded grid starts with fresh reserves
+        if let Some(energy) = &mut self.sim.energy {
+            energy.reset();
+        }
+        // and with new particles
+        if let Some(ids) = &mut self.sim.ids {
+            ids.reset(&self.sim.type_grid);
+        }
+        self.refresh_output();
+    }
+}
@@ -1,309 +1,539 @@
 use wasm_bindgen::prelude::*;
 use rand::prelude::*;
+
+use std::ops::{Deref, DerefMut};
+
+use config::SimulationConfig;
+use core::rules;
+use core::{EnergyConfig, Simulation, StepStats, TypeChange};
 // use std::fmt;
 
-// Import console.log for debugging
-#[wasm_bindgen]
-extern "C" {
-    #[wasm_bindgen(js_namespace = console)]
-    fn log(s: &str);
+// Leveled logging; the message is only formatted when its level is enabled
+macro_rules! log_at {
+    ($level:expr, $($t:tt)*) => {
+        if $crate::logging::enabled($level) {
+            $crate::logging::write($level, &format!($($t)*));
+        }
+    };
+}
+
+// Only the image encoders warn
+#[allow(unused_macros)]
+macro_rules! log_warn {
+    ($($t:tt)*) => (log_at!($crate::logging::LogLevel::Warn, $($t)*))
 }
 
-// Macro for console logging from Rust
-macro_rules! console_log {
-    ($($t:tt)*) => (log(&format_args!($($t)*).to_string()))
+macro_rules! log_info {
+    ($($t:tt)*) => (log_at!($crate::logging::LogLevel::Info, $($t)*))
 }
 
+macro_rules! log_debug {
+    ($($t:tt)*) => (log_at!($crate::logging::LogLevel::Debug, $($t)*))
+}
+
+// Profiling span covering the rest of the enclosing block (trace feature)
+#[cfg(feature = "trace")]
+macro_rules! trace_span {
+    ($name:expr) => {
+        let _span = $crate::trace::Span::enter($name);
+    };
+}
+
+#[cfg(not(feature = "trace"))]
+macro_rules! trace_span {
+    ($name:expr) => {};
+}
+
+#[cfg(feature = "ndarray")]
+mod array;
+mod auto_reseed;
+mod compress;
+pub mod config;
+pub mod coupled;
+pub mod core;
+pub mod edit;
+pub mod features;
+#[cfg(all(feature = "webgpu", target_arch = "wasm32"))]
+pub mod gpu;
+#[cfg(feature = "analysis")]
+mod graph;
+#[cfg(feature = "images")]
+mod image_init;
+pub mod init;
+pub mod logging;
+#[cfg(feature = "analysis")]
+pub mod metrics;
+pub mod movement;
+#[cfg(feature = "analysis")]
+pub mod optimize;
+pub mod patch;
+mod pattern;
+pub mod philox;
+#[cfg(all(feature = "python", not(target_arch = "wasm32")))]
+mod python;
+#[cfg(all(any(feature = "webgpu", feature = "webgl"), target_arch = "wasm32"))]
+pub mod render;
+mod rule_dsl;
+mod save;
+pub mod selection;
+#[cfg(feature = "images")]
+pub mod stream;
+mod subcell;
+pub mod symmetry;
+pub mod sync;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod timeline;
+mod trails;
+#[cfg(feature = "trace")]
+mod trace;
+mod trajectory;
+mod wasm;
+#[cfg(feature = "native")]
+pub mod serve;
+#[cfg(feature = "native")]
+pub mod sweep;
+
+// Re-exported so JS can spin up the worker pool before the first step
+#[cfg(all(feature = "parallel", target_arch = "wasm32"))]
+pub use wasm_bindgen_rayon::init_thread_pool;
+
 #[wasm_bindgen]
 pub struct ParticleGrid {
-    size: usize,
-    num_types: usize,
-    density: f32,
-    radius: usize,
-    type_grid: Vec<Vec<u8>>,
-    affinity: Vec<Vec<i8>>,
-    copy_type: Vec<u8>,
-    replace_type: Vec<u8>,
-    // colors: Vec<[f32; 3]>,
-    rng: ThreadRng,
+    sim: Simulation,
+    colors: Vec<[u8; 3]>,
+    type_names: Vec<String>,
+    output: Option<OutputBuffer>,
+    trails: Option<trails::Trails>,
+    // Per edit call, the cells it changed and their previous types
+    undo: Vec<Vec<(usize, usize, u8)>>,
+    // Cells written by the edit call in progress
+    journal: Option<edit::Journal>,
+    // Draw particles at their id's quadrant (needs particle ids)
+    subcell_jitter: bool,
+    trajectories: trajectory::Trajectories,
+    auto_reseed: Option<auto_reseed::AutoReseed>,
+    sync: Option<sync::Sync>,
+}
+
+// The wrapper only adds presentation state, so simulation fields and
+// methods are reachable straight through it
+impl Deref for ParticleGrid {
+    type Target = Simulation;
+
+    fn deref(&self) -> &Simulation {
+        &self.sim
+    }
+}
+
+impl DerefMut for ParticleGrid {
+    fn deref_mut(&mut self) -> &mut Simulation {
+        &mut self.sim
+    }
+}
+
+// Persistent export target; `data` is never reallocated while attached
+struct OutputBuffer {
+    rgba: bool,
+    data: Vec<u8>,
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThumbnailMode {
+    Majority = 0,
+    Density = 1,
 }
 
 #[wasm_bindgen]
 impl ParticleGrid {
     #[wasm_bindgen(constructor)]
     pub fn new(
-        size: usize, 
-        num_types: usize, 
-        density: f32, 
-        radius: usize, 
-        affinity_array: Option<Vec<i32>>
+        size: usize,
+        num_types: usize,
+        density: f32,
+        radius: usize,
+        affinity_array: Option<Vec<i32>>,
+        type_densities: Option<Vec<f32>>,
     ) -> ParticleGrid {
-        console_log!("Creating ParticleGrid: {}x{}, {} types, density {:.2}, radius {}", 
-            size, size, num_types, density, radius);
-            
-        let mut rng = thread_rng();
-
-        // Initialize grid with random particles
-        let mut type_grid = vec![vec![0u8; size]; size];
-        for x in 0..size {
-            for y in 0..size {
-                if rng.gen::<f32>() < density {
-                    type_grid[x][y] = rng.gen_range(1..=num_types as u8);
-                }
-            }
-        }
-
-        // Initialize affinity matrix
-        let mut affinity = vec![vec![0i8; num_types + 1]; num_types + 1];
-        if let Some(aff_array) = affinity_array {
-            if aff_array.len() >= (num_types + 1) * (num_types + 1) {
-                let mut idx = 0;
-                for t in 0..=num_types {
-                    for u in 0..=num_types {
-                        affinity[t][u] = aff_array[idx] as i8;
-                        idx += 1;
-                    }
-                }
-                console_log!("Used custom affinity matrix");
-            } else {
-                console_log!("Custom affinity array too small, using random");
-                Self::randomize_affinity(&mut affinity, num_types, &mut rng);
-            }
-        } else {
-            Self::randomize_affinity(&mut affinity, num_types, &mut rng);
-        }
-
-        // Initialize copy_type array
-        let mut copy_type = vec![0u8; num_types + 1];
-        for t in 0..=num_types {
-            let choices: Vec<u8> = (1..=num_types as u8)
-                .filter(|&c| c != t as u8)
-                .collect();
-            copy_type[t] = *choices
-                .choose(&mut rng)
-                .unwrap_or(&((t as u8 % num_types as u8) + 1));
-        }
-
-        // Initialize replace_type array
-        let mut replace_type = vec![0u8; num_types + 1];
-        for t in 0..=num_types {
-            let choices: Vec<u8> = (1..=num_types as u8)
-                .filter(|&c| c != t as u8 && c != copy_type[t])
-                .collect();
-            if choices.is_empty() {
-                replace_type[t] = if num_types >= 1 { 1 } else { 0 };
-            } else {
-                replace_type[t] = *choices.choose(&mut rng).unwrap();
-            }
-        }
-
-        // Initialize color palette
-        // let mut colors = vec![[0.1, 0.1, 0.1]; num_types + 1];
-        // for t in 1..=num_types {
-        //     let h = (t as f32 - 1.0) / (num_types as f32);
-        //     // colors[t] = Self::hsv_to_rgb(h, 0.8, 1.0);
-        // }
-
-        console_log!("ParticleGrid initialized successfully");
+        Self::with_seed(size, num_types, density, radius, affinity_array, thread_rng().gen(), type_densities)
+    }
 
-        ParticleGrid {
+    // Same as the constructor, but every random choice (initial grid,
+    // random affinities and reactions, and all later steps) is drawn from
+    // `seed`, so two grids built with the same arguments evolve identically.
+    // `type_densities`, indexed by type with entry 0 ignored, gives each
+    // type its own starting density and overrides `density` (which becomes
+    // their sum). Totals above 1 are scaled down to fit.
+    #[wasm_bindgen]
+    pub fn with_seed(
+        size: usize,
+        num_types: usize,
+        density: f32,
+        radius: usize,
+        affinity_array: Option<Vec<i32>>,
+        seed: u64,
+        type_densities: Option<Vec<f32>>,
+    ) -> ParticleGrid {
+        let n = num_types + 1;
+        let affinity = affinity_array
+            .map(|values| values.chunks(n).map(|row| row.iter().map(|&v| v as i8).collect()).collect())
+            .unwrap_or_default();
+        Self::from_config(&SimulationConfig {
             size,
             num_types,
             density,
+            type_densities,
             radius,
-            type_grid,
+            seed: Some(seed),
             affinity,
-            copy_type,
-            replace_type,
-            // colors,
-            rng,
-        }
+            ..SimulationConfig::default()
+        })
     }
 
-    fn randomize_affinity(affinity: &mut Vec<Vec<i8>>, num_types: usize, rng: &mut ThreadRng) {
-        for t in 0..=num_types {
-            for u in 0..=num_types {
-                affinity[t][u] = if rng.gen_bool(0.5) { 1 } else { -1 };
-            }
+    #[wasm_bindgen]
+    pub fn step(&mut self) {
+        if let Err(e) = self.try_step() {
+            raise(&e);
         }
     }
 
-    // fn hsv_to_rgb(h: f32, s: f32, v: f32) -> [f32; 3] {
-    //     let h6 = (h * 6.0).fract();
-    //     let i = (h * 6.0).floor() as i32 % 6;
-    //     let f = h6;
-    //     let p = v * (1.0 - s);
-    //     let q = v * (1.0 - f * s);
-    //     let t = v * (1.0 - (1.0 - f) * s);
-        
-    //     match i {
-    //         0 => [v, t, p],
-    //         1 => [q, v, p],
-    //         2 => [p, v, t],
-    //         3 => [p, q, v],
-    //         4 => [t, p, v],
-    //         _ => [v, p, q],
-    //     }
-    // }
-
-    // #[inline]
-    // fn inside(&self, x: isize, y: isize) -> bool {
-    //     x >= 0 && y >= 0 && (x as usize) < self.size && (y as usize) < self.size
-    // }
-
-    fn try_replace_particle(&mut self, x: usize, y: usize) {
-        let p_type = self.type_grid[x][y];
-        if p_type == 0 {
-            return;
-        }
+    // Debug mode: after every step, check that the grid and rule tables are
+    // well formed, every cell holds a known type and the step's moves and
+    // reactions stayed on the grid and within reach, throwing an error that
+    // names the first violation. With `conservation` the particle count
+    // must also be unchanged by each step.
+    #[wasm_bindgen]
+    pub fn enable_invariant_checks(&mut self, conservation: bool) {
+        self.sim.enable_invariant_checks(conservation);
+    }
 
-        let ct = self.copy_type[p_type as usize];
-        let rt = self.replace_type[p_type as usize];
+    #[wasm_bindgen]
+    pub fn disable_invariant_checks(&mut self) {
+        self.sim.disable_invariant_checks();
+    }
 
-        // Look for copy_type neighbor
-        let mut has_copy_neighbor = false;
-        for j in (y.saturating_sub(1))..=((y + 1).min(self.size - 1)) {
-            for i in (x.saturating_sub(1))..=((x + 1).min(self.size - 1)) {
-                if self.type_grid[i][j] == ct {
-                    has_copy_neighbor = true;
-                    break;
-                }
-            }
-            if has_copy_neighbor {
-                break;
+    // Schedule a parameter to reach `value` at generation `step`, easing
+    // linearly from its previous keyframe. `param` is one of "radius",
+    // "attraction_scale", "repulsion_scale", "replace_probability",
+    // "anisotropy_x", "anisotropy_y", "crowding", "update_fraction",
+    // "softmax_sharpness", "noise:<type>" or "affinity:<from>:<to>".
+    // Returns false if the parameter is not recognised.
+    #[wasm_bindgen]
+    pub fn add_keyframe(&mut self, step: f64, param: &str, value: f32) -> bool {
+        self.sim.add_keyframe(step.max(0.0) as u64, param, value)
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_keyframes(&mut self) {
+        self.sim.clear_keyframes();
+    }
+
+    // Feed a 0-1 control signal (e.g. an audio level) to `param`: one of
+    // "attraction", "repulsion", "radius_weight", "noise" or
+    // "affinity:<from>:<to>".
+    // 0.5 leaves the parameter as configured, 0 mutes it and 1 doubles it;
+    // the effect eases in over the next few steps. Meant to be called every
+    // frame. Returns false if the parameter is not recognised.
+    #[wasm_bindgen]
+    pub fn set_modulation(&mut self, param: &str, value: f32) -> bool {
+        self.sim.set_modulation(param, value)
+    }
+
+    // How quickly modulation follows its input, from 0.01 (slow) to 1
+    // (instant). Default 0.2.
+    #[wasm_bindgen]
+    pub fn set_modulation_smoothing(&mut self, smoothing: f32) {
+        self.sim.set_modulation_smoothing(smoothing);
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_modulation(&mut self) {
+        self.sim.clear_modulation();
+    }
+
+    // Only sample and update particles inside the rectangle (x0, y0)-(x1, y1)
+    // (inclusive, clamped to the grid). The update budget shrinks with the
+    // area, so the region evolves at the same pace as the whole grid would.
+    #[wasm_bindgen]
+    pub fn set_active_region(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        self.sim.set_active_region(x0, y0, x1, y1);
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_active_region(&mut self) {
+        self.sim.clear_active_region();
+    }
+
+    // Step in tiles from now on: the scheme the worker pool of a `parallel`
+    // build and GpuStepper share, so the grid comes out the same whichever
+    // runs it. `tile_size` defaults to 32 cells and is raised to what the
+    // radii need.
+    #[wasm_bindgen]
+    pub fn enable_tiling(&mut self, tile_size: Option<usize>) {
+        self.sim.enable_tiling(tile_size.unwrap_or(core::DEFAULT_TILE_SIZE));
+    }
+
+    #[wasm_bindgen]
+    pub fn disable_tiling(&mut self) {
+        self.sim.disable_tiling();
+    }
+
+    // Requested tile size, undefined while stepping sequentially
+    #[wasm_bindgen(getter)]
+    pub fn tile_size(&self) -> Option<usize> {
+        self.sim.tile_size()
+    }
+
+    // FNV-1a hash of the grid size and contents. Equal grids hash equally
+    // across runs and platforms, so this doubles as a regression check.
+    #[wasm_bindgen]
+    pub fn state_hash(&self) -> u64 {
+        self.sim.state_hash()
+    }
+
+    // Watch for the grid revisiting one of its last `max_period` states.
+    // Hashing costs a pass over the grid per step, so this is off until
+    // enabled; 0 turns it back off.
+    #[wasm_bindgen]
+    pub fn enable_cycle_detection(&mut self, max_period: usize) {
+        self.sim.enable_cycle_detection(max_period);
+    }
+
+    // Period of the loop the simulation is in (1 means frozen), or
+    // undefined if no repeat has been seen in the most recent step
+    #[wasm_bindgen]
+    pub fn cycle_period(&self) -> Option<u32> {
+        self.sim.cycle_period()
+    }
+
+    // Number of steps is_converged() looks back over (default 50)
+    #[wasm_bindgen]
+    pub fn set_convergence_window(&mut self, steps: usize) {
+        self.sim.set_convergence_window(steps);
+    }
+
+    // Total cells changed (moves count two, conversions one) over the
+    // convergence window
+    #[wasm_bindgen]
+    pub fn recent_changes(&self) -> usize {
+        self.sim.recent_changes()
+    }
+
+    // True once a full window has been observed and, on average, no more
+    // than `threshold` of the grid's cells changed per step. A threshold of
+    // 0 only accepts a completely frozen grid.
+    #[wasm_bindgen]
+    pub fn is_converged(&self, threshold: f64) -> bool {
+        self.sim.is_converged(threshold)
+    }
+
+    #[wasm_bindgen]
+    pub fn export_grid(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.size * self.size);
+        for y in 0..self.size {
+            for x in 0..self.size {
+                data.push(self.type_grid[x][y]);
             }
         }
+        data
+    }
 
-        if !has_copy_neighbor {
-            return;
+    // Overwrite the grid with cells in export_grid's layout, e.g. read back
+    // from a GpuSimulation, or with export_grid_compressed output. Returns
+    // false (and changes nothing) if the length is wrong, the data does not
+    // decompress or a cell holds an unknown type.
+    #[wasm_bindgen]
+    pub fn import_grid(&mut self, cells: Vec<u8>) -> bool {
+        let Some(cells) = self.import_cells(cells) else {
+            return false;
+        };
+        if cells.iter().any(|&t| t as usize > self.num_types) {
+            return false;
         }
+        self.set_cells(&cells);
+        self.refresh_output();
+        true
+    }
 
-        // Replace all rt with ct in neighborhood
-        for j in (y.saturating_sub(1))..=((y + 1).min(self.size - 1)) {
-            for i in (x.saturating_sub(1))..=((x + 1).min(self.size - 1)) {
-                if self.type_grid[i][j] == rt {
-                    self.type_grid[i][j] = ct;
-                }
+    // Window of the grid starting at (x0, y0), `w` x `h` cells in the same
+    // row-major layout as export_grid. Cells past the grid edge read as
+    // empty, so the result is always w * h long. Windows wider or taller
+    // than three grid widths (the grid plus a grid of padding either side),
+    // or whose far edge overflows usize, give an empty result.
+    #[wasm_bindgen]
+    pub fn export_region(&self, x0: usize, y0: usize, w: usize, h: usize) -> Vec<u8> {
+        let Some((x1, y1, cells)) = self.region_window(x0, y0, w, h) else {
+            return Vec::new();
+        };
+        let mut data = Vec::with_capacity(cells);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                data.push(self.cell_or_empty(x, y));
             }
         }
+        data
     }
 
-    fn score_within_radius(&mut self, x: usize, y: usize) -> (usize, usize) {
-        let p_type = self.type_grid[x][y];
-        let mut best: f32 = -1_000_000.0;
-        let mut tiebreak: Vec<(usize, usize)> = vec![(x, y)];
+    // export_region as RGBA pixels using the type palette
+    #[wasm_bindgen]
+    pub fn export_region_rgba(&self, x0: usize, y0: usize, w: usize, h: usize) -> Vec<u8> {
+        let Some((x1, y1, cells)) = self.region_window(x0, y0, w, h) else {
+            return Vec::new();
+        };
+        let Some(bytes) = cells.checked_mul(4) else {
+            return Vec::new();
+        };
+        let mut data = Vec::with_capacity(bytes);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                data.extend_from_slice(&self.rgba(self.cell_or_empty(x, y)));
+            }
+        }
+        data
+    }
 
-        // Check adjacent empty cells
-        for j in (y.saturating_sub(1))..=((y + 1).min(self.size - 1)) {
-            for i in (x.saturating_sub(1))..=((x + 1).min(self.size - 1)) {
-                if self.type_grid[i][j] != 0 {
-                    continue;
-                }
+    // Downsampled RGBA image of the whole grid, `target_size` pixels square.
+    // Each pixel covers a block of cells and shows its most common particle
+    // type; in Density mode that color is also dimmed by how full the
+    // block is.
+    #[wasm_bindgen]
+    pub fn export_thumbnail(&self, target_size: usize, mode: ThumbnailMode) -> Vec<u8> {
+        let mut data = Vec::with_capacity(target_size * target_size * 4);
+        if self.size == 0 {
+            data.resize(target_size * target_size * 4, 0);
+            return data;
+        }
 
-                let mut score = 0i32;
-                let mut cell_count = 0i32;
-
-                // Calculate bounds for scoring region
-                let rx0 = i.saturating_sub(self.radius);
-                let rx1 = (i + self.radius).min(self.size - 1);
-                let ry0 = j.saturating_sub(self.radius);
-                let ry1 = (j + self.radius).min(self.size - 1);
-
-                // Score calculation
-                for yy in ry0..=ry1 {
-                    for xx in rx0..=rx1 {
-                        cell_count += 1;
-                        let ct = self.type_grid[xx][yy];
-                        if ct != 0 {
-                            let a = self.affinity[p_type as usize][ct as usize];
-                            score += if a == 1 { 1 } else { -1 };
-                        }
+        let block_bounds = |p: usize| {
+            let start = p * self.size / target_size;
+            let end = ((p + 1) * self.size / target_size).max(start + 1).min(self.size);
+            start..end
+        };
+
+        let mut counts = vec![0usize; self.num_types + 1];
+        for py in 0..target_size {
+            for px in 0..target_size {
+                counts.iter_mut().for_each(|c| *c = 0);
+                let mut cells = 0;
+                for y in block_bounds(py) {
+                    for x in block_bounds(px) {
+                        counts[self.type_grid[x][y] as usize] += 1;
+                        cells += 1;
                     }
                 }
 
-                let norm = score as f32 / (cell_count as f32).max(1.0);
-                if norm > best {
-                    best = norm;
-                    tiebreak.clear();
-                    tiebreak.push((i, j));
-                } else if (norm - best).abs() < f32::EPSILON {
-                    tiebreak.push((i, j));
+                // Most common particle type, lowest index on ties
+                let (majority, _) = counts
+                    .iter()
+                    .enumerate()
+                    .skip(1)
+                    .fold((0, 0), |best, (t, &c)| if c > best.1 { (t, c) } else { best });
+                let mut pixel = self.rgba(majority as u8);
+                if mode == ThumbnailMode::Density && cells > 0 {
+                    let fill = counts[1..].iter().sum::<usize>() as f32 / cells as f32;
+                    for channel in &mut pixel[..3] {
+                        *channel = (*channel as f32 * fill) as u8;
+                    }
                 }
+                data.extend_from_slice(&pixel);
             }
         }
-
-        if tiebreak.is_empty() {
-            (x, y)
-        } else {
-            *tiebreak.choose(&mut self.rng).unwrap()
-        }
+        data
     }
 
-    fn move_particle(&mut self, x: usize, y: usize) {
-        let p_type = self.type_grid[x][y];
-        if p_type == 0 {
-            return;
-        }
-
-        let (bx, by) = self.score_within_radius(x, y);
-        if bx == x && by == y {
-            return;
-        }
-
-        self.type_grid[bx][by] = p_type;
-        self.type_grid[x][y] = 0;
+    // Keep a long-lived export buffer inside wasm memory and rewrite it in
+    // place after every step (types, or RGBA when `rgba` is set). Its
+    // address from output_ptr() stays valid until the buffer is detached or
+    // reattached, so a render worker can keep a view on the shared memory,
+    // e.g. `new Uint8Array(memory.buffer, grid.output_ptr(), grid.output_len())`.
+    #[wasm_bindgen]
+    pub fn attach_output_buffer(&mut self, rgba: bool) {
+        let len = self.size * self.size * if rgba { 4 } else { 1 };
+        self.output = Some(OutputBuffer {
+            rgba,
+            data: vec![0; len],
+        });
+        self.refresh_output();
     }
 
     #[wasm_bindgen]
-    pub fn step(&mut self) {
-        let total_cells = (self.size * self.size) as f32;
-        let updates = (0.2 * self.density * total_cells).floor() as usize;
+    pub fn detach_output_buffer(&mut self) {
+        self.output = None;
+    }
 
-        if updates == 0 {
+    // Rewrite the attached buffer from the current grid; step() already
+    // does this, so it is only needed after edits between steps
+    #[wasm_bindgen]
+    pub fn refresh_output(&mut self) {
+        let Some(output) = self.output.as_mut() else {
             return;
-        }
-
-        // Collect current non-empty cells
-        let mut particles: Vec<(usize, usize)> = Vec::new();
-        particles.reserve(self.size * self.size / 2);
-        
-        for x in 0..self.size {
-            for y in 0..self.size {
-                if self.type_grid[x][y] != 0 {
-                    particles.push((x, y));
+        };
+        trace_span!("output");
+        // Cells are stored by column; the buffer is row-major
+        let size = self.sim.size;
+        for (x, column) in self.sim.type_grid.iter().enumerate() {
+            for (y, &t) in column.iter().enumerate() {
+                let i = y * size + x;
+                if output.rgba {
+                    let [r, g, b] = self.colors.get(t as usize).copied().unwrap_or([0, 0, 0]);
+                    output.data[i * 4..i * 4 + 4].copy_from_slice(&[r, g, b, 255]);
+                } else {
+                    output.data[i] = t;
                 }
             }
         }
+    }
 
-        if particles.is_empty() {
-            return;
-        }
+    // Address of the attached buffer in wasm memory (null if none)
+    #[wasm_bindgen]
+    pub fn output_ptr(&self) -> *const u8 {
+        self.output.as_ref().map_or(std::ptr::null(), |o| o.data.as_ptr())
+    }
 
-        // Update random particles
-        for _ in 0..updates {
-            if particles.is_empty() {
-                break;
-            }
-            let idx = self.rng.gen_range(0..particles.len());
-            let (x, y) = particles[idx];
-            
-            // Check if particle still exists
-            if self.type_grid[x][y] == 0 {
-                particles.swap_remove(idx);
-                continue;
-            }
+    #[wasm_bindgen]
+    pub fn output_len(&self) -> usize {
+        self.output.as_ref().map_or(0, |o| o.data.len())
+    }
 
-            self.try_replace_particle(x, y);
-            self.move_particle(x, y);
+    // Far corner (exclusive) and cell count of an export_region window, or
+    // None if it is too large to export
+    fn region_window(&self, x0: usize, y0: usize, w: usize, h: usize) -> Option<(usize, usize, usize)> {
+        let limit = self.size.saturating_mul(3);
+        if w > limit || h > limit {
+            return None;
         }
+        Some((x0.checked_add(w)?, y0.checked_add(h)?, w.checked_mul(h)?))
     }
 
+    #[inline]
+    fn cell_or_empty(&self, x: usize, y: usize) -> u8 {
+        if x < self.size && y < self.size { self.type_grid[x][y] } else { 0 }
+    }
+
+    #[inline]
+    fn rgba(&self, t: u8) -> [u8; 4] {
+        let [r, g, b] = self.colors.get(t as usize).copied().unwrap_or([0, 0, 0]);
+        [r, g, b, 255]
+    }
+
+    // Score a particle of type `p_type` would get in each empty cell, in the
+    // same row-major layout as export_grid. Occupied cells are NaN. Returns
+    // an empty array for an unknown type.
     #[wasm_bindgen]
-    pub fn export_grid(&self) -> Vec<u8> {
+    pub fn export_score_field(&self, p_type: u8) -> Vec<f32> {
+        if p_type == 0 || p_type as usize > self.num_types {
+            return Vec::new();
+        }
+
         let mut data = Vec::with_capacity(self.size * self.size);
         for y in 0..self.size {
             for x in 0..self.size {
-                data.push(self.type_grid[x][y]);
+                if self.type_grid[x][y] == 0 {
+                    data.push(self.rules.cell_score(&self.type_grid, p_type, x, y));
+                } else {
+                    data.push(f32::NAN);
+                }
             }
         }
         data
@@ -325,36 +555,49 @@ impl ParticleGrid {
         self.density
     }
 
+    // Starting density of each type, indexed by type (entry 0 is 0)
+    #[wasm_bindgen(getter)]
+    pub fn type_densities(&self) -> Vec<f32> {
+        match &self.type_densities {
+            Some(d) => d.clone(),
+            None => {
+                let each = self.density / self.num_types.max(1) as f32;
+                (0..=self.num_types).map(|t| if t == 0 { 0.0 } else { each }).collect()
+            }
+        }
+    }
+
     #[wasm_bindgen(getter)]
     pub fn radius(&self) -> usize {
-        self.radius
+        self.rules.radius
+    }
+
+    // Number of step() calls so far (f64 so JS gets a plain number)
+    #[wasm_bindgen(getter)]
+    pub fn generation(&self) -> f64 {
+        self.generation as f64
+    }
+
+    // Cumulative particle updates across all steps
+    #[wasm_bindgen(getter)]
+    pub fn updates_performed(&self) -> f64 {
+        self.updates_performed as f64
     }
 
     // Debug method
     #[wasm_bindgen]
     pub fn debug_info(&self) -> String {
         format!(
-            "Grid {}x{}, {} types, density {:.2}, radius {}, particles: {}",
+            "Grid {}x{}, {} types, density {:.2}, radius {}, particles: {}, generation {}",
             self.size,
             self.size,
             self.num_types,
             self.density,
-            self.radius,
-            self.count_particles()
+            self.rules.radius,
+            self.count_particles(),
+            self.generation
         )
     }
-
-    fn count_particles(&self) -> usize {
-        let mut count = 0;
-        for x in 0..self.size {
-            for y in 0..self.size {
-                if self.type_grid[x][y] != 0 {
-                    count += 1;
-                }
-            }
-        }
-        count
-    }
 }
 
 #[wasm_bindgen]
@@ -365,18 +608,445 @@ impl ParticleGrid {
             let mut idx = 0;
             for t in 0..=self.num_types {
                 for u in 0..=self.num_types {
-                    self.affinity[t][u] = new_affinity[idx] as i8;
+                    self.rules.affinity[t][u] = new_affinity[idx] as i8;
                     idx += 1;
                 }
             }
         }
     }
-    
+
+    // Tune the copy/replace reaction: the neighborhood radius it scans, the
+    // chance it fires on a given update, and how many cells it may convert
+    // at once (0 = no limit). Defaults are 1, 1.0 and 0.
+    #[wasm_bindgen]
+    pub fn set_replace_params(&mut self, radius: usize, trigger_probability: f32, max_conversions: usize) {
+        self.rules.replace_radius = radius;
+        self.rules.replace_probability = trigger_probability.clamp(0.0, 1.0);
+        self.rules.max_conversions = max_conversions;
+    }
+
+    // Scale attraction and repulsion independently, e.g. (1.0, 3.0) makes
+    // avoidance three times stronger than attraction. Affinity values
+    // beyond +-1 act as per-pair weights on top of these. Negative or
+    // non-finite scales are ignored.
+    #[wasm_bindgen]
+    pub fn set_interaction_weights(&mut self, attraction_scale: f32, repulsion_scale: f32) {
+        if attraction_scale.is_finite() && attraction_scale >= 0.0 {
+            self.rules.attraction_scale = attraction_scale;
+        }
+        if repulsion_scale.is_finite() && repulsion_scale >= 0.0 {
+            self.rules.repulsion_scale = repulsion_scale;
+        }
+    }
+
+    // Penalize crowded cells: each candidate cell loses `weight` times the
+    // squared occupied fraction of its neighborhood, whatever the types in
+    // it. Around 0.5-2 keeps strongly attracted types from fusing into one
+    // solid blob; 0 (the default) turns it off. Negative or non-finite
+    // weights are ignored.
+    #[wasm_bindgen]
+    pub fn set_crowding_penalty(&mut self, weight: f32) {
+        if weight.is_finite() && weight >= 0.0 {
+            self.rules.crowding = weight;
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn crowding_penalty(&self) -> f32 {
+        self.rules.crowding
+    }
+
+    // Particle updates per step as a fraction of the starting particle
+    // count: 0.2 (the default) updates a fifth of them. Lower values give
+    // smoother slow motion, higher ones (up to 10) race ahead. Non-finite
+    // values are ignored; others are clamped to 0-10.
+    #[wasm_bindgen]
+    pub fn set_update_fraction(&mut self, fraction: f32) {
+        if fraction.is_finite() {
+            self.rules.update_fraction = crate::core::clamp_update_fraction(fraction);
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn update_fraction(&self) -> f32 {
+        self.rules.update_fraction
+    }
+
+    // Per-cell speed in export_grid's layout: 1 is normal, 0.2 a sluggish
+    // mud patch, 0 holds particles still and 2 an ice patch where they move
+    // twice as often (speeds are capped at 8). Returns false if the length
+    // is not size * size.
+    #[wasm_bindgen]
+    pub fn set_terrain(&mut self, speeds: Vec<f32>) -> bool {
+        self.sim.set_terrain(&speeds)
+    }
+
+    // Back to uniform ground
+    #[wasm_bindgen]
+    pub fn clear_terrain(&mut self) {
+        self.sim.clear_terrain();
+    }
+
+    // Pin the particle at (x, y) so it neither moves nor gets converted,
+    // while still scoring for its neighbors and converting them. Returns
+    // false for an empty cell. A pin goes away with its particle.
+    #[wasm_bindgen]
+    pub fn pin_cell(&mut self, x: usize, y: usize) -> bool {
+        self.sim.pin_cell(x, y)
+    }
+
+    #[wasm_bindgen]
+    pub fn unpin_cell(&mut self, x: usize, y: usize) {
+        self.sim.unpin_cell(x, y);
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_pins(&mut self) {
+        self.sim.clear_pins();
+    }
+
+    #[wasm_bindgen]
+    pub fn is_pinned(&self, x: usize, y: usize) -> bool {
+        self.sim.is_pinned(x, y)
+    }
+
+    // Hold type `t` near `fraction` (0-1) of all cells: after each step a
+    // little of the gap is closed by placing particles of the type on
+    // random empty cells or removing random ones. Returns false for an
+    // unknown type or a fraction outside 0-1.
+    #[wasm_bindgen]
+    pub fn set_target_density(&mut self, t: u8, fraction: f32) -> bool {
+        self.sim.set_target_density(t, fraction)
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_target_density(&mut self, t: u8) {
+        self.sim.clear_target_density(t);
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_target_densities(&mut self) {
+        self.sim.clear_target_densities();
+    }
+
+    // Target set for type `t`, if any
+    #[wasm_bindgen]
+    pub fn target_density(&self, t: u8) -> Option<f32> {
+        self.sim.target_density(t)
+    }
+
+    // Weight horizontal neighbors by `wx` and vertical ones by `wy` when
+    // scoring (diagonals get a blend), e.g. (2.0, 1.0) favors horizontal
+    // stripes. Negative or non-finite weights are ignored.
+    #[wasm_bindgen]
+    pub fn set_anisotropy(&mut self, wx: f32, wy: f32) {
+        if wx.is_finite() && wy.is_finite() && wx >= 0.0 && wy >= 0.0 {
+            self.rules.anisotropy = [wx, wy];
+        }
+    }
+
+    // [wx, wy]
+    #[wasm_bindgen(getter)]
+    pub fn anisotropy(&self) -> Vec<f32> {
+        self.rules.anisotropy.to_vec()
+    }
+
+    // Chance (0-1) that a particle of type `t` ignores its scores on an
+    // update and steps to a random free neighbor instead. 0 (the default)
+    // is fully greedy; mixing rigid and jittery types gives glassy phases.
+    #[wasm_bindgen]
+    pub fn set_type_noise(&mut self, t: u8, p: f32) {
+        if t != 0 && p.is_finite() {
+            if let Some(noise) = self.rules.noise.get_mut(t as usize) {
+                *noise = p.clamp(0.0, 1.0);
+            }
+        }
+    }
+
+    // Noise indexed by type (entry 0 is empty space)
+    #[wasm_bindgen]
+    pub fn get_type_noise(&self) -> Vec<f32> {
+        self.rules.noise.clone()
+    }
+
+    // Turn on the energy model from a JSON EnergyConfig (missing fields
+    // take their defaults, so "{}" works). Every particle starts with the
+    // initial reserve; moves, time and starvation then take their toll.
+    #[wasm_bindgen]
+    pub fn set_energy_config(&mut self, json: &str) -> Result<(), String> {
+        let config: EnergyConfig = serde_json::from_str(json).map_err(|e| format!("invalid energy config: {}", e))?;
+        self.sim.set_energy(Some(config));
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn disable_energy(&mut self) {
+        self.sim.set_energy(None);
+    }
+
+    // Particle energies in export_grid's layout, 0 for empty cells (and
+    // everywhere while the energy model is off)
+    #[wasm_bindgen]
+    pub fn export_energy(&self) -> Vec<f32> {
+        let mut data = Vec::with_capacity(self.size * self.size);
+        for y in 0..self.size {
+            for x in 0..self.size {
+                data.push(self.sim.particle_energy(x, y).unwrap_or(0.0));
+            }
+        }
+        data
+    }
+
+    // Start giving every particle a persistent id that follows it as it
+    // moves. A particle converted to another type is a new particle and
+    // gets a new id; particles placed by edits or a reseed get new ids too.
+    #[wasm_bindgen]
+    pub fn enable_particle_ids(&mut self) {
+        self.sim.track_identities(true);
+    }
+
+    // Drop the ids (and with them subcell jitter)
+    #[wasm_bindgen]
+    pub fn disable_particle_ids(&mut self) {
+        self.sim.track_identities(false);
+        self.subcell_jitter = false;
+    }
+
+    // Particle ids in export_grid's layout, 0 for empty cells (and
+    // everywhere while ids are off)
+    #[wasm_bindgen]
+    pub fn export_particle_ids(&mut self) -> Vec<u32> {
+        self.sim.sync_identities();
+        let mut data = vec![0; self.size * self.size];
+        if let Some(ids) = &self.sim.ids {
+            for (x, column) in ids.grid.iter().enumerate() {
+                for (y, &id) in column.iter().enumerate() {
+                    data[y * self.size + x] = id;
+                }
+            }
+        }
+        data
+    }
+
+    // Append a new particle type and return its index (0 if the 255-type
+    // limit is reached). `affinity_row` is the new type's affinity towards
+    // types 0..=new, `affinity_col` the affinity of types 0..num_types
+    // towards it; missing entries are filled at random. An empty `color`
+    // picks the default palette color.
+    #[wasm_bindgen]
+    pub fn add_type(&mut self, affinity_row: Vec<i32>, affinity_col: Vec<i32>, color: Vec<u8>) -> u8 {
+        let sim = &mut self.sim;
+        if sim.num_types >= u8::MAX as usize {
+            return 0;
+        }
+        let new_type = sim.num_types + 1;
+
+        for (u, row) in sim.rules.affinity.iter_mut().enumerate() {
+            let a = match affinity_col.get(u) {
+                Some(&v) => v as i8,
+                None => if sim.rng.gen_bool(0.5) { 1 } else { -1 },
+            };
+            row.push(a);
+        }
+        let row = (0..=new_type)
+            .map(|u| match affinity_row.get(u) {
+                Some(&v) => v as i8,
+                None => if sim.rng.gen_bool(0.5) { 1 } else { -1 },
+            })
+            .collect();
+        sim.rules.affinity.push(row);
+
+        let copy = rules::pick_copy_type(new_type as u8, new_type, &mut sim.rng);
+        let replace = rules::pick_replace_type(new_type as u8, copy, new_type, &mut sim.rng);
+        sim.rules.copy_type.push(copy);
+        sim.rules.replace_type.push(replace);
+
+        self.colors.push(match color.as_slice() {
+            [r, g, b, ..] => [*r, *g, *b],
+            _ => default_color(new_type as u8),
+        });
+        self.type_names.push(default_type_name(new_type));
+        if let Some(densities) = &mut sim.type_densities {
+            densities.push(0.0);
+        }
+        sim.rules.noise.push(0.0);
+        sim.retype_tables(TypeChange::Added(new_type as u8));
+
+        sim.num_types = new_type;
+        self.refresh_output();
+        new_type as u8
+    }
+
+    // Remove type `t`, turning its particles into `replacement` (0 deletes
+    // them). Higher types shift down by one to keep indices contiguous, and
+    // copy/replace rules that pointed at `t` follow the replacement or are
+    // re-picked. Other per-type settings for `t` (coupling, energy income,
+    // density target, modulation and keyframes) are dropped. The last
+    // remaining type cannot be removed.
+    #[wasm_bindgen]
+    pub fn remove_type(&mut self, t: u8, replacement: u8) -> bool {
+        let sim = &mut self.sim;
+        let t_idx = t as usize;
+        if t == 0 || t_idx > sim.num_types || sim.num_types <= 1
+            || replacement == t || replacement as usize > sim.num_types {
+            return false;
+        }
+
+        // Old index -> new index, with `t` itself mapped to the replacement
+        let shift = |c: u8| if c > t { c - 1 } else { c };
+        let remap = |c: u8| if c == t { shift(replacement) } else { shift(c) };
+
+        for column in sim.type_grid.iter_mut() {
+            for cell in column.iter_mut() {
+                *cell = remap(*cell);
+            }
+        }
+
+        sim.rules.affinity.remove(t_idx);
+        for row in sim.rules.affinity.iter_mut() {
+            row.remove(t_idx);
+        }
+        sim.rules.copy_type.remove(t_idx);
+        sim.rules.replace_type.remove(t_idx);
+        sim.rules.noise.remove(t_idx);
+        self.colors.remove(t_idx);
+        self.type_names.remove(t_idx);
+        if let Some(densities) = &mut sim.type_densities {
+            densities.remove(t_idx);
+        }
+        sim.retype_tables(TypeChange::Removed(t));
+        sim.num_types -= 1;
+
+        for u in 0..=sim.num_types {
+            let copy = sim.rules.copy_type[u];
+            sim.rules.copy_type[u] = if copy == t && replacement == 0 {
+                rules::pick_copy_type(u as u8, sim.num_types, &mut sim.rng)
+            } else {
+                remap(copy)
+            };
+            let replace = sim.rules.replace_type[u];
+            sim.rules.replace_type[u] = if replace == t && replacement == 0 {
+                rules::pick_replace_type(u as u8, sim.rules.copy_type[u], sim.num_types, &mut sim.rng)
+            } else {
+                remap(replace)
+            };
+        }
+        self.refresh_output();
+        true
+    }
+
+    // Palette as flat RGB triples, indexed by type (entry 0 is empty space)
+    #[wasm_bindgen]
+    pub fn get_colors(&self) -> Vec<u8> {
+        self.colors.iter().flatten().copied().collect()
+    }
+
+    #[wasm_bindgen]
+    pub fn set_type_color(&mut self, t: u8, r: u8, g: u8, b: u8) {
+        if let Some(color) = self.colors.get_mut(t as usize) {
+            *color = [r, g, b];
+        }
+    }
+
+    // Human-readable label for a type; an empty name restores the default
+    #[wasm_bindgen]
+    pub fn set_type_name(&mut self, t: u8, name: &str) {
+        if let Some(entry) = self.type_names.get_mut(t as usize) {
+            *entry = if name.is_empty() { default_type_name(t as usize) } else { name.to_string() };
+        }
+    }
+
+    // Labels indexed by type (entry 0 is empty space)
+    #[wasm_bindgen]
+    pub fn get_type_names(&self) -> Vec<String> {
+        self.type_names.clone()
+    }
+
     #[wasm_bindgen]
     pub fn update_copy_replace(&mut self, copy_types: Vec<u8>, replace_types: Vec<u8>) {
         if copy_types.len() > self.num_types && replace_types.len() > self.num_types {
-            self.copy_type = copy_types;
-            self.replace_type = replace_types;
+            self.rules.copy_type = copy_types;
+            self.rules.replace_type = replace_types;
+        }
+    }
+}
+
+impl ParticleGrid {
+    // step() for callers that report errors themselves; the presentation
+    // state is brought up to date either way
+    pub fn try_step(&mut self) -> Result<StepStats, String> {
+        trace_span!("step");
+        let result = self.sim.try_step();
+        if let Some(trails) = &mut self.trails {
+            trails.update(&self.sim.type_grid);
+        }
+        self.record_trajectories();
+        if let (Some(_), Ok(stats)) = (&self.auto_reseed, &result) {
+            self.check_auto_reseed(stats.changed_cells);
+        }
+        self.refresh_output();
+        result
+    }
+}
+
+// Throw a JS Error (wasm) or panic (native) with `message`
+fn raise(message: &str) -> ! {
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen::throw_str(message);
+    #[cfg(not(target_arch = "wasm32"))]
+    panic!("{}", message);
+}
+
+fn default_type_name(t: usize) -> String {
+    if t == 0 { "Empty".to_string() } else { format!("Type {}", t) }
+}
+
+// Default palette, matching the colors the web UI uses for each type
+fn default_color(t: u8) -> [u8; 3] {
+    match t {
+        0 => [0, 0, 0],
+        1 => [255, 102, 102],
+        2 => [102, 255, 102],
+        3 => [102, 102, 255],
+        4 => [255, 255, 102],
+        5 => [255, 102, 255],
+        6 => [102, 255, 255],
+        _ => {
+            let hue = ((t - 1) % 12) as f32 / 12.0;
+            let (s, v) = (0.8, 1.0);
+            let c = v * s;
+            let h_prime = hue * 6.0;
+            let x = c * (1.0 - ((h_prime % 2.0) - 1.0f32).abs());
+            let m = v - c;
+            let (r, g, b) = match h_prime as u32 {
+                0 => (c, x, 0.0),
+                1 => (x, c, 0.0),
+                2 => (0.0, c, x),
+                3 => (0.0, x, c),
+                4 => (x, 0.0, c),
+                _ => (c, 0.0, x),
+            };
+            [((r + m) * 255.0) as u8, ((g + m) * 255.0) as u8, ((b + m) * 255.0) as u8]
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_region_pads_and_rejects_overflow() {
+        let grid = ParticleGrid::from_config(&SimulationConfig { size: 4, seed: Some(1), ..Default::default() });
+        assert_eq!(grid.export_region(3, 3, 2, 2)[1..], [0, 0, 0]);
+        assert_eq!(grid.export_region_rgba(0, 0, 4, 4).len(), 64);
+        assert!(grid.export_region(usize::MAX, 0, 2, 1).is_empty());
+        assert!(grid.export_region_rgba(0, usize::MAX - 1, 1, 2).is_empty());
+        // Windows past three grid widths are refused rather than allocated
+        assert!(grid.export_region(0, 0, usize::MAX / 2, 4).is_empty());
+        assert!(grid.export_region_rgba(0, 0, usize::MAX / 2, 4).is_empty());
+        assert!(grid.export_region(0, 0, 13, 1).is_empty());
+        assert_eq!(grid.export_region(usize::MAX / 2, 0, 12, 12), vec![0; 144]);
+        assert_eq!(grid.export_region_rgba(2, 2, 12, 1).len(), 48);
+    }
+}
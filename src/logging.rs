@@ -0,0 +1,88 @@
+// Leveled logging. Messages go to the matching browser console method
+// (console.error, console.warn, ...) on wasm and to stderr natively. The
+// level is global and starts at Off in release builds (Warn in debug
+// builds) so embedding pages get a quiet console unless they opt in.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Once;
+
+use wasm_bindgen::prelude::*;
+
+use crate::core::{self, Level};
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Off = 0,
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+}
+
+const DEFAULT_LEVEL: LogLevel = if cfg!(debug_assertions) { LogLevel::Warn } else { LogLevel::Off };
+
+static LEVEL: AtomicU8 = AtomicU8::new(DEFAULT_LEVEL as u8);
+
+// Show messages at `level` and more severe; Off silences everything
+#[wasm_bindgen]
+pub fn set_log_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+#[wasm_bindgen]
+pub fn log_level() -> LogLevel {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => LogLevel::Off,
+        1 => LogLevel::Error,
+        2 => LogLevel::Warn,
+        3 => LogLevel::Info,
+        _ => LogLevel::Debug,
+    }
+}
+
+#[inline]
+pub(crate) fn enabled(level: LogLevel) -> bool {
+    level != LogLevel::Off && level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn write(level: LogLevel, message: &str) {
+    use web_sys::console;
+
+    let message = JsValue::from_str(message);
+    match level {
+        LogLevel::Error => console::error_1(&message),
+        LogLevel::Warn => console::warn_1(&message),
+        LogLevel::Info => console::info_1(&message),
+        LogLevel::Debug => console::debug_1(&message),
+        LogLevel::Off => {}
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn write(level: LogLevel, message: &str) {
+    if level != LogLevel::Off {
+        eprintln!("[{:?}] {}", level, message);
+    }
+}
+
+// Route the core's messages (core/log.rs) through the level filter above.
+// Installed on the first grid built; a hook set after that replaces it.
+pub(crate) fn install_core_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| core::set_log_hook(Some(forward)));
+}
+
+fn forward(level: Level, message: fmt::Arguments) {
+    let level = match level {
+        Level::Error => LogLevel::Error,
+        Level::Warn => LogLevel::Warn,
+        Level::Info => LogLevel::Info,
+        Level::Debug => LogLevel::Debug,
+    };
+    if enabled(level) {
+        write(level, &message.to_string());
+    }
+}
@@ -0,0 +1,211 @@
+// Summary statistics of a grid state, shared by the sweep runner and the
+// rule search, and the radial distribution function.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::core::BoundaryMode;
+use crate::selection::flood_fill;
+use crate::ParticleGrid;
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Metrics {
+    pub particles: usize,
+    // Connected same-type groups (8-connected, matching the 3x3 rules)
+    pub cluster_count: usize,
+    pub largest_cluster: usize,
+    pub mean_cluster_size: f64,
+    // Shannon entropy (bits) of the type mix among particles
+    pub type_entropy: f64,
+    // Types with at least one particle left, and their names
+    pub survivor_types: Vec<u8>,
+    pub survivor_names: Vec<String>,
+    // Shannon entropy (bits) of the 2x2 blocks of cells, empty space
+    // included: 0 for a uniform grid, low for regular patterns, highest
+    // for noise
+    pub pattern_entropy: f64,
+}
+
+impl ParticleGrid {
+    pub fn metrics(&self) -> Metrics {
+        trace_span!("metrics");
+        let mut counts = vec![0usize; self.num_types + 1];
+        for column in &self.type_grid {
+            for &t in column {
+                counts[t as usize] += 1;
+            }
+        }
+        let particles: usize = counts[1..].iter().sum();
+
+        let mut type_entropy = 0.0;
+        if particles > 0 {
+            for &c in &counts[1..] {
+                if c > 0 {
+                    let p = c as f64 / particles as f64;
+                    type_entropy -= p * p.log2();
+                }
+            }
+        }
+
+        let survivor_types: Vec<u8> = (1..=self.num_types)
+            .filter(|&t| counts[t] > 0)
+            .map(|t| t as u8)
+            .collect();
+        let survivor_names = survivor_types.iter().map(|&t| self.type_names[t as usize].clone()).collect();
+
+        let mut visited = vec![false; self.size * self.size];
+        let mut cluster_count = 0;
+        let mut largest_cluster = 0;
+        for x in 0..self.size {
+            for y in 0..self.size {
+                if self.type_grid[x][y] == 0 || visited[x * self.size + y] {
+                    continue;
+                }
+                let cells = flood_fill(&self.type_grid, x, y, &mut visited);
+                cluster_count += 1;
+                largest_cluster = largest_cluster.max(cells.len());
+            }
+        }
+
+        Metrics {
+            particles,
+            cluster_count,
+            largest_cluster,
+            mean_cluster_size: if cluster_count > 0 {
+                particles as f64 / cluster_count as f64
+            } else {
+                0.0
+            },
+            type_entropy,
+            survivor_types,
+            survivor_names,
+            pattern_entropy: self.pattern_entropy(),
+        }
+    }
+
+    fn pattern_entropy(&self) -> f64 {
+        let grid = &self.type_grid;
+        let mut blocks: HashMap<[u8; 4], usize> = HashMap::new();
+        for x in 1..self.size {
+            for y in 1..self.size {
+                let block = [grid[x - 1][y - 1], grid[x][y - 1], grid[x - 1][y], grid[x][y]];
+                *blocks.entry(block).or_default() += 1;
+            }
+        }
+        let total = blocks.values().sum::<usize>() as f64;
+        blocks
+            .values()
+            .map(|&c| {
+                let p = c as f64 / total;
+                -p * p.log2()
+            })
+            .sum()
+    }
+}
+
+#[wasm_bindgen]
+impl ParticleGrid {
+    // Radial distribution function g(r) of type_b around type_a for
+    // r = 0..=max_r cells (entry 0 is always 0). Distances are Euclidean,
+    // rounded to the nearest cell. g = 1 means type_b turns up at that
+    // distance as often as its overall density predicts, so peaks mark
+    // preferred spacings and dips exclusion. A wrapping grid is measured
+    // across its edges (max_r capped at half the size); otherwise only
+    // cells inside the grid are counted, in the expectation as well.
+    // Returns an empty array for an unknown type.
+    #[wasm_bindgen]
+    pub fn radial_distribution(&self, type_a: u8, type_b: u8, max_r: usize) -> Vec<f32> {
+        let size = self.size;
+        if type_a as usize > self.num_types || type_b as usize > self.num_types {
+            return Vec::new();
+        }
+        let wrap = self.rules.boundary == BoundaryMode::Wrap;
+        let max_r = max_r.min(if wrap { size / 2 } else { size.saturating_sub(1) });
+
+        // Density of type_b as seen from a type_a particle
+        let cells = (size * size) as f64;
+        let count_b = self.type_grid.iter().flatten().filter(|&&t| t == type_b).count() as f64;
+        let density = if type_a == type_b { (count_b - 1.0) / (cells - 1.0) } else { count_b / cells };
+
+        let r = max_r as isize;
+        let offsets: Vec<(isize, isize, usize)> = (-r..=r)
+            .flat_map(|dy| (-r..=r).map(move |dx| (dx, dy, ((dx * dx + dy * dy) as f64).sqrt().round() as usize)))
+            .filter(|&(_, _, bin)| bin > 0 && bin <= max_r)
+            .collect();
+        let mut found = vec![0u64; max_r + 1];
+        let mut reachable = vec![0u64; max_r + 1];
+        for x in 0..size {
+            for y in 0..size {
+                if self.type_grid[x][y] != type_a {
+                    continue;
+                }
+                for &(dx, dy, bin) in &offsets {
+                    let (mut i, mut j) = (x as isize + dx, y as isize + dy);
+                    if wrap {
+                        (i, j) = (i.rem_euclid(size as isize), j.rem_euclid(size as isize));
+                    } else if i < 0 || j < 0 || i >= size as isize || j >= size as isize {
+                        continue;
+                    }
+                    reachable[bin] += 1;
+                    if self.type_grid[i as usize][j as usize] == type_b {
+                        found[bin] += 1;
+                    }
+                }
+            }
+        }
+
+        found
+            .iter()
+            .zip(&reachable)
+            .map(|(&f, &n)| {
+                let expected = n as f64 * density;
+                if expected > 0.0 { (f as f64 / expected) as f32 } else { 0.0 }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::SimulationConfig;
+    use crate::ParticleGrid;
+
+    fn grid(cells: impl Fn(usize, usize) -> u8) -> ParticleGrid {
+        let mut grid = ParticleGrid::from_config(&SimulationConfig { size: 8, num_types: 2, ..Default::default() });
+        for x in 0..8 {
+            for y in 0..8 {
+                grid.type_grid[x][y] = cells(x, y);
+            }
+        }
+        grid
+    }
+
+    #[test]
+    fn pattern_entropy() {
+        assert_eq!(grid(|_, _| 1).metrics().pattern_entropy, 0.0);
+        // A checkerboard has two 2x2 blocks in (nearly) equal numbers
+        let checkers = grid(|x, y| 1 + ((x + y) % 2) as u8).metrics();
+        assert!((checkers.pattern_entropy - 1.0).abs() < 1e-3);
+        // Same type mix, less regular: more block kinds
+        let stripes = grid(|x, y| 1 + ((x / 2 + y / 3) % 2) as u8).metrics();
+        assert!(stripes.pattern_entropy > checkers.pattern_entropy);
+        assert_eq!(checkers.type_entropy, 1.0);
+    }
+
+    #[test]
+    fn clusters() {
+        let metrics = grid(|x, _| if x < 4 { 1 } else { 0 }).metrics();
+        assert_eq!((metrics.particles, metrics.cluster_count, metrics.largest_cluster), (32, 1, 32));
+        assert_eq!(metrics.survivor_types, vec![1]);
+    }
+
+    #[test]
+    fn survivors_carry_names() {
+        let mut grid = grid(|x, _| if x < 4 { 2 } else { 0 });
+        grid.set_type_name(2, "moss");
+        let metrics = grid.metrics();
+        assert_eq!((metrics.survivor_types, metrics.survivor_names), (vec![2], vec!["moss".to_string()]));
+    }
+}
@@ -0,0 +1,36 @@
+// Movement settings that don't involve the MovementMode enum (the policies
+// themselves are described in core/movement.rs; the mode's bindings are in
+// wasm.rs).
+
+use wasm_bindgen::prelude::*;
+
+use crate::ParticleGrid;
+
+#[wasm_bindgen]
+impl ParticleGrid {
+    // Choose destinations by softmax with the given sharpness (finite,
+    // 0 or more; anything else is ignored)
+    #[wasm_bindgen]
+    pub fn enable_softmax(&mut self, sharpness: f32) {
+        if sharpness.is_finite() && sharpness >= 0.0 {
+            self.rules.softmax = Some(sharpness);
+        }
+    }
+
+    // Back to always taking the best option
+    #[wasm_bindgen]
+    pub fn disable_softmax(&mut self) {
+        self.rules.softmax = None;
+    }
+
+    // Current sharpness, undefined when softmax selection is off
+    #[wasm_bindgen(getter)]
+    pub fn softmax_sharpness(&self) -> Option<f32> {
+        self.rules.softmax
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn move_threshold(&self) -> f32 {
+        self.rules.move_threshold
+    }
+}
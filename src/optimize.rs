@@ -0,0 +1,225 @@
+// Search over affinity matrices for rule sets that score well on a chosen
+// metric. Hill climbing with random restarts: each iteration mutates the
+// current matrix, runs a fresh simulation with it, and keeps the mutation if
+// the metric did not get worse. Every candidate is evaluated from the same
+// seed so scores are comparable.
+//
+// Evaluations are full simulations, so this is slow; the wasm API is meant
+// to be driven a few iterations per frame.
+
+use rand::prelude::*;
+use wasm_bindgen::prelude::*;
+
+use crate::ParticleGrid;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Objective {
+    ClusterCount = 0,
+    MeanClusterSize = 1,
+    TypeEntropy = 2,
+    // Steps until the grid stops changing (capped at the run length)
+    LongestTransient = 3,
+    // Entropy of the grid's 2x2 blocks (see Metrics)
+    PatternEntropy = 4,
+}
+
+// A restart happens after this many iterations without improvement
+const PATIENCE: usize = 25;
+// Fraction of cells changing per step below which a run counts as settled
+const SETTLED_THRESHOLD: f64 = 1e-4;
+
+#[wasm_bindgen]
+pub struct RuleSearch {
+    size: usize,
+    num_types: usize,
+    density: f32,
+    radius: usize,
+    steps: usize,
+    objective: Objective,
+    eval_seed: u64,
+    rng: StdRng,
+    current: Vec<i32>,
+    current_score: f64,
+    best: Vec<i32>,
+    best_score: f64,
+    iterations: usize,
+    stale: usize,
+}
+
+#[wasm_bindgen]
+impl RuleSearch {
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        size: usize,
+        num_types: usize,
+        density: f32,
+        radius: usize,
+        steps: usize,
+        objective: Objective,
+        seed: u64,
+    ) -> RuleSearch {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let eval_seed = rng.gen();
+        let mut search = RuleSearch {
+            size,
+            num_types,
+            density,
+            radius,
+            steps,
+            objective,
+            eval_seed,
+            rng,
+            current: Vec::new(),
+            current_score: f64::NEG_INFINITY,
+            best: Vec::new(),
+            best_score: f64::NEG_INFINITY,
+            iterations: 0,
+            stale: 0,
+        };
+        search.restart();
+        search.best = search.current.clone();
+        search.best_score = search.current_score;
+        search
+    }
+
+    // Run `n` more search iterations and return the best score so far
+    #[wasm_bindgen]
+    pub fn run(&mut self, n: usize) -> f64 {
+        for _ in 0..n {
+            self.iterate();
+        }
+        self.best_score
+    }
+
+    // Best affinity matrix found, flattened like update_affinity expects
+    #[wasm_bindgen]
+    pub fn best_affinity(&self) -> Vec<i32> {
+        self.best.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn best_score(&self) -> f64 {
+        self.best_score
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+}
+
+impl RuleSearch {
+    fn iterate(&mut self) {
+        self.iterations += 1;
+
+        if self.stale >= PATIENCE {
+            self.restart();
+        } else {
+            let candidate = self.mutate(&self.current.clone());
+            let score = self.evaluate(&candidate);
+            if score >= self.current_score {
+                if score > self.current_score {
+                    self.stale = 0;
+                } else {
+                    self.stale += 1;
+                }
+                self.current = candidate;
+                self.current_score = score;
+            } else {
+                self.stale += 1;
+            }
+        }
+
+        if self.current_score > self.best_score {
+            self.best = self.current.clone();
+            self.best_score = self.current_score;
+        }
+    }
+
+    fn restart(&mut self) {
+        let n = (self.num_types + 1) * (self.num_types + 1);
+        self.current = (0..n).map(|_| if self.rng.gen_bool(0.5) { 1 } else { -1 }).collect();
+        self.current_score = self.evaluate(&self.current.clone());
+        self.stale = 0;
+    }
+
+    // Flip one to three distinct entries between the real types (row and
+    // column 0 never affect scoring)
+    fn mutate(&mut self, affinity: &[i32]) -> Vec<i32> {
+        let mut out = affinity.to_vec();
+        let n = self.num_types;
+        if n == 0 {
+            return out;
+        }
+        let flips = self.rng.gen_range(1..=3).min(n * n);
+        for k in rand::seq::index::sample(&mut self.rng, n * n, flips) {
+            let idx = (k / n + 1) * (n + 1) + k % n + 1;
+            out[idx] = -out[idx];
+        }
+        out
+    }
+
+    fn evaluate(&self, affinity: &[i32]) -> f64 {
+        let mut grid = ParticleGrid::with_seed(
+            self.size,
+            self.num_types,
+            self.density,
+            self.radius,
+            Some(affinity.to_vec()),
+            self.eval_seed,
+            None,
+        );
+        grid.set_convergence_window(10);
+
+        let mut settled_at = self.steps;
+        for step in 0..self.steps {
+            grid.step();
+            if self.objective == Objective::LongestTransient && grid.is_converged(SETTLED_THRESHOLD) {
+                settled_at = step + 1;
+                break;
+            }
+        }
+
+        let metrics = grid.metrics();
+        match self.objective {
+            Objective::ClusterCount => metrics.cluster_count as f64,
+            Objective::MeanClusterSize => metrics.mean_cluster_size,
+            Objective::TypeEntropy => metrics.type_entropy,
+            Objective::LongestTransient => settled_at as f64,
+            Objective::PatternEntropy => metrics.pattern_entropy,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_with_no_types() {
+        let mut search = RuleSearch::new(8, 0, 0.3, 1, 2, Objective::ClusterCount, 1);
+        search.run(3);
+        assert_eq!(search.iterations(), 3);
+    }
+
+    #[test]
+    fn mutation_flips_distinct_entries() {
+        let mut search = RuleSearch::new(8, 1, 0.3, 1, 1, Objective::PatternEntropy, 1);
+        for _ in 0..20 {
+            // With one type there is a single entry to flip, so it must
+            // change every time
+            let before = search.current.clone();
+            let after = search.mutate(&before);
+            assert_eq!(after[3], -before[3]);
+            assert_eq!(after[..3], before[..3]);
+        }
+
+        let mut search = RuleSearch::new(8, 3, 0.3, 1, 1, Objective::PatternEntropy, 2);
+        for _ in 0..50 {
+            let before = search.current.clone();
+            let changed = search.mutate(&before).iter().zip(&before).filter(|(a, b)| a != b).count();
+            assert!((1..=3).contains(&changed), "{} entries changed", changed);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+// Cell-level deltas between two grids, for syncing a simulation to peers or
+// storing checkpoints as changes against a base. A patch lists the cells
+// that differ (row-major index and new type) in index order.
+//
+// to_bytes() writes the magic bytes "PAGP", the grid size as a LEB128
+// number, then every change as the LEB128 gap since the previous change's
+// index followed by the type byte. Neighbouring changes, the usual case
+// after a step, take two bytes each.
+
+use wasm_bindgen::prelude::*;
+
+use crate::ParticleGrid;
+
+const MAGIC: &[u8; 4] = b"PAGP";
+
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GridPatch {
+    pub(crate) size: usize,
+    // (row-major index, new type), indices strictly increasing
+    pub(crate) changes: Vec<(usize, u8)>,
+}
+
+#[wasm_bindgen]
+impl GridPatch {
+    #[wasm_bindgen]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        push_leb128(&mut bytes, self.size);
+        let mut next = 0;
+        for &(i, t) in &self.changes {
+            push_leb128(&mut bytes, i - next);
+            bytes.push(t);
+            next = i + 1;
+        }
+        bytes
+    }
+
+    #[wasm_bindgen]
+    pub fn from_bytes(bytes: &[u8]) -> Result<GridPatch, String> {
+        let body = bytes.strip_prefix(MAGIC).ok_or("not a grid patch")?;
+        let mut pos = 0;
+        let size = read_leb128(body, &mut pos)?;
+        let cells = size.checked_mul(size).ok_or("grid patch size overflows")?;
+        let mut changes = Vec::new();
+        let mut next = 0usize;
+        while pos < body.len() {
+            let i = next.checked_add(read_leb128(body, &mut pos)?).filter(|&i| i < cells);
+            let i = i.ok_or("grid patch change lies outside the grid")?;
+            let t = *body.get(pos).ok_or("grid patch ends mid-change")?;
+            pos += 1;
+            changes.push((i, t));
+            next = i + 1;
+        }
+        Ok(GridPatch { size, changes })
+    }
+
+    // Side of the grid the patch applies to
+    #[wasm_bindgen(getter)]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // Number of changed cells
+    #[wasm_bindgen(getter)]
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    #[wasm_bindgen]
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    // Changes as flat [x, y, type, ...] triples
+    #[wasm_bindgen]
+    pub fn cells(&self) -> Vec<u32> {
+        let size = self.size;
+        self.changes.iter().flat_map(|&(i, t)| [(i % size) as u32, (i / size) as u32, t as u32]).collect()
+    }
+}
+
+impl GridPatch {
+    // The patch from `old` to `new`, both row-major `size` x `size` cells
+    pub(crate) fn between(size: usize, old: &[u8], new: &[u8]) -> GridPatch {
+        let changes = old.iter().zip(new).enumerate().filter(|(_, (a, b))| a != b).map(|(i, (_, &t))| (i, t));
+        GridPatch { size, changes: changes.collect() }
+    }
+}
+
+#[wasm_bindgen]
+impl ParticleGrid {
+    // The patch that turns this grid's cells into `other`'s. Only cells are
+    // compared; rules, ids and the clock are not part of a patch.
+    #[wasm_bindgen]
+    pub fn diff(&self, other: &ParticleGrid) -> Result<GridPatch, String> {
+        if other.size != self.size {
+            return Err(format!("cannot diff a {0}x{0} grid against a {1}x{1} one", self.size, other.size));
+        }
+        Ok(GridPatch::between(self.size, &self.export_grid(), &other.export_grid()))
+    }
+
+    // Write a patch's cells into the grid. Returns false (and changes
+    // nothing) if it was made for another size or uses an unknown type.
+    #[wasm_bindgen]
+    pub fn apply_patch(&mut self, patch: &GridPatch) -> bool {
+        if patch.size != self.size || patch.changes.iter().any(|&(_, t)| t as usize > self.num_types) {
+            return false;
+        }
+        let size = self.size;
+        for &(i, t) in &patch.changes {
+            self.type_grid[i % size][i / size] = t;
+        }
+        self.refresh_output();
+        true
+    }
+}
+
+fn push_leb128(bytes: &mut Vec<u8>, mut n: usize) {
+    loop {
+        let low = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            bytes.push(low);
+            break;
+        }
+        bytes.push(low | 0x80);
+    }
+}
+
+fn read_leb128(bytes: &[u8], pos: &mut usize) -> Result<usize, String> {
+    let mut n = 0usize;
+    let mut shift = 0;
+    loop {
+        let b = *bytes.get(*pos).ok_or("grid patch ends mid-number")?;
+        *pos += 1;
+        n |= ((b & 0x7f) as usize) << shift;
+        if b & 0x80 == 0 {
+            return Ok(n);
+        }
+        shift += 7;
+        if shift >= usize::BITS {
+            return Err("grid patch number overflows".to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_round_trip() {
+        let patch = GridPatch::between(3, &[0, 1, 2, 0, 0, 0, 0, 0, 1], &[0, 2, 2, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(patch.changes, vec![(1, 2), (4, 1), (8, 0)]);
+        assert_eq!(GridPatch::from_bytes(&patch.to_bytes()).unwrap(), patch);
+    }
+
+    #[test]
+    fn rejects_malformed_bytes() {
+        let error = |bytes: &[u8]| GridPatch::from_bytes(bytes).unwrap_err();
+        assert!(error(b"nope").contains("not a grid patch"));
+        assert!(error(b"PAGP").contains("mid-number"));
+        // 3x3 grid, a change at index 9
+        assert!(error(b"PAGP\x03\x09\x01").contains("outside the grid"));
+        assert!(error(b"PAGP\x03\x02").contains("mid-change"));
+        assert!(error(b"PAGP\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01").contains("overflows"));
+        let mut huge = b"PAGP".to_vec();
+        push_leb128(&mut huge, usize::MAX);
+        assert!(error(&huge).contains("size overflows"));
+    }
+}
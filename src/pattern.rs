@@ -0,0 +1,264 @@
+// Compact text encodings of a grid, short enough to paste into a URL hash
+// or a chat message. Three forms are accepted:
+//
+//   rows    one line per grid row, one character per cell: '.' or '0' for
+//           empty, '1'-'9' then 'a'-'z' for types 1-35. Rows are separated
+//           by newlines or '/', so "1.2/.3./2.1" is a 3x3 grid.
+//   rle     "rle:<size>:<data>", where data is base64url (no padding) of
+//           the row-major cells as (type byte, LEB128 run length) pairs.
+//   lz4     "lz4:<size>:<data>", where data is base64url (no padding) of
+//           the row-major cells LZ4-compressed (see compress.rs).
+//
+// export_pattern_string() writes whichever of rle and lz4 is shorter: rle
+// for sparse or blocky grids, lz4 for busy ones.
+
+use wasm_bindgen::prelude::*;
+
+use crate::compress;
+use crate::core;
+use crate::ParticleGrid;
+
+const RLE_PREFIX: &str = "rle:";
+const LZ4_PREFIX: &str = "lz4:";
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+#[wasm_bindgen]
+impl ParticleGrid {
+    // Replace the grid with a pattern string (see the top of pattern.rs).
+    // A rows pattern smaller than the grid is centered on an empty grid.
+    // Density is recomputed and the simulation clock restarts; rules are
+    // kept. On error the grid is left untouched.
+    #[wasm_bindgen]
+    pub fn reseed_from_string(&mut self, s: &str) -> Result<(), String> {
+        let cells = decode_pattern(s, self.size, self.num_types)?;
+        self.set_cells(&cells);
+        core::symmetrize(&mut self.sim.type_grid, self.sim.rules.symmetry);
+        let occupied = cells.iter().filter(|&&t| t != 0).count();
+        self.density = occupied as f32 / cells.len().max(1) as f32;
+        self.type_densities = None;
+        self.reset_history();
+        Ok(())
+    }
+
+    // The current grid in the shorter of the rle and lz4 forms accepted by
+    // reseed_from_string
+    #[wasm_bindgen]
+    pub fn export_pattern_string(&self) -> String {
+        let cells = self.export_grid();
+        let runs = encode_runs(&cells);
+        let packed = compress::compress(&cells);
+        let (prefix, bytes) = if packed.len() < runs.len() { (LZ4_PREFIX, packed) } else { (RLE_PREFIX, runs) };
+        format!("{}{}:{}", prefix, self.size, base64_encode(&bytes))
+    }
+}
+
+// Row-major cells of a `size` x `size` grid in the rle form
+pub(crate) fn encode_rle(cells: &[u8], size: usize) -> String {
+    format!("{}{}:{}", RLE_PREFIX, size, base64_encode(&encode_runs(cells)))
+}
+
+impl ParticleGrid {
+    // Overwrite the grid from row-major cells
+    pub(crate) fn set_cells(&mut self, cells: &[u8]) {
+        let size = self.size;
+        for (i, &t) in cells.iter().enumerate() {
+            self.type_grid[i % size][i / size] = t;
+        }
+    }
+}
+
+// Row-major cells of a `size` x `size` grid from any pattern form
+pub(crate) fn decode_pattern(s: &str, size: usize, num_types: usize) -> Result<Vec<u8>, String> {
+    let s = s.trim();
+    let cells = if let Some(rest) = s.strip_prefix(RLE_PREFIX) {
+        decode_sized(rest, size, "rle", decode_runs)?
+    } else if let Some(rest) = s.strip_prefix(LZ4_PREFIX) {
+        decode_sized(rest, size, "lz4", compress::decompress_cells)?
+    } else {
+        decode_rows(s, size)?
+    };
+    match cells.iter().find(|&&t| t as usize > num_types) {
+        Some(&t) => Err(format!("pattern uses type {} but the grid has {} types", t, num_types)),
+        None => Ok(cells),
+    }
+}
+
+fn push_run(bytes: &mut Vec<u8>, t: u8, mut n: usize) {
+    bytes.push(t);
+    loop {
+        let low = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            bytes.push(low);
+            break;
+        }
+        bytes.push(low | 0x80);
+    }
+}
+
+// Row-major cells of a `size` x `size` grid from the "<size>:<data>" part
+// of a `form` pattern, `decode` turning the data's bytes into cells
+fn decode_sized(
+    s: &str,
+    size: usize,
+    form: &str,
+    decode: fn(&[u8], usize) -> Result<Vec<u8>, String>,
+) -> Result<Vec<u8>, String> {
+    let (size_str, data) = s.split_once(':').ok_or(format!("{} pattern is missing its size", form))?;
+    let pattern_size: usize = size_str.parse().map_err(|_| format!("bad {} size '{}'", form, size_str))?;
+    if pattern_size != size {
+        return Err(format!("pattern is {0}x{0} but the grid is {1}x{1}", pattern_size, size));
+    }
+
+    decode(&base64_decode(data, form)?, size)
+}
+
+// Row-major cells as (type byte, LEB128 run length) pairs
+pub(crate) fn encode_runs(cells: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut run: Option<(u8, usize)> = None;
+    for &t in cells {
+        run = match run {
+            Some((rt, n)) if rt == t => Some((rt, n + 1)),
+            Some((rt, n)) => {
+                push_run(&mut bytes, rt, n);
+                Some((t, 1))
+            }
+            None => Some((t, 1)),
+        };
+    }
+    if let Some((t, n)) = run {
+        push_run(&mut bytes, t, n);
+    }
+    bytes
+}
+
+// encode_runs output back to the row-major cells of a `size` x `size` grid
+pub(crate) fn decode_runs(bytes: &[u8], size: usize) -> Result<Vec<u8>, String> {
+    let total = size.checked_mul(size).ok_or("rle grid size overflows")?;
+    let mut cells = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let t = bytes[pos];
+        pos += 1;
+        let mut n = 0usize;
+        let mut shift = 0;
+        loop {
+            let b = *bytes.get(pos).ok_or("rle data ends mid-run")?;
+            pos += 1;
+            n |= ((b & 0x7f) as usize) << shift;
+            if b & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+            if shift >= usize::BITS {
+                return Err("rle run length overflows".to_string());
+            }
+        }
+        let end = cells
+            .len()
+            .checked_add(n)
+            .filter(|&end| end <= total)
+            .ok_or("rle data covers more cells than the grid")?;
+        cells.resize(end, t);
+    }
+    if cells.len() != total {
+        return Err(format!("rle data covers {} of {} cells", cells.len(), total));
+    }
+    Ok(cells)
+}
+
+fn decode_rows(s: &str, size: usize) -> Result<Vec<u8>, String> {
+    let rows: Vec<&str> = s
+        .split(['\n', '/'])
+        .map(str::trim)
+        .filter(|row| !row.is_empty())
+        .collect();
+    let height = rows.len();
+    let width = rows.iter().map(|row| row.chars().count()).max().unwrap_or(0);
+    if width > size || height > size {
+        return Err(format!("pattern is {}x{} but the grid is {}x{}", width, height, size, size));
+    }
+
+    let (ox, oy) = ((size - width) / 2, (size - height) / 2);
+    let mut cells = vec![0u8; size * size];
+    for (y, row) in rows.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            let t = match c {
+                '.' => 0,
+                _ => c.to_digit(36).ok_or_else(|| format!("unexpected character '{}' in pattern", c))? as u8,
+            };
+            cells[(oy + y) * size + ox + x] = t;
+        }
+    }
+    Ok(cells)
+}
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            out.push(BASE64_ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3f] as char);
+        }
+    }
+    out
+}
+
+// `form` names the pattern form in errors
+fn base64_decode(s: &str, form: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for c in s.bytes().filter(|&c| c != b'=') {
+        let v = BASE64_ALPHABET
+            .iter()
+            .position(|&a| a == c)
+            .ok_or_else(|| format!("unexpected character '{}' in {} data", c as char, form))?;
+        acc = (acc << 6) | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_round_trip() {
+        let cells = [0, 0, 0, 1, 1, 2, 0, 0, 3];
+        assert_eq!(decode_runs(&encode_runs(&cells), 3).unwrap(), cells);
+        let empty = vec![0; 300 * 300];
+        assert_eq!(decode_runs(&encode_runs(&empty), 300).unwrap(), empty);
+    }
+
+    #[test]
+    fn runs_reject_bad_lengths() {
+        // Too few and too many cells
+        assert!(decode_runs(&[1, 8], 3).unwrap_err().contains("8 of 9"));
+        assert!(decode_runs(&[1, 10], 3).unwrap_err().contains("more cells"));
+        // Cut off mid-length
+        assert!(decode_runs(&[1, 0x85], 3).unwrap_err().contains("mid-run"));
+        // A run long enough to wrap the count, after a real one
+        let mut huge = vec![1, 4, 2];
+        huge.extend_from_slice(&[0xff; 9]);
+        huge.push(0x01);
+        assert!(decode_runs(&huge, 3).unwrap_err().contains("more cells"));
+        assert!(decode_runs(&[1, 1], usize::MAX).unwrap_err().contains("overflows"));
+    }
+
+    #[test]
+    fn patterns_name_their_form() {
+        assert!(decode_pattern("rle:3:AA!A", 3, 2).unwrap_err().contains("in rle data"));
+        assert!(decode_pattern("lz4:3:AA!A", 3, 2).unwrap_err().contains("in lz4 data"));
+        assert!(decode_pattern("rle:4:AQk", 3, 2).unwrap_err().contains("4x4"));
+        assert!(decode_pattern("3..", 3, 2).unwrap_err().contains("type 3"));
+        // Centered, rounding towards the top left
+        assert_eq!(decode_pattern("1/.2", 3, 2).unwrap(), [1, 0, 0, 0, 2, 0, 0, 0, 0]);
+    }
+}
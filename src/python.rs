@@ -0,0 +1,151 @@
+// Python bindings (`python` feature). The module wraps the same
+// ParticleGrid the web demo runs, so a seeded run here matches one in the
+// browser step for step. Grids cross over as numpy uint8 arrays indexed
+// [y, x] (see array.rs) and affinity tables as int8 arrays indexed
+// [from, to].
+//
+// Build with maturin (`maturin develop --release`), which picks the
+// feature up from pyproject.toml.
+
+use numpy::{IntoPyArray, PyArray2, PyReadonlyArray2};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+
+use crate::config::SimulationConfig;
+use crate::ParticleGrid;
+
+#[pyclass(name = "ParticleGrid", module = "particle_affinity_wasm")]
+struct PyParticleGrid {
+    grid: ParticleGrid,
+}
+
+#[pymethods]
+impl PyParticleGrid {
+    // Same arguments as the JS constructor. `affinity` is an (n + 1) x
+    // (n + 1) nested sequence or array, random if omitted; without a seed
+    // the run is not reproducible.
+    #[new]
+    #[pyo3(signature = (size, num_types, density, radius, affinity=None, seed=None, type_densities=None))]
+    fn new(
+        size: usize,
+        num_types: usize,
+        density: f32,
+        radius: usize,
+        affinity: Option<Vec<Vec<i32>>>,
+        seed: Option<u64>,
+        type_densities: Option<Vec<f32>>,
+    ) -> PyResult<Self> {
+        if size == 0 || num_types == 0 || num_types > u8::MAX as usize - 1 {
+            return Err(PyValueError::new_err("size must be positive and num_types between 1 and 254"));
+        }
+        let affinity = affinity.map(|rows| flatten_affinity(&rows, num_types)).transpose()?;
+        let grid = match seed {
+            Some(seed) => ParticleGrid::with_seed(size, num_types, density, radius, affinity, seed, type_densities),
+            None => ParticleGrid::new(size, num_types, density, radius, affinity, type_densities),
+        };
+        Ok(PyParticleGrid { grid })
+    }
+
+    #[staticmethod]
+    fn from_config_json(json: &str) -> PyResult<Self> {
+        let config = SimulationConfig::from_json(json).map_err(PyValueError::new_err)?;
+        Ok(PyParticleGrid { grid: ParticleGrid::from_config(&config) })
+    }
+
+    fn export_config(&self) -> String {
+        self.grid.export_config()
+    }
+
+    // Run `steps` steps, releasing the GIL while they run
+    #[pyo3(signature = (steps=1))]
+    fn step(&mut self, py: Python<'_>, steps: usize) -> PyResult<()> {
+        let grid = &mut self.grid;
+        py.allow_threads(|| (0..steps).try_for_each(|_| grid.try_step().map(|_| ())))
+            .map_err(PyRuntimeError::new_err)
+    }
+
+    // Copy of the cells as a size x size uint8 array
+    fn grid<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray2<u8>> {
+        self.grid.to_array().into_pyarray(py)
+    }
+
+    // Overwrite the cells from a size x size uint8 array
+    fn set_grid(&mut self, cells: PyReadonlyArray2<'_, u8>) -> PyResult<()> {
+        self.grid.set_array(cells.as_array()).map_err(PyValueError::new_err)?;
+        self.grid.refresh_output();
+        Ok(())
+    }
+
+    fn affinity<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray2<i8>> {
+        let rows = &self.grid.rules.affinity;
+        let n = rows.len();
+        ndarray::Array2::from_shape_fn((n, n), |(a, b)| rows[a][b]).into_pyarray(py)
+    }
+
+    // Replace the whole affinity table; values are clamped to int8
+    fn set_affinity(&mut self, affinity: Vec<Vec<i32>>) -> PyResult<()> {
+        let flat = flatten_affinity(&affinity, self.grid.num_types)?;
+        self.grid.update_affinity(flat.into_iter().map(|a| a.clamp(i8::MIN as i32, i8::MAX as i32)).collect());
+        Ok(())
+    }
+
+    // Affinity of type `from` towards type `to`
+    fn set_affinity_entry(&mut self, from: usize, to: usize, value: i32) -> PyResult<()> {
+        let n = self.grid.num_types + 1;
+        if from >= n || to >= n {
+            return Err(PyValueError::new_err(format!("types must be below {}", n)));
+        }
+        self.grid.rules.affinity[from][to] = value.clamp(i8::MIN as i32, i8::MAX as i32) as i8;
+        Ok(())
+    }
+
+    fn set_interaction_weights(&mut self, attraction_scale: f32, repulsion_scale: f32) {
+        self.grid.set_interaction_weights(attraction_scale, repulsion_scale);
+    }
+
+    fn set_replace_params(&mut self, radius: usize, trigger_probability: f32, max_conversions: usize) {
+        self.grid.set_replace_params(radius, trigger_probability, max_conversions);
+    }
+
+    #[getter]
+    fn size(&self) -> usize {
+        self.grid.size
+    }
+
+    #[getter]
+    fn num_types(&self) -> usize {
+        self.grid.num_types
+    }
+
+    #[getter]
+    fn radius(&self) -> usize {
+        self.grid.rules.radius
+    }
+
+    #[getter]
+    fn generation(&self) -> u64 {
+        self.grid.generation
+    }
+
+    fn state_hash(&self) -> u64 {
+        self.grid.state_hash()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<ParticleGrid {}>", self.grid.debug_info())
+    }
+}
+
+// Row-major copy of a square (num_types + 1) table
+fn flatten_affinity(rows: &[Vec<i32>], num_types: usize) -> PyResult<Vec<i32>> {
+    let n = num_types + 1;
+    if rows.len() != n || rows.iter().any(|row| row.len() != n) {
+        return Err(PyValueError::new_err(format!("affinity must be {}x{}", n, n)));
+    }
+    Ok(rows.concat())
+}
+
+#[pymodule]
+fn particle_affinity_wasm(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyParticleGrid>()
+}
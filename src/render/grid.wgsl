@@ -0,0 +1,25 @@
+// Full-canvas quad showing one texel of the grid texture per cell
+
+@group(0) @binding(0) var grid: texture_2d<f32>;
+
+struct VsOut {
+    @builtin(position) pos: vec4f,
+    @location(0) uv: vec2f,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) i: u32) -> VsOut {
+    // Two triangles covering clip space; uv (0, 0) is the top-left cell
+    let corner = vec2f(f32((0x32u >> i) & 1u), f32((0x2Cu >> i) & 1u));
+    var out: VsOut;
+    out.pos = vec4f(corner * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2f(corner.x, 1.0 - corner.y);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VsOut) -> @location(0) vec4f {
+    let size = vec2f(textureDimensions(grid));
+    let cell = vec2i(min(in.uv * size, size - 1.0));
+    return textureLoad(grid, cell, 0);
+}
@@ -0,0 +1,136 @@
+// Hardware-accelerated drawing of a ParticleGrid onto a canvas. Each draw
+// uploads the grid as an RGBA texture (one texel per cell, in the grid's
+// own colors) and stretches it over the canvas with nearest filtering.
+//
+// Two backends sit behind the same GridRenderer: WebGPU (`webgpu` feature)
+// and a WebGL2 fallback (`webgl` feature) for browsers without WebGPU.
+// GridRenderer.create picks the first one that is compiled in and works
+// on this browser, WebGPU first.
+//
+// CanvasPresenter (`canvas` feature) wraps an OffscreenCanvas for the
+// render loop in a worker, drawing through a GridRenderer or a plain 2D
+// context.
+
+use wasm_bindgen::prelude::*;
+use web_sys::HtmlCanvasElement;
+
+use crate::ParticleGrid;
+
+#[cfg(feature = "canvas")]
+mod presenter;
+#[cfg(feature = "webgl")]
+mod webgl;
+#[cfg(feature = "webgpu")]
+mod webgpu;
+
+#[cfg(feature = "canvas")]
+pub use presenter::CanvasPresenter;
+
+// Either kind of canvas; both hand out the same contexts
+pub(crate) enum Surface {
+    Html(HtmlCanvasElement),
+    #[cfg(feature = "canvas")]
+    Offscreen(web_sys::OffscreenCanvas),
+}
+
+impl Surface {
+    fn get_context(&self, kind: &str) -> Result<Option<js_sys::Object>, JsValue> {
+        match self {
+            Surface::Html(c) => c.get_context(kind),
+            #[cfg(feature = "canvas")]
+            Surface::Offscreen(c) => c.get_context(kind),
+        }
+    }
+
+    #[cfg(feature = "webgl")]
+    fn size(&self) -> (u32, u32) {
+        match self {
+            Surface::Html(c) => (c.width(), c.height()),
+            #[cfg(feature = "canvas")]
+            Surface::Offscreen(c) => (c.width(), c.height()),
+        }
+    }
+}
+
+enum Backend {
+    #[cfg(feature = "webgpu")]
+    WebGpu(webgpu::WebGpuBackend),
+    #[cfg(feature = "webgl")]
+    WebGl2(webgl::WebGl2Backend),
+}
+
+#[wasm_bindgen]
+pub struct GridRenderer {
+    backend: Backend,
+    // RGBA texels of the last draw, reused between frames
+    pixels: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl GridRenderer {
+    // Resolves to a renderer drawing into `canvas`. `backend` is "webgpu",
+    // "webgl2" or, when omitted or "auto", whichever is available. A
+    // canvas can only ever hand out one kind of context, so use a fresh
+    // canvas if this rejects after a backend was already tried on it.
+    #[wasm_bindgen]
+    pub async fn create(canvas: HtmlCanvasElement, backend: Option<String>) -> Result<GridRenderer, JsValue> {
+        Self::on_surface(Surface::Html(canvas), backend.as_deref()).await
+    }
+
+    // "webgpu" or "webgl2"
+    #[wasm_bindgen(getter)]
+    pub fn backend(&self) -> String {
+        match self.backend {
+            #[cfg(feature = "webgpu")]
+            Backend::WebGpu(_) => "webgpu",
+            #[cfg(feature = "webgl")]
+            Backend::WebGl2(_) => "webgl2",
+        }
+        .to_string()
+    }
+
+    // Upload the grid and draw it over the whole canvas
+    #[wasm_bindgen]
+    pub fn draw(&mut self, grid: &ParticleGrid) -> Result<(), JsValue> {
+        self.pixels.clear();
+        for y in 0..grid.size {
+            for x in 0..grid.size {
+                self.pixels.extend_from_slice(&grid.rgba(grid.type_grid[x][y]));
+            }
+        }
+        match &mut self.backend {
+            #[cfg(feature = "webgpu")]
+            Backend::WebGpu(b) => b.draw(&self.pixels, grid.size as u32),
+            #[cfg(feature = "webgl")]
+            Backend::WebGl2(b) => b.draw(&self.pixels, grid.size as u32),
+        }
+    }
+}
+
+impl GridRenderer {
+    pub(crate) async fn on_surface(surface: Surface, backend: Option<&str>) -> Result<GridRenderer, JsValue> {
+        let backend = match backend.unwrap_or("auto") {
+            "auto" => Self::auto(surface).await?,
+            #[cfg(feature = "webgpu")]
+            "webgpu" => Backend::WebGpu(webgpu::WebGpuBackend::new(&surface).await?),
+            #[cfg(feature = "webgl")]
+            "webgl2" => Backend::WebGl2(webgl::WebGl2Backend::new(surface)?),
+            other => return Err(JsValue::from_str(&format!("renderer backend '{}' is not available", other))),
+        };
+        Ok(GridRenderer { backend, pixels: Vec::new() })
+    }
+
+    async fn auto(surface: Surface) -> Result<Backend, JsValue> {
+        #[cfg(feature = "webgpu")]
+        if webgpu::WebGpuBackend::supported().await {
+            return Ok(Backend::WebGpu(webgpu::WebGpuBackend::new(&surface).await?));
+        }
+        #[cfg(feature = "webgl")]
+        return webgl::WebGl2Backend::new(surface).map(Backend::WebGl2);
+        #[cfg(not(feature = "webgl"))]
+        {
+            let _ = surface;
+            Err(JsValue::from_str("WebGPU is not available and the WebGL2 fallback is not compiled in"))
+        }
+    }
+}
@@ -0,0 +1,103 @@
+// Worker-side presentation. A page hands its canvas to a worker with
+// transferControlToOffscreen(); the worker wraps the OffscreenCanvas in a
+// CanvasPresenter and calls present(grid) after each step, so both the
+// simulation and the drawing stay off the main thread.
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::Clamped;
+use web_sys::{ImageData, OffscreenCanvas, OffscreenCanvasRenderingContext2d};
+
+use super::{GridRenderer, Surface};
+use crate::ParticleGrid;
+
+enum Target {
+    Gpu(GridRenderer),
+    // The grid is put into `cells` at one pixel per cell, then scaled up
+    // onto the visible canvas
+    Context2d {
+        canvas: OffscreenCanvas,
+        context: OffscreenCanvasRenderingContext2d,
+        cells: Option<(OffscreenCanvas, OffscreenCanvasRenderingContext2d)>,
+    },
+}
+
+#[wasm_bindgen]
+pub struct CanvasPresenter {
+    target: Target,
+}
+
+#[wasm_bindgen]
+impl CanvasPresenter {
+    // Resolves to a presenter owning `canvas`'s context. `mode` is "gpu"
+    // (a GridRenderer, WebGPU or WebGL2), "2d" or, when omitted or "auto",
+    // the GPU if there is one and 2D otherwise.
+    #[wasm_bindgen]
+    pub async fn create(canvas: OffscreenCanvas, mode: Option<String>) -> Result<CanvasPresenter, JsValue> {
+        let target = match mode.as_deref().unwrap_or("auto") {
+            "gpu" => Target::Gpu(GridRenderer::on_surface(Surface::Offscreen(canvas), None).await?),
+            "2d" => Self::context_2d(canvas)?,
+            "auto" => {
+                // A GPU backend only takes the canvas's context once it is
+                // known to work, so after a failure it is still free for 2D
+                match GridRenderer::on_surface(Surface::Offscreen(canvas.clone()), None).await {
+                    Ok(renderer) => Target::Gpu(renderer),
+                    Err(_) => Self::context_2d(canvas)?,
+                }
+            }
+            other => return Err(JsValue::from_str(&format!("unknown presenter mode '{}'", other))),
+        };
+        Ok(CanvasPresenter { target })
+    }
+
+    // "webgpu", "webgl2" or "2d"
+    #[wasm_bindgen(getter)]
+    pub fn backend(&self) -> String {
+        match &self.target {
+            Target::Gpu(renderer) => renderer.backend(),
+            Target::Context2d { .. } => "2d".to_string(),
+        }
+    }
+
+    // Draw the grid as it is now over the whole canvas
+    #[wasm_bindgen]
+    pub fn present(&mut self, grid: &ParticleGrid) -> Result<(), JsValue> {
+        let (canvas, context, cells) = match &mut self.target {
+            Target::Gpu(renderer) => return renderer.draw(grid),
+            Target::Context2d { canvas, context, cells } => (canvas, context, cells),
+        };
+        let size = grid.size as u32;
+        if cells.as_ref().is_none_or(|(c, _)| c.width() != size) {
+            let c = OffscreenCanvas::new(size, size)?;
+            let cells_context = c
+                .get_context("2d")?
+                .ok_or_else(|| JsValue::from_str("could not get a 2D context"))?
+                .unchecked_into();
+            *cells = Some((c, cells_context));
+        }
+        let Some((cells, cells_context)) = cells.as_ref() else {
+            return Ok(());
+        };
+
+        let pixels = grid.export_region_rgba(0, 0, grid.size, grid.size);
+        let image = ImageData::new_with_u8_clamped_array_and_sh(Clamped(&pixels), size, size)?;
+        cells_context.put_image_data(&image, Default::default(), Default::default())?;
+        context.set_image_smoothing_enabled(false);
+        context.draw_image_with_offscreen_canvas_and_dw_and_dh(
+            cells,
+            0.0,
+            0.0,
+            canvas.width() as f64,
+            canvas.height() as f64,
+        )
+    }
+}
+
+impl CanvasPresenter {
+    fn context_2d(canvas: OffscreenCanvas) -> Result<Target, JsValue> {
+        let context = canvas
+            .get_context("2d")?
+            .ok_or_else(|| JsValue::from_str("canvas already has a different context"))?
+            .unchecked_into();
+        Ok(Target::Context2d { canvas, context, cells: None })
+    }
+}
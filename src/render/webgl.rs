@@ -0,0 +1,118 @@
+// WebGL2 fallback: texSubImage2D into an RGBA8 texture, then the same
+// full-canvas quad as the WebGPU backend, in GLSL
+
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGl2RenderingContext as Gl, WebGlProgram, WebGlShader, WebGlTexture};
+
+use super::Surface;
+
+const VERTEX_SHADER: &str = "#version 300 es
+out vec2 uv;
+void main() {
+    // Two triangles covering clip space; uv (0, 0) is the top-left cell
+    vec2 corner = vec2((0x32 >> gl_VertexID) & 1, (0x2C >> gl_VertexID) & 1);
+    gl_Position = vec4(corner * 2.0 - 1.0, 0.0, 1.0);
+    uv = vec2(corner.x, 1.0 - corner.y);
+}
+";
+
+const FRAGMENT_SHADER: &str = "#version 300 es
+precision highp float;
+uniform sampler2D grid;
+in vec2 uv;
+out vec4 color;
+void main() {
+    vec2 size = vec2(textureSize(grid, 0));
+    color = texelFetch(grid, ivec2(min(uv * size, size - 1.0)), 0);
+}
+";
+
+pub(super) struct WebGl2Backend {
+    gl: Gl,
+    surface: Surface,
+    program: WebGlProgram,
+    texture: WebGlTexture,
+    // Side of the allocated texture, 0 before the first draw
+    size: u32,
+}
+
+impl WebGl2Backend {
+    pub(super) fn new(surface: Surface) -> Result<WebGl2Backend, JsValue> {
+        let gl: Gl = surface
+            .get_context("webgl2")?
+            .ok_or_else(|| JsValue::from_str("WebGL2 is not available on this canvas"))?
+            .unchecked_into();
+
+        let vertex = compile(&gl, Gl::VERTEX_SHADER, VERTEX_SHADER)?;
+        let fragment = compile(&gl, Gl::FRAGMENT_SHADER, FRAGMENT_SHADER)?;
+        let program = gl.create_program().ok_or_else(|| JsValue::from_str("could not create a WebGL program"))?;
+        gl.attach_shader(&program, &vertex);
+        gl.attach_shader(&program, &fragment);
+        gl.link_program(&program);
+        if !gl.get_program_parameter(&program, Gl::LINK_STATUS).as_bool().unwrap_or(false) {
+            return Err(JsValue::from_str(&gl.get_program_info_log(&program).unwrap_or_default()));
+        }
+
+        let texture = gl.create_texture().ok_or_else(|| JsValue::from_str("could not create a WebGL texture"))?;
+        gl.bind_texture(Gl::TEXTURE_2D, Some(&texture));
+        for param in [Gl::TEXTURE_MIN_FILTER, Gl::TEXTURE_MAG_FILTER] {
+            gl.tex_parameteri(Gl::TEXTURE_2D, param, Gl::NEAREST as i32);
+        }
+        for param in [Gl::TEXTURE_WRAP_S, Gl::TEXTURE_WRAP_T] {
+            gl.tex_parameteri(Gl::TEXTURE_2D, param, Gl::CLAMP_TO_EDGE as i32);
+        }
+        // Rows of a grid are tightly packed whatever the size
+        gl.pixel_storei(Gl::UNPACK_ALIGNMENT, 1);
+
+        Ok(WebGl2Backend { gl, surface, program, texture, size: 0 })
+    }
+
+    pub(super) fn draw(&mut self, pixels: &[u8], size: u32) -> Result<(), JsValue> {
+        let gl = &self.gl;
+        gl.bind_texture(Gl::TEXTURE_2D, Some(&self.texture));
+        if size != self.size {
+            gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+                Gl::TEXTURE_2D,
+                0,
+                Gl::RGBA8 as i32,
+                size as i32,
+                size as i32,
+                0,
+                Gl::RGBA,
+                Gl::UNSIGNED_BYTE,
+                Some(pixels),
+            )?;
+            self.size = size;
+        } else {
+            gl.tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_u8_array(
+                Gl::TEXTURE_2D,
+                0,
+                0,
+                0,
+                size as i32,
+                size as i32,
+                Gl::RGBA,
+                Gl::UNSIGNED_BYTE,
+                Some(pixels),
+            )?;
+        }
+
+        let (width, height) = self.surface.size();
+        gl.viewport(0, 0, width as i32, height as i32);
+        gl.use_program(Some(&self.program));
+        gl.uniform1i(gl.get_uniform_location(&self.program, "grid").as_ref(), 0);
+        gl.draw_arrays(Gl::TRIANGLES, 0, 6);
+        Ok(())
+    }
+}
+
+fn compile(gl: &Gl, kind: u32, source: &str) -> Result<WebGlShader, JsValue> {
+    let shader = gl.create_shader(kind).ok_or_else(|| JsValue::from_str("could not create a WebGL shader"))?;
+    gl.shader_source(&shader, source);
+    gl.compile_shader(&shader);
+    if gl.get_shader_parameter(&shader, Gl::COMPILE_STATUS).as_bool().unwrap_or(false) {
+        Ok(shader)
+    } else {
+        Err(JsValue::from_str(&gl.get_shader_info_log(&shader).unwrap_or_default()))
+    }
+}
@@ -0,0 +1,114 @@
+// WebGPU backend: writeTexture into an rgba8unorm texture, then one render
+// pass drawing the quad in grid.wgsl
+
+use js_sys::JsNullable;
+use wasm_bindgen::prelude::*;
+use web_sys::{
+    gpu_texture_usage, Gpu, GpuAutoLayoutMode, GpuBindGroup, GpuBindGroupDescriptor, GpuBindGroupEntry,
+    GpuCanvasConfiguration, GpuCanvasContext, GpuColorTargetState, GpuDevice, GpuFragmentState, GpuLoadOp,
+    GpuRenderPassColorAttachment, GpuRenderPassDescriptor, GpuRenderPipeline, GpuRenderPipelineDescriptor,
+    GpuShaderModuleDescriptor, GpuStoreOp, GpuTexelCopyBufferLayout, GpuTexelCopyTextureInfo, GpuTexture,
+    GpuTextureDescriptor, GpuTextureFormat, GpuVertexState,
+};
+
+use super::Surface;
+
+const SHADER: &str = include_str!("grid.wgsl");
+
+pub(super) struct WebGpuBackend {
+    device: GpuDevice,
+    context: GpuCanvasContext,
+    pipeline: GpuRenderPipeline,
+    // Grid texture and its bind group, rebuilt when the grid size changes
+    texture: Option<(GpuTexture, GpuBindGroup, u32)>,
+}
+
+impl WebGpuBackend {
+    // navigator.gpu exists and hands out an adapter
+    pub(super) async fn supported() -> bool {
+        match gpu() {
+            Some(gpu) => wasm_bindgen_futures::JsFuture::from(gpu.request_adapter())
+                .await
+                .is_ok_and(|adapter| !adapter.is_empty()),
+            None => false,
+        }
+    }
+
+    pub(super) async fn new(surface: &Surface) -> Result<WebGpuBackend, JsValue> {
+        let gpu = gpu().ok_or_else(|| JsValue::from_str("WebGPU is not supported by this browser"))?;
+        let adapter = wasm_bindgen_futures::JsFuture::from(gpu.request_adapter())
+            .await?
+            .into_option()
+            .ok_or_else(|| JsValue::from_str("no WebGPU adapter found"))?;
+        let device = wasm_bindgen_futures::JsFuture::from(adapter.request_device()).await?;
+
+        let context: GpuCanvasContext = surface
+            .get_context("webgpu")?
+            .ok_or_else(|| JsValue::from_str("canvas already has a different context"))?
+            .unchecked_into();
+        let format = gpu.get_preferred_canvas_format();
+        context.configure(&GpuCanvasConfiguration::new(&device, format))?;
+
+        let module = device.create_shader_module(&GpuShaderModuleDescriptor::new(SHADER));
+        let vertex = GpuVertexState::new(&module);
+        vertex.set_entry_point("vs_main");
+        let fragment = GpuFragmentState::new(&module, &[JsNullable::wrap(GpuColorTargetState::new(format))]);
+        fragment.set_entry_point("fs_main");
+        let descriptor = GpuRenderPipelineDescriptor::new_with_gpu_auto_layout_mode(GpuAutoLayoutMode::Auto, &vertex);
+        descriptor.set_fragment(&fragment);
+        let pipeline = device.create_render_pipeline(&descriptor)?;
+
+        Ok(WebGpuBackend { device, context, pipeline, texture: None })
+    }
+
+    pub(super) fn draw(&mut self, pixels: &[u8], size: u32) -> Result<(), JsValue> {
+        if !matches!(&self.texture, Some((_, _, s)) if *s == size) {
+            if let Some((old, _, _)) = self.texture.take() {
+                old.destroy();
+            }
+            let usage = gpu_texture_usage::TEXTURE_BINDING | gpu_texture_usage::COPY_DST;
+            let extent = [size.into(), size.into()];
+            let texture = self.device.create_texture(&GpuTextureDescriptor::new(
+                GpuTextureFormat::Rgba8unorm,
+                &extent,
+                usage,
+            ))?;
+            let entries = [GpuBindGroupEntry::new_with_gpu_texture(0, &texture)];
+            let layout = self.pipeline.get_bind_group_layout(0);
+            let group = self.device.create_bind_group(&GpuBindGroupDescriptor::new(&entries, &layout));
+            self.texture = Some((texture, group, size));
+        }
+        let Some((texture, group, _)) = &self.texture else {
+            return Ok(());
+        };
+
+        let layout = GpuTexelCopyBufferLayout::new();
+        layout.set_bytes_per_row(size * 4);
+        self.device.queue().write_texture_with_u8_slice_and_u32_sequence(
+            &GpuTexelCopyTextureInfo::new(texture),
+            pixels,
+            &layout,
+            &[size.into(), size.into()],
+        )?;
+
+        let view = self.context.get_current_texture()?.create_view()?;
+        let attachment =
+            GpuRenderPassColorAttachment::new_with_gpu_texture_view(GpuLoadOp::Clear, GpuStoreOp::Store, &view);
+        let encoder = self.device.create_command_encoder();
+        let pass = encoder.begin_render_pass(&GpuRenderPassDescriptor::new(&[JsNullable::wrap(attachment)]))?;
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, Some(group));
+        pass.draw(6);
+        pass.end();
+        self.device.queue().submit(&[encoder.finish()]);
+        Ok(())
+    }
+}
+
+// navigator.gpu, if this browser has it. Looked up on the global object so
+// it also works in workers, where there is no window.
+fn gpu() -> Option<Gpu> {
+    let navigator = js_sys::Reflect::get(&js_sys::global(), &"navigator".into()).ok()?;
+    let gpu = js_sys::Reflect::get(&navigator, &"gpu".into()).ok()?;
+    (!gpu.is_undefined()).then(|| gpu.unchecked_into())
+}
@@ -0,0 +1,242 @@
+// A small text language for rule sets, so people designing rules can write
+//
+//   A likes B; A converts C -> B near B; B avoids A 2
+//
+// instead of editing flattened integer tables. Statements are separated by
+// ';' or newlines, and '#' starts a comment running to the end of the line.
+//
+//   X likes Y[, Z ...] [n]     affinity of X towards each target is n (1-127,
+//                              default 1)
+//   X avoids Y[, Z ...] [n]    affinity is -n (n up to 128)
+//   X ignores Y[, Z ...]       affinity is 0
+//   X converts Y -> Z [near Z] X's reaction: near a Z particle it turns the
+//                              Y particles around it into Z. The trigger is
+//                              always the product, so "near" may only name Z.
+//
+// Types are named by their type name (when it is a single word), by letter
+// (A is type 1, B type 2, ...), by number, or as "empty" for type 0. A rule
+// text describes the whole rule set: pairs it does not mention get affinity
+// 0 and types without a "converts" statement get no reaction.
+// export_rules() writes the current tables back in this form.
+
+use wasm_bindgen::prelude::*;
+
+use crate::ParticleGrid;
+
+// The parsed tables, indexed like the simulation's
+struct RuleTables {
+    affinity: Vec<Vec<i8>>,
+    copy_type: Vec<u8>,
+    replace_type: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl ParticleGrid {
+    // Replace the affinity and reaction tables with those described by
+    // `text` (see the top of rule_dsl.rs). On error nothing changes and the
+    // message names the statement at fault.
+    #[wasm_bindgen]
+    pub fn load_rules(&mut self, text: &str) -> Result<(), String> {
+        let tables = self.parse_rules(text)?;
+        self.rules.affinity = tables.affinity;
+        self.rules.copy_type = tables.copy_type;
+        self.rules.replace_type = tables.replace_type;
+        Ok(())
+    }
+
+    // The affinity and reaction tables as rule text load_rules accepts,
+    // one statement per line, with types written by letter
+    #[wasm_bindgen]
+    pub fn export_rules(&self) -> String {
+        let name = |t: usize| if t == 0 { "empty".to_string() } else { letter_name(t) };
+        let mut lines = Vec::new();
+        for from in 1..=self.num_types {
+            for (to, &a) in self.rules.affinity[from].iter().enumerate() {
+                let strength = if a.unsigned_abs() > 1 { format!(" {}", a.unsigned_abs()) } else { String::new() };
+                match a {
+                    0 => {}
+                    a if a > 0 => lines.push(format!("{} likes {}{}", name(from), name(to), strength)),
+                    _ => lines.push(format!("{} avoids {}{}", name(from), name(to), strength)),
+                }
+            }
+        }
+        for t in 1..=self.num_types {
+            let (ct, rt) = (self.rules.copy_type[t] as usize, self.rules.replace_type[t] as usize);
+            if ct != rt {
+                lines.push(format!("{} converts {} -> {}", name(t), name(rt), name(ct)));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+impl ParticleGrid {
+    fn parse_rules(&self, text: &str) -> Result<RuleTables, String> {
+        let n = self.num_types + 1;
+        let mut tables = RuleTables {
+            affinity: vec![vec![0; n]; n],
+            copy_type: (0..n as u8).collect(),
+            replace_type: (0..n as u8).collect(),
+        };
+        let statements = text.lines().flat_map(|line| line.split('#').next().unwrap_or("").split(';'));
+        for (index, statement) in statements.filter(|s| !s.trim().is_empty()).enumerate() {
+            self.parse_statement(statement, &mut tables)
+                .map_err(|e| format!("statement {} ('{}'): {}", index + 1, statement.trim(), e))?;
+        }
+        Ok(tables)
+    }
+
+    fn parse_statement(&self, statement: &str, tables: &mut RuleTables) -> Result<(), String> {
+        let spaced = statement.replace("->", " -> ").replace(',', " , ");
+        let mut tokens = spaced.split_whitespace();
+        let subject = self.parse_type(tokens.next())?;
+        let verb = tokens.next().ok_or("expected 'likes', 'avoids', 'ignores' or 'converts'")?;
+        match verb.to_ascii_lowercase().as_str() {
+            verb @ ("likes" | "avoids" | "ignores") => {
+                let mut targets = vec![self.parse_type(tokens.next())?];
+                let mut strength = 1;
+                while let Some(token) = tokens.next() {
+                    if token == "," {
+                        targets.push(self.parse_type(tokens.next())?);
+                        continue;
+                    }
+                    if verb == "ignores" {
+                        return Err(format!("unexpected '{}'; 'ignores' takes no strength", token));
+                    }
+                    // Affinities run from -128 to 127
+                    let max = if verb == "avoids" { 128 } else { 127 };
+                    strength = token
+                        .parse::<i16>()
+                        .ok()
+                        .filter(|&s| (1..=max).contains(&s))
+                        .ok_or(format!("expected a strength from 1 to {}, found '{}'", max, token))?;
+                    if let Some(extra) = tokens.next() {
+                        return Err(format!("unexpected '{}' after the strength", extra));
+                    }
+                }
+                let value = match verb {
+                    "likes" => strength as i8,
+                    "avoids" => -strength as i8,
+                    _ => 0,
+                };
+                for target in targets {
+                    tables.affinity[subject as usize][target as usize] = value;
+                }
+            }
+            "converts" => {
+                if subject == 0 {
+                    return Err("empty space has no reaction".to_string());
+                }
+                let from = self.parse_type(tokens.next())?;
+                match tokens.next() {
+                    Some("->") => {}
+                    other => return Err(format!("expected '->', found {}", describe(other))),
+                }
+                let to = self.parse_type(tokens.next())?;
+                match tokens.next() {
+                    None => {}
+                    Some(word) if word.eq_ignore_ascii_case("near") => {
+                        let near = self.parse_type(tokens.next())?;
+                        if near != to {
+                            return Err("a reaction is triggered by its product, so 'near' must name it".to_string());
+                        }
+                        if let Some(extra) = tokens.next() {
+                            return Err(format!("unexpected '{}' at the end", extra));
+                        }
+                    }
+                    Some(other) => return Err(format!("expected 'near' or the end, found '{}'", other)),
+                }
+                if from == to {
+                    return Err("a reaction must turn one type into another".to_string());
+                }
+                let t = subject as usize;
+                if tables.copy_type[t] != tables.replace_type[t] {
+                    return Err("the type already has a reaction".to_string());
+                }
+                tables.copy_type[t] = to;
+                tables.replace_type[t] = from;
+            }
+            other => {
+                return Err(format!("expected 'likes', 'avoids', 'ignores' or 'converts', found '{}'", other));
+            }
+        }
+        Ok(())
+    }
+
+    // A type by name, letter, number or "empty"
+    fn parse_type(&self, token: Option<&str>) -> Result<u8, String> {
+        let found = describe(token);
+        let token = token.filter(|&t| t != "," && t != "->").ok_or(format!("expected a type, found {}", found))?;
+        let n = self.num_types;
+        let by_name = self.type_names.iter().skip(1).position(|name| name.eq_ignore_ascii_case(token)).map(|i| i + 1);
+        let by_letter = match token.as_bytes() {
+            &[c] if c.is_ascii_alphabetic() => Some((c.to_ascii_uppercase() - b'A') as usize + 1),
+            _ => None,
+        };
+        let t = if token.eq_ignore_ascii_case("empty") {
+            Some(0)
+        } else {
+            // Letters first, so export_rules output reads back the same
+            by_letter.filter(|&t| t <= n).or(by_name).or(by_letter).or_else(|| token.parse().ok())
+        };
+        match t {
+            Some(t) if t <= n => Ok(t as u8),
+            Some(_) => Err(format!("'{}' is not one of the {} types", token, n)),
+            None => Err(format!("unknown type '{}'", token)),
+        }
+    }
+}
+
+// Letters for types 1-26, then numbers
+fn letter_name(t: usize) -> String {
+    if t <= 26 { ((b'A' + t as u8 - 1) as char).to_string() } else { t.to_string() }
+}
+
+fn describe(token: Option<&str>) -> String {
+    token.map_or("the end".to_string(), |t| format!("'{}'", t))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::config::SimulationConfig;
+    use crate::ParticleGrid;
+
+    fn grid() -> ParticleGrid {
+        ParticleGrid::from_config(&SimulationConfig { size: 8, num_types: 3, seed: Some(6), ..Default::default() })
+    }
+
+    #[test]
+    fn loads_and_exports_rules() {
+        let mut grid = grid();
+        grid.load_rules("A likes B, C 3 # comment\nB avoids A 128; A converts C -> B near B").unwrap();
+        assert_eq!(grid.rules.affinity[1][2..], [3, 3]);
+        assert_eq!(grid.rules.affinity[2][1], -128);
+        assert_eq!((grid.rules.copy_type[1], grid.rules.replace_type[1]), (2, 3));
+        assert_eq!(grid.rules.copy_type[2], grid.rules.replace_type[2]);
+
+        let text = grid.export_rules();
+        let mut copy = self::grid();
+        copy.load_rules(&text).unwrap();
+        assert_eq!(copy.export_rules(), text);
+    }
+
+    #[test]
+    fn errors_name_the_statement() {
+        let mut grid = grid();
+        let before = grid.export_rules();
+        let error = |grid: &mut ParticleGrid, text: &str| grid.load_rules(text).unwrap_err();
+        assert!(error(&mut grid, "A likes B; A likes D").starts_with("statement 2 ('A likes D')"));
+        assert!(error(&mut grid, "A likes B 128").contains("strength from 1 to 127"));
+        assert!(error(&mut grid, "A ignores B 2").contains("takes no strength"));
+        assert!(error(&mut grid, "A hates B").contains("expected 'likes'"));
+        assert!(error(&mut grid, "empty converts A -> B").contains("empty space"));
+        assert!(error(&mut grid, "A converts B -> C near B").contains("must name it"));
+        assert!(error(&mut grid, "A converts B -> B").contains("one type into another"));
+        assert!(error(&mut grid, "A converts B -> C; A converts C -> B").contains("already has a reaction"));
+        assert!(error(&mut grid, "A likes zebra").contains("unknown type 'zebra'"));
+        assert!(error(&mut grid, "A likes 9").contains("not one of the"));
+        // Failed loads leave the rules alone
+        assert_eq!(grid.export_rules(), before);
+    }
+}
@@ -0,0 +1,282 @@
+// Save files. A save is the magic bytes "PAGS", a little-endian u32 format
+// version, and a JSON body holding the config, the cells (as a pattern
+// string) and the simulation clock. Since version 2 the body is
+// LZ4-compressed (see compress.rs), so the cells are written in the rle
+// form rather than compressed twice. Version 3 adds the per-cell state the
+// config does not describe: pins, terrain, energy reserves and density
+// targets. Loading runs the body through every migration between its
+// version and the current one, so saves written by older builds keep
+// working after the layout changes.
+//
+// The random generator is not saved; a loaded grid continues with a fresh
+// seed.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use wasm_bindgen::prelude::*;
+
+use crate::compress;
+use crate::config::SimulationConfig;
+use crate::pattern;
+use crate::ParticleGrid;
+
+const MAGIC: &[u8; 4] = b"PAGS";
+const SAVE_VERSION: u32 = 3;
+// Largest save body loaded, uncompressed
+const MAX_BODY_LEN: usize = 1 << 30;
+// Most cells in a loaded grid (a 4096x4096 grid). A few bytes of rle can
+// describe any size, so this bounds what a small file can make us allocate.
+const MAX_CELLS: usize = 1 << 24;
+
+// Rewrites a save body in place from one version to the next
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+// MIGRATIONS[i] upgrades a version i + 1 body to version i + 2. Append one
+// whenever SAVE_VERSION is bumped.
+const MIGRATIONS: &[Migration] = &[
+    // Version 2 only compresses the body
+    |_| Ok(()),
+    add_cell_state,
+];
+
+// Version 3: nothing pinned, flat terrain, no reserves or targets (energy
+// starts at the initial reserve, as older builds loaded it)
+fn add_cell_state(body: &mut Value) -> Result<(), String> {
+    let body = body.as_object_mut().ok_or("corrupt save: body is not an object")?;
+    for field in ["pins", "terrain", "energy", "target_densities"] {
+        body.entry(field).or_insert(Value::Array(Vec::new()));
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedState {
+    config: SimulationConfig,
+    cells: String,
+    generation: u64,
+    updates_performed: u64,
+    // Indices into export_grid's layout
+    pins: Vec<usize>,
+    // In export_grid's layout; empty for flat ground
+    terrain: Vec<f32>,
+    // In export_grid's layout; empty with the energy model off
+    energy: Vec<f32>,
+    // Indexed by type
+    target_densities: Vec<Option<f32>>,
+}
+
+#[wasm_bindgen]
+impl ParticleGrid {
+    // Serialize the grid, rules and clock into a versioned save
+    #[wasm_bindgen]
+    pub fn save_state(&self) -> Vec<u8> {
+        let state = SavedState {
+            config: self.config(),
+            cells: pattern::encode_rle(&self.export_grid(), self.size),
+            generation: self.generation,
+            updates_performed: self.updates_performed,
+            pins: self.pinned_cells(),
+            terrain: self.terrain_speeds(),
+            energy: if self.energy.is_some() { self.export_energy() } else { Vec::new() },
+            target_densities: self.target_densities().to_vec(),
+        };
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&SAVE_VERSION.to_le_bytes());
+        out.extend_from_slice(&compress::compress(&serde_json::to_vec(&state).unwrap_or_default()));
+        out
+    }
+
+    // Restore a grid from save_state output, upgrading older saves
+    #[wasm_bindgen]
+    pub fn load_state(bytes: &[u8]) -> Result<ParticleGrid, String> {
+        let body = bytes.strip_prefix(MAGIC).ok_or("not a particle grid save")?;
+        if body.len() < 4 {
+            return Err("save is truncated".to_string());
+        }
+        let (version, body) = body.split_at(4);
+        let version = u32::from_le_bytes([version[0], version[1], version[2], version[3]]);
+        if version == 0 || version > SAVE_VERSION {
+            return Err(format!(
+                "save format version {} is not supported (this build reads up to {})",
+                version, SAVE_VERSION
+            ));
+        }
+
+        let body = if version >= 2 { compress::decompress(body, MAX_BODY_LEN)? } else { body.to_vec() };
+        let mut value: Value = serde_json::from_slice(&body).map_err(|e| format!("corrupt save: {}", e))?;
+        for migrate in &MIGRATIONS[version as usize - 1..] {
+            migrate(&mut value)?;
+        }
+        let state: SavedState = serde_json::from_value(value).map_err(|e| format!("corrupt save: {}", e))?;
+
+        let config = SimulationConfig { seed: None, ..state.config };
+        // The size comes from the file, so check it before allocating
+        let cell_count = config.size.checked_mul(config.size).filter(|&n| n <= MAX_CELLS);
+        if cell_count.is_none() || config.num_types > u8::MAX as usize {
+            let (size, types) = (config.size, config.num_types);
+            return Err(format!("save holds a {0}x{0} grid of {1} types, too large to load", size, types));
+        }
+        let cells = pattern::decode_pattern(&state.cells, config.size, config.num_types)?;
+        let mut grid = ParticleGrid::from_config(&config);
+        grid.set_cells(&cells);
+        grid.generation = state.generation;
+        grid.updates_performed = state.updates_performed;
+
+        let n = config.size;
+        for &i in &state.pins {
+            if i >= n * n || !grid.pin_cell(i % n, i / n) {
+                return Err(format!("corrupt save: pin on empty or missing cell {}", i));
+            }
+        }
+        if !state.terrain.is_empty() && !grid.set_terrain(state.terrain) {
+            return Err("corrupt save: terrain does not match the grid".to_string());
+        }
+        if !state.energy.is_empty() && !grid.restore_energy(&state.energy) {
+            return Err("corrupt save: energy reserves do not match the grid".to_string());
+        }
+        for (t, target) in state.target_densities.iter().enumerate() {
+            if let &Some(fraction) = target {
+                if t > u8::MAX as usize || !grid.set_target_density(t as u8, fraction) {
+                    return Err(format!("corrupt save: bad density target for type {}", t));
+                }
+            }
+        }
+        grid.refresh_output();
+        Ok(grid)
+    }
+
+    // Format version written by save_state
+    #[wasm_bindgen]
+    pub fn save_format_version() -> u32 {
+        SAVE_VERSION
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> ParticleGrid {
+        ParticleGrid::from_config(&SimulationConfig { size: 16, num_types: 3, seed: Some(4), ..Default::default() })
+    }
+
+    // A save of `version` with `body` as its JSON
+    fn save_with(version: u32, body: &Value) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&version.to_le_bytes());
+        out.extend_from_slice(&compress::compress(body.to_string().as_bytes()));
+        out
+    }
+
+    fn load_error(bytes: &[u8]) -> String {
+        ParticleGrid::load_state(bytes).err().expect("load should fail")
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut grid = grid();
+        for _ in 0..5 {
+            grid.step();
+        }
+        let loaded = ParticleGrid::load_state(&grid.save_state()).unwrap();
+        assert_eq!(loaded.export_grid(), grid.export_grid());
+        assert_eq!(loaded.generation, 5);
+        assert_eq!(loaded.config(), grid.config());
+    }
+
+    #[test]
+    fn round_trip_keeps_cell_state() {
+        let mut grid = grid();
+        grid.set_energy(Some(Default::default()));
+        for _ in 0..3 {
+            grid.step();
+        }
+        let (x, y) = (0..16 * 16).map(|i| (i % 16, i / 16)).find(|&(x, y)| grid.cell(x, y) != 0).unwrap();
+        grid.pin_cell(x, y);
+        grid.set_terrain((0..16 * 16).map(|i| (i % 3) as f32).collect());
+        grid.set_target_density(2, 0.1);
+
+        let loaded = ParticleGrid::load_state(&grid.save_state()).unwrap();
+        assert!(loaded.is_pinned(x, y));
+        assert_eq!(loaded.pinned_cells(), grid.pinned_cells());
+        assert_eq!(loaded.terrain_speeds(), grid.terrain_speeds());
+        assert_eq!(loaded.export_energy(), grid.export_energy());
+        assert_eq!(loaded.target_density(2), Some(0.1));
+    }
+
+    #[test]
+    fn cells_are_saved_as_runs() {
+        let save = grid().save_state();
+        let body = compress::decompress(&save[8..], MAX_BODY_LEN).unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert!(body["cells"].as_str().unwrap().starts_with("rle:16:"));
+    }
+
+    #[test]
+    fn loads_version_2_saves() {
+        let grid = grid();
+        let body = serde_json::json!({
+            "config": grid.config(),
+            "cells": grid.export_pattern_string(),
+            "generation": 7,
+            "updates_performed": 0,
+        });
+        let loaded = ParticleGrid::load_state(&save_with(2, &body)).unwrap();
+        assert_eq!(loaded.export_grid(), grid.export_grid());
+        assert_eq!(loaded.generation, 7);
+        assert!(loaded.terrain_speeds().is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_cell_state() {
+        let grid = grid();
+        let mut body = serde_json::to_value(SavedState {
+            config: grid.config(),
+            cells: grid.export_pattern_string(),
+            generation: 0,
+            updates_performed: 0,
+            pins: vec![16 * 16],
+            terrain: Vec::new(),
+            energy: Vec::new(),
+            target_densities: Vec::new(),
+        })
+        .unwrap();
+        assert!(load_error(&save_with(SAVE_VERSION, &body)).contains("pin"));
+        body["pins"] = serde_json::json!([]);
+        body["terrain"] = serde_json::json!([1.0, 2.0]);
+        assert!(load_error(&save_with(SAVE_VERSION, &body)).contains("terrain"));
+        body["terrain"] = serde_json::json!([]);
+        body["energy"] = serde_json::json!([1.0]);
+        assert!(load_error(&save_with(SAVE_VERSION, &body)).contains("energy"));
+        body["energy"] = serde_json::json!([]);
+        body["target_densities"] = serde_json::json!([null, null, null, null, 0.5]);
+        assert!(load_error(&save_with(SAVE_VERSION, &body)).contains("type 4"));
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let save = grid().save_state();
+        assert!(ParticleGrid::load_state(b"nope").is_err());
+        assert!(ParticleGrid::load_state(&save[..6]).is_err());
+        let mut future = save.clone();
+        future[4..8].copy_from_slice(&(SAVE_VERSION + 1).to_le_bytes());
+        assert!(load_error(&future).contains("not supported"));
+        assert!(ParticleGrid::load_state(&save[..save.len() - 3]).is_err());
+    }
+
+    #[test]
+    fn rejects_huge_grids() {
+        let config = SimulationConfig { size: 1 << 20, num_types: 2, ..Default::default() };
+        let body = serde_json::json!({
+            "config": config,
+            "cells": format!("rle:{}:AAA=", 1 << 20),
+            "generation": 0,
+            "updates_performed": 0,
+        });
+        assert!(load_error(&save_with(2, &body)).contains("too large"));
+
+        let config = SimulationConfig { size: usize::MAX, ..config };
+        let body = serde_json::json!({ "config": config, "cells": "", "generation": 0, "updates_performed": 0 });
+        assert!(load_error(&save_with(2, &body)).contains("too large"));
+    }
+}
@@ -0,0 +1,150 @@
+// Selection queries for interactive tools: marquee (rectangle) selection
+// with live per-type counts, and flood-fill selection of a cluster.
+
+use wasm_bindgen::prelude::*;
+
+use crate::core::Region;
+use crate::ParticleGrid;
+
+// A connected same-type blob picked with select_cluster
+#[wasm_bindgen]
+pub struct ClusterSelection {
+    particle_type: u8,
+    cells: Vec<u32>,
+    bounds: Region,
+}
+
+#[wasm_bindgen]
+impl ClusterSelection {
+    #[wasm_bindgen(getter)]
+    pub fn particle_type(&self) -> u8 {
+        self.particle_type
+    }
+
+    // Member cells as flat [x, y, x, y, ...] pairs
+    #[wasm_bindgen]
+    pub fn cells(&self) -> Vec<u32> {
+        self.cells.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn len(&self) -> usize {
+        self.cells.len() / 2
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    // Inclusive bounding box
+    #[wasm_bindgen(getter)]
+    pub fn min_x(&self) -> usize {
+        self.bounds.x0
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn min_y(&self) -> usize {
+        self.bounds.y0
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn max_x(&self) -> usize {
+        self.bounds.x1
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn max_y(&self) -> usize {
+        self.bounds.y1
+    }
+}
+
+#[wasm_bindgen]
+impl ParticleGrid {
+    // Particles inside the rectangle (x0, y0)-(x1, y1), corners inclusive
+    // and in either order, as flat [x, y, type, x, y, type, ...] triples
+    // in row-major order
+    #[wasm_bindgen]
+    pub fn query_region(&self, x0: usize, y0: usize, x1: usize, y1: usize) -> Vec<u32> {
+        let Some(region) = Region::clamped(x0, y0, x1, y1, self.size) else {
+            return Vec::new();
+        };
+
+        let mut out = Vec::new();
+        for y in region.y0..=region.y1 {
+            for x in region.x0..=region.x1 {
+                let t = self.type_grid[x][y];
+                if t != 0 {
+                    out.extend_from_slice(&[x as u32, y as u32, t as u32]);
+                }
+            }
+        }
+        out
+    }
+
+    // Cell counts inside the rectangle, indexed by type (entry 0 counts
+    // empty cells)
+    #[wasm_bindgen]
+    pub fn count_region(&self, x0: usize, y0: usize, x1: usize, y1: usize) -> Vec<u32> {
+        let mut counts = vec![0u32; self.num_types + 1];
+        if let Some(region) = Region::clamped(x0, y0, x1, y1, self.size) {
+            for column in &self.type_grid[region.x0..=region.x1] {
+                for &t in &column[region.y0..=region.y1] {
+                    counts[t as usize] += 1;
+                }
+            }
+        }
+        counts
+    }
+
+    // Flood-fill the 8-connected cluster of same-type particles containing
+    // (x, y). Returns undefined for an empty or out-of-range cell.
+    #[wasm_bindgen]
+    pub fn select_cluster(&self, x: usize, y: usize) -> Option<ClusterSelection> {
+        if x >= self.size || y >= self.size || self.type_grid[x][y] == 0 {
+            return None;
+        }
+
+        let mut visited = vec![false; self.size * self.size];
+        let members = flood_fill(&self.type_grid, x, y, &mut visited);
+
+        let mut bounds = Region { x0: x, y0: y, x1: x, y1: y };
+        let mut cells = Vec::with_capacity(members.len() * 2);
+        for (cx, cy) in members {
+            bounds.x0 = bounds.x0.min(cx);
+            bounds.y0 = bounds.y0.min(cy);
+            bounds.x1 = bounds.x1.max(cx);
+            bounds.y1 = bounds.y1.max(cy);
+            cells.extend_from_slice(&[cx as u32, cy as u32]);
+        }
+
+        Some(ClusterSelection {
+            particle_type: self.type_grid[x][y],
+            cells,
+            bounds,
+        })
+    }
+}
+
+// Cells of the 8-connected same-type region containing (x, y), marking them
+// in `visited` (indexed x * size + y)
+pub(crate) fn flood_fill(grid: &[Vec<u8>], x: usize, y: usize, visited: &mut [bool]) -> Vec<(usize, usize)> {
+    let size = grid.len();
+    let t = grid[x][y];
+    let mut cells = Vec::new();
+    let mut stack = vec![(x, y)];
+    visited[x * size + y] = true;
+
+    while let Some((cx, cy)) = stack.pop() {
+        cells.push((cx, cy));
+        for j in cy.saturating_sub(1)..=(cy + 1).min(size - 1) {
+            for i in cx.saturating_sub(1)..=(cx + 1).min(size - 1) {
+                if grid[i][j] == t && !visited[i * size + j] {
+                    visited[i * size + j] = true;
+                    stack.push((i, j));
+                }
+            }
+        }
+    }
+    cells
+}
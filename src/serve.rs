@@ -0,0 +1,137 @@
+// Headless video server (native builds only).
+//
+// serve() runs a simulation on its own thread and streams it over plain
+// HTTP as MJPEG, which every browser shows in an <img> tag, so a big
+// machine can step a huge grid while any browser on the network watches.
+// Frames are encoded once and shared by all viewers; a slow viewer just
+// skips frames rather than holding the simulation back.
+//
+//   /            a page showing the stream, scaled up with crisp pixels
+//   /stream.mjpg the MJPEG stream (multipart/x-mixed-replace)
+//   /frame.jpg   the latest frame on its own
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::SimulationConfig;
+use crate::stream::{FrameFormat, FrameStream};
+use crate::ParticleGrid;
+
+// Frames are produced at most this often
+const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+
+const PAGE: &str = "<!doctype html>
+<title>particle affinity</title>
+<style>body{margin:0;background:#000;display:grid;place-items:center;height:100vh}
+img{height:100vmin;image-rendering:pixelated}</style>
+<img src=\"/stream.mjpg\" alt=\"simulation\">
+";
+
+// Latest encoded frame and its number, for the viewer threads
+struct Latest {
+    frame: Mutex<(u64, Arc<Vec<u8>>)>,
+    fresh: Condvar,
+}
+
+impl Latest {
+    // Block until a frame newer than `seen` is out
+    fn after(&self, seen: u64) -> (u64, Arc<Vec<u8>>) {
+        let mut frame = self.frame.lock().unwrap_or_else(|e| e.into_inner());
+        while frame.0 <= seen {
+            frame = self.fresh.wait(frame).unwrap_or_else(|e| e.into_inner());
+        }
+        frame.clone()
+    }
+}
+
+// Serve the simulation described by `config` on `addr` (e.g.
+// "0.0.0.0:8080"), advancing one step per frame. Only returns if the
+// address cannot be bound.
+pub fn serve(config: &SimulationConfig, addr: &str) -> io::Result<()> {
+    serve_with(config, addr, 1)
+}
+
+// serve() with `steps_per_frame` steps between frames
+pub fn serve_with(config: &SimulationConfig, addr: &str, steps_per_frame: usize) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    let mut stream = FrameStream::new(ParticleGrid::from_config(config), steps_per_frame, FrameFormat::Jpeg);
+    let latest = Arc::new(Latest {
+        frame: Mutex::new((1, Arc::new(stream.current_frame()))),
+        fresh: Condvar::new(),
+    });
+
+    let producer = Arc::clone(&latest);
+    thread::spawn(move || loop {
+        let started = Instant::now();
+        let frame = Arc::new(stream.next_frame());
+        {
+            let mut latest = producer.frame.lock().unwrap_or_else(|e| e.into_inner());
+            *latest = (latest.0 + 1, frame);
+        }
+        producer.fresh.notify_all();
+        if let Some(rest) = FRAME_INTERVAL.checked_sub(started.elapsed()) {
+            thread::sleep(rest);
+        }
+    });
+
+    log_info!("Serving on http://{}", listener.local_addr()?);
+    for connection in listener.incoming() {
+        let Ok(connection) = connection else {
+            continue;
+        };
+        let latest = Arc::clone(&latest);
+        // Viewers hanging up is routine, so write errors just end the thread
+        thread::spawn(move || {
+            let _ = respond(connection, &latest);
+        });
+    }
+    Ok(())
+}
+
+fn respond(mut connection: TcpStream, latest: &Latest) -> io::Result<()> {
+    // The request line is all that matters; read the head and ignore the rest
+    let mut head = [0u8; 4096];
+    let n = connection.read(&mut head)?;
+    let head = String::from_utf8_lossy(&head[..n]);
+    let path = head.split_whitespace().nth(1).unwrap_or("/");
+
+    match path {
+        "/" => {
+            let header = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                PAGE.len()
+            );
+            connection.write_all(header.as_bytes())?;
+            connection.write_all(PAGE.as_bytes())
+        }
+        "/frame.jpg" => {
+            let (_, frame) = latest.after(0);
+            let header = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\
+                 Cache-Control: no-cache\r\nConnection: close\r\n\r\n",
+                frame.len()
+            );
+            connection.write_all(header.as_bytes())?;
+            connection.write_all(&frame)
+        }
+        "/stream.mjpg" => {
+            connection.write_all(
+                b"HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary=frame\r\n\
+                  Cache-Control: no-cache\r\nConnection: close\r\n\r\n",
+            )?;
+            let mut seen = 0;
+            loop {
+                let (number, frame) = latest.after(seen);
+                seen = number;
+                let part = format!("--frame\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n", frame.len());
+                connection.write_all(part.as_bytes())?;
+                connection.write_all(&frame)?;
+                connection.write_all(b"\r\n")?;
+            }
+        }
+        _ => connection.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"),
+    }
+}
@@ -0,0 +1,141 @@
+// Pull-based frame source for thin-client rendering. The consumer asks for
+// each frame with next_frame(), which advances the simulation and returns
+// the encoded image, so a slow ReadableStream or WebSocket reader naturally
+// throttles the simulation instead of frames piling up.
+
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::{ExtendedColorType, ImageEncoder};
+use wasm_bindgen::prelude::*;
+
+use crate::ParticleGrid;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameFormat {
+    // Raw RGBA8, size * size * 4 bytes, rows top to bottom
+    Rgba = 0,
+    Png = 1,
+    // Baseline JPEG at quality 90: lossy, but smaller than the PNG
+    Jpeg = 2,
+}
+
+#[wasm_bindgen]
+pub struct FrameStream {
+    grid: ParticleGrid,
+    steps_per_frame: usize,
+    format: FrameFormat,
+    frames: usize,
+}
+
+#[wasm_bindgen]
+impl FrameStream {
+    // Takes ownership of `grid`; the JS handle passed in is consumed
+    #[wasm_bindgen(constructor)]
+    pub fn new(grid: ParticleGrid, steps_per_frame: usize, format: FrameFormat) -> FrameStream {
+        FrameStream {
+            grid,
+            steps_per_frame,
+            format,
+            frames: 0,
+        }
+    }
+
+    // Step the simulation `steps_per_frame` times and encode the result
+    #[wasm_bindgen]
+    pub fn next_frame(&mut self) -> Vec<u8> {
+        for _ in 0..self.steps_per_frame {
+            self.grid.step();
+        }
+        self.frames += 1;
+        self.encode()
+    }
+
+    // Encode the current state without stepping (e.g. the first frame)
+    #[wasm_bindgen]
+    pub fn current_frame(&self) -> Vec<u8> {
+        self.encode()
+    }
+
+    #[wasm_bindgen]
+    pub fn set_steps_per_frame(&mut self, steps: usize) {
+        self.steps_per_frame = steps;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> usize {
+        self.grid.size
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> usize {
+        self.grid.size
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    // Hand the grid back, ending the stream
+    #[wasm_bindgen]
+    pub fn into_grid(self) -> ParticleGrid {
+        self.grid
+    }
+}
+
+impl FrameStream {
+    pub fn grid(&self) -> &ParticleGrid {
+        &self.grid
+    }
+
+    pub fn grid_mut(&mut self) -> &mut ParticleGrid {
+        &mut self.grid
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let size = self.grid.size;
+        let rgba = self.grid.export_region_rgba(0, 0, size, size);
+        match self.format {
+            FrameFormat::Rgba => rgba,
+            FrameFormat::Png => encode_png(size, &rgba),
+            FrameFormat::Jpeg => encode_jpeg(size, &rgba),
+        }
+    }
+}
+
+fn encode_png(size: usize, rgba: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let encoder = PngEncoder::new(&mut out);
+    if let Err(e) = encoder.write_image(rgba, size as u32, size as u32, ExtendedColorType::Rgba8) {
+        log_warn!("could not encode frame: {}", e);
+    }
+    out
+}
+
+fn encode_jpeg(size: usize, rgba: &[u8]) -> Vec<u8> {
+    let rgb: Vec<u8> = rgba.chunks_exact(4).flat_map(|p| [p[0], p[1], p[2]]).collect();
+    let mut out = Vec::new();
+    let mut encoder = JpegEncoder::new_with_quality(&mut out, 90);
+    if let Err(e) = encoder.encode(&rgb, size as u32, size as u32, ExtendedColorType::Rgb8) {
+        log_warn!("could not encode frame: {}", e);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SimulationConfig;
+
+    #[test]
+    fn png_frames_decode_to_the_rgba_frame() {
+        let config = SimulationConfig { size: 12, num_types: 3, seed: Some(5), ..Default::default() };
+        let mut rgba = FrameStream::new(ParticleGrid::from_config(&config), 2, FrameFormat::Rgba);
+        let mut png = FrameStream::new(ParticleGrid::from_config(&config), 2, FrameFormat::Png);
+        let expected = rgba.next_frame();
+        let decoded = image::load_from_memory(&png.next_frame()).unwrap().to_rgba8();
+        assert_eq!(decoded.dimensions(), (12, 12));
+        assert_eq!(decoded.into_raw(), expected);
+    }
+}
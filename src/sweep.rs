@@ -0,0 +1,166 @@
+// Headless parameter sweeps (native builds only).
+//
+// Every combination of density, radius, noise and seed is run for a fixed
+// number of steps on the rayon pool, and the final state is summarized with
+// the metrics from `metrics.rs`. The seed drives the random affinity matrix
+// and reactions as well as the initial grid, so it doubles as a rule seed.
+
+use std::fmt::Write;
+
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::metrics::Metrics;
+use crate::ParticleGrid;
+
+#[derive(Clone, Debug)]
+pub struct Sweep {
+    pub size: usize,
+    pub num_types: usize,
+    pub steps: usize,
+    pub densities: Vec<f32>,
+    pub radii: Vec<usize>,
+    // Random move probability, given to every type
+    pub noises: Vec<f32>,
+    pub seeds: Vec<u64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SweepResult {
+    pub density: f32,
+    pub radius: usize,
+    pub noise: f32,
+    pub seed: u64,
+    #[serde(flatten)]
+    pub metrics: Metrics,
+}
+
+impl Sweep {
+    pub fn run(&self) -> Vec<SweepResult> {
+        let mut configs = Vec::new();
+        for &density in &self.densities {
+            for &radius in &self.radii {
+                for &noise in &self.noises {
+                    for &seed in &self.seeds {
+                        configs.push((density, radius, noise, seed));
+                    }
+                }
+            }
+        }
+
+        configs
+            .into_par_iter()
+            .map(|(density, radius, noise, seed)| {
+                let mut grid = ParticleGrid::with_seed(self.size, self.num_types, density, radius, None, seed, None);
+                for t in 1..=self.num_types {
+                    grid.set_type_noise(t as u8, noise);
+                }
+                for _ in 0..self.steps {
+                    grid.step();
+                }
+                SweepResult {
+                    density,
+                    radius,
+                    noise,
+                    seed,
+                    metrics: grid.metrics(),
+                }
+            })
+            .collect()
+    }
+}
+
+// Evenly spaced values from `start` to `end` inclusive
+pub fn range_f32(start: f32, end: f32, step: f32) -> Vec<f32> {
+    if step <= 0.0 || end < start {
+        return vec![start];
+    }
+    let n = ((end - start) / step + 1e-4).floor() as usize;
+    (0..=n).map(|i| start + i as f32 * step).collect()
+}
+
+fn survivors(metrics: &Metrics, sep: &str) -> String {
+    metrics
+        .survivor_types
+        .iter()
+        .map(|t| t.to_string())
+        .collect::<Vec<_>>()
+        .join(sep)
+}
+
+// Names as a CSV field, quoted since names are free text
+fn survivor_names(metrics: &Metrics) -> String {
+    format!("\"{}\"", metrics.survivor_names.join(";").replace('"', "\"\""))
+}
+
+pub fn to_csv(results: &[SweepResult]) -> String {
+    let mut out = String::from(
+        "density,radius,noise,seed,particles,cluster_count,largest_cluster,mean_cluster_size,type_entropy,\
+         survivor_types,survivor_names,pattern_entropy\n",
+    );
+    for r in results {
+        let m = &r.metrics;
+        let _ = writeln!(
+            out,
+            "{},{},{},{},{},{},{},{:.4},{:.4},{},{},{:.4}",
+            r.density,
+            r.radius,
+            r.noise,
+            r.seed,
+            m.particles,
+            m.cluster_count,
+            m.largest_cluster,
+            m.mean_cluster_size,
+            m.type_entropy,
+            survivors(m, ";"),
+            survivor_names(m),
+            m.pattern_entropy
+        );
+    }
+    out
+}
+
+pub fn to_json(results: &[SweepResult]) -> String {
+    serde_json::to_string(results).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sweep() -> Sweep {
+        Sweep {
+            size: 16,
+            num_types: 3,
+            steps: 3,
+            densities: vec![0.2, 0.4],
+            radii: vec![1],
+            noises: vec![0.0, 0.5],
+            seeds: vec![u64::MAX],
+        }
+    }
+
+    #[test]
+    fn covers_every_combination() {
+        let results = sweep().run();
+        assert_eq!(results.len(), 4);
+        assert!(results.iter().any(|r| r.density == 0.4 && r.noise == 0.5));
+        assert_eq!(to_csv(&results).lines().count(), 5);
+        assert!(results.iter().all(|r| r.metrics.survivor_names.len() == r.metrics.survivor_types.len()));
+    }
+
+    #[test]
+    fn json_matches_csv_columns() {
+        let results = sweep().run();
+        let json: serde_json::Value = serde_json::from_str(&to_json(&results)).unwrap();
+        let first = &json[0];
+        assert_eq!(first["seed"].as_u64(), Some(u64::MAX));
+        assert_eq!(first["noise"].as_f64(), Some(0.0));
+        assert_eq!(first["particles"].as_u64(), Some(results[0].metrics.particles as u64));
+        assert_eq!(first["survivor_names"][0], "Type 1");
+        let header = to_csv(&results);
+        for column in header.lines().next().unwrap().split(',') {
+            assert!(first.get(column).is_some(), "JSON lacks {}", column);
+        }
+    }
+}
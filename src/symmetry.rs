@@ -0,0 +1,48 @@
+// Symmetric edits and reseeds on the wrapper. The modes and the mirrored
+// move rule are in core/symmetry.rs.
+
+use crate::core::{self, InitPattern, Symmetry};
+use crate::ParticleGrid;
+
+impl ParticleGrid {
+    // Switch symmetry mode. The current grid is made symmetric right away by
+    // copying the canonical cell of each orbit over its images.
+    pub fn set_symmetry(&mut self, symmetry: Symmetry) {
+        self.rules.symmetry = symmetry;
+        core::symmetrize(&mut self.type_grid, symmetry);
+        self.refresh_output();
+    }
+
+    // Run an edit and, under a symmetry mode, copy every cell it changed
+    // onto that cell's images
+    pub(crate) fn mirrored_edit<T>(&mut self, edit: impl FnOnce(&mut Self) -> T) -> T {
+        if self.rules.symmetry == Symmetry::None {
+            return edit(self);
+        }
+
+        let (result, written) = self.journaled(edit);
+        let size = self.size;
+        for (x, y, old) in written {
+            let t = self.type_grid[x][y];
+            if t != old {
+                for (ix, iy) in self.rules.symmetry.images(x, y, size) {
+                    self.write_cell(ix, iy, t);
+                }
+            }
+        }
+        result
+    }
+
+    pub(crate) fn fill_symmetric(&mut self, pattern: InitPattern) {
+        let sim = &mut self.sim;
+        sim.type_grid = core::fill_grid(
+            sim.size,
+            sim.num_types,
+            sim.density,
+            sim.type_densities.as_deref(),
+            pattern,
+            &mut sim.rng,
+        );
+        core::symmetrize(&mut sim.type_grid, sim.rules.symmetry);
+    }
+}
@@ -0,0 +1,180 @@
+// Hooks for painting into one grid from several browsers. The crate does no
+// networking: encode_update() hands the page bytes to send over whatever
+// transport it manages (a WebRTC data channel, a WebSocket relay) and the
+// page passes whatever arrives to apply_remote_update().
+//
+// Two kinds of update travel between peers:
+//
+//   edits  the cells this peer's user changed (through apply_edits, region
+//          moves or undo) since its last update, as they were painted
+//   full   every cell that differs from this peer's previous full update,
+//          painted or simulated; the first one covers the whole grid
+//
+// The usual set-up has one peer, the host, step the grid and send full
+// updates a few times a second, while the others only apply them and send
+// their edits: every browser then shows the host's run, and paint from any
+// of them lands in it. Peers that step on their own drift apart.
+//
+// An update is the magic bytes "PAGU", the sender's peer id (u32), its
+// sequence number (u64), the kind (0 edits, 1 full), all little-endian,
+// then a GridPatch (see patch.rs). Updates older than one already applied
+// from the same peer, and a peer's own updates echoed back, are dropped.
+
+use std::collections::BTreeMap;
+
+use wasm_bindgen::prelude::*;
+
+use crate::patch::GridPatch;
+use crate::ParticleGrid;
+
+const MAGIC: &[u8; 4] = b"PAGU";
+const HEADER_LEN: usize = 4 + 4 + 8 + 1;
+
+// What a remote update does to cells the local user has painted but not
+// yet sent
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    // Remote cells are written and the local edits to them dropped
+    RemoteWins = 0,
+    // The local edits stay and go out with the next update
+    LocalWins = 1,
+}
+
+pub(crate) struct Sync {
+    peer: u32,
+    policy: ConflictPolicy,
+    sequence: u64,
+    // Row-major cells as of the last full update sent
+    base: Option<Vec<u8>>,
+    // Unsent local edits: row-major index -> type painted
+    edits: BTreeMap<usize, u8>,
+    // Highest sequence applied from each peer
+    seen: BTreeMap<u32, u64>,
+}
+
+#[wasm_bindgen]
+impl ParticleGrid {
+    // Start recording local edits for other peers. `peer_id` must be
+    // unique among the peers of a session.
+    #[wasm_bindgen]
+    pub fn enable_sync(&mut self, peer_id: u32, policy: ConflictPolicy) {
+        self.sync = Some(Sync {
+            peer: peer_id,
+            policy,
+            sequence: 0,
+            base: None,
+            edits: BTreeMap::new(),
+            seen: BTreeMap::new(),
+        });
+    }
+
+    #[wasm_bindgen]
+    pub fn disable_sync(&mut self) {
+        self.sync = None;
+    }
+
+    #[wasm_bindgen]
+    pub fn set_conflict_policy(&mut self, policy: ConflictPolicy) {
+        if let Some(sync) = &mut self.sync {
+            sync.policy = policy;
+        }
+    }
+
+    // Local edits waiting for the next update
+    #[wasm_bindgen(getter)]
+    pub fn pending_sync_edits(&self) -> usize {
+        self.sync.as_ref().map_or(0, |sync| sync.edits.len())
+    }
+
+    // The next update to send: the local edits, or with `full` every cell
+    // changed since the last full update. Empty when sync is off or there
+    // is nothing to send.
+    #[wasm_bindgen]
+    pub fn encode_update(&mut self, full: bool) -> Vec<u8> {
+        let cells = self.export_grid();
+        let size = self.size;
+        let Some(sync) = &mut self.sync else {
+            return Vec::new();
+        };
+        let patch = if full {
+            let patch = match &sync.base {
+                Some(base) => GridPatch::between(size, base, &cells),
+                None => GridPatch { size, changes: cells.iter().copied().enumerate().collect() },
+            };
+            sync.base = Some(cells);
+            patch
+        } else {
+            GridPatch { size, changes: sync.edits.iter().map(|(&i, &t)| (i, t)).collect() }
+        };
+        // A full update carries the edits too
+        sync.edits.clear();
+        if patch.is_empty() {
+            return Vec::new();
+        }
+        sync.sequence += 1;
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&sync.peer.to_le_bytes());
+        bytes.extend_from_slice(&sync.sequence.to_le_bytes());
+        bytes.push(full as u8);
+        bytes.extend_from_slice(&patch.to_bytes());
+        bytes
+    }
+
+    // Apply an update from another peer under the conflict policy. Returns
+    // how many cells changed (0 for stale or echoed updates); errors if sync
+    // is off or the update is malformed or made for another grid.
+    #[wasm_bindgen]
+    pub fn apply_remote_update(&mut self, bytes: &[u8]) -> Result<usize, String> {
+        if bytes.len() < HEADER_LEN || !bytes.starts_with(MAGIC) {
+            return Err("not a sync update".to_string());
+        }
+        let peer = u32::from_le_bytes(bytes[4..8].try_into().expect("4 bytes"));
+        let sequence = u64::from_le_bytes(bytes[8..16].try_into().expect("8 bytes"));
+        if bytes[16] > 1 {
+            return Err(format!("unknown sync update kind {}", bytes[16]));
+        }
+        let patch = GridPatch::from_bytes(&bytes[HEADER_LEN..])?;
+        if patch.size != self.size || patch.changes.iter().any(|&(_, t)| t as usize > self.num_types) {
+            return Err("sync update does not fit this grid".to_string());
+        }
+
+        let size = self.size;
+        let Some(sync) = &mut self.sync else {
+            return Err("sync is off".to_string());
+        };
+        if peer == sync.peer || sync.seen.get(&peer).is_some_and(|&seen| seen >= sequence) {
+            return Ok(0);
+        }
+        sync.seen.insert(peer, sequence);
+
+        let mut changed = 0;
+        for &(i, t) in &patch.changes {
+            if sync.policy == ConflictPolicy::LocalWins && sync.edits.contains_key(&i) {
+                continue;
+            }
+            sync.edits.remove(&i);
+            let cell = &mut self.sim.type_grid[i % size][i / size];
+            if *cell != t {
+                *cell = t;
+                changed += 1;
+            }
+        }
+        if changed > 0 {
+            self.refresh_output();
+        }
+        Ok(changed)
+    }
+}
+
+impl ParticleGrid {
+    // Called by the edit functions with the cells they changed
+    pub(crate) fn record_sync_edits(&mut self, cells: impl IntoIterator<Item = (usize, usize)>) {
+        let Some(sync) = &mut self.sync else {
+            return;
+        };
+        for (x, y) in cells {
+            sync.edits.insert(y * self.sim.size + x, self.sim.type_grid[x][y]);
+        }
+    }
+}
@@ -0,0 +1,288 @@
+// Property checks for the rule engine, shared by the fuzz targets in fuzz/
+// and the property test in tests/. Inputs are plain bytes: a Source turns
+// them into a random SimulationConfig (small grids, every boundary,
+// movement and symmetry mode, hand-set or random tables, energy on or off)
+// and a step count, so a fuzzer's mutations map onto configs directly and
+// a failing input replays exactly.
+//
+// Each check returns Err naming the first property that failed. Panics
+// (out-of-bounds indexing, overflow in debug builds) are left to
+// propagate, which is what the fuzzer reports.
+
+use rand::prelude::*;
+
+use crate::config::SimulationConfig;
+use crate::core::{BoundaryMode, EnergyConfig, MovementMode, Symmetry};
+use crate::patch::GridPatch;
+use crate::ParticleGrid;
+
+// Generated grids are at most this many cells on a side
+pub const MAX_SIZE: usize = 24;
+pub const MAX_TYPES: usize = 12;
+pub const MAX_STEPS: usize = 16;
+
+// Reads fuzz input as a stream of choices. Running out of bytes reads as
+// zeros, so every input, including the empty one, is a valid case.
+pub struct Source<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Source<'a> {
+    pub fn new(bytes: &'a [u8]) -> Source<'a> {
+        Source { bytes }
+    }
+
+    pub fn byte(&mut self) -> u8 {
+        match self.bytes.split_first() {
+            Some((&b, rest)) => {
+                self.bytes = rest;
+                b
+            }
+            None => 0,
+        }
+    }
+
+    pub fn u64(&mut self) -> u64 {
+        (0..8).fold(0, |v, _| v << 8 | self.byte() as u64)
+    }
+
+    // Uniform-ish in lo..=hi
+    pub fn range(&mut self, lo: usize, hi: usize) -> usize {
+        let v = (self.byte() as usize) << 8 | self.byte() as usize;
+        lo + v % (hi - lo + 1)
+    }
+
+    pub fn chance(&mut self) -> bool {
+        self.byte() & 1 == 1
+    }
+
+    // In 0-1
+    pub fn unit(&mut self) -> f32 {
+        self.byte() as f32 / 255.0
+    }
+
+    fn pick<T: Copy>(&mut self, options: &[T]) -> T {
+        options[self.range(0, options.len() - 1)]
+    }
+}
+
+// A config within the generator's bounds. Every float is finite, so the
+// config also survives a JSON round trip.
+pub fn config(src: &mut Source) -> SimulationConfig {
+    let size = src.range(1, MAX_SIZE);
+    let num_types = src.range(1, MAX_TYPES);
+    let n = num_types + 1;
+
+    let type_densities = src.chance().then(|| units(src, n + 1, 1.0));
+    // Empty, full or too small: the last two fall back to random rules
+    let affinity = match src.range(0, 2) {
+        0 => Vec::new(),
+        1 => (0..n).map(|_| (0..n).map(|_| src.byte() as i8).collect()).collect(),
+        _ => vec![vec![1; n - 1]; n],
+    };
+    let (copy_types, replace_types) = if src.chance() {
+        let table = |src: &mut Source| (0..n).map(|_| src.range(0, num_types) as u8).collect();
+        (table(src), table(src))
+    } else {
+        (Vec::new(), Vec::new())
+    };
+    let type_noise = units(src, n, 0.2);
+    let energy = src.chance().then(|| EnergyConfig {
+        initial: src.unit() * 2.0,
+        capacity: src.unit() * 4.0,
+        move_cost: src.unit() * 0.1,
+        drain: src.unit() * 0.05,
+        income: units(src, n, 0.1),
+        predation_gain: src.unit(),
+        offspring: src.unit(),
+    });
+
+    SimulationConfig {
+        size,
+        num_types,
+        density: src.unit(),
+        type_densities,
+        // Up to twice the grid, so windows overhang both edges
+        radius: src.range(0, 2 * size),
+        seed: Some(src.u64()),
+        affinity,
+        copy_types,
+        replace_types,
+        replace_radius: src.range(0, 3),
+        replace_probability: src.unit(),
+        max_conversions: src.range(0, 4),
+        attraction_scale: src.unit() * 2.0,
+        repulsion_scale: src.unit() * 2.0,
+        anisotropy: [src.unit() * 2.0, src.unit() * 2.0],
+        crowding: src.unit(),
+        update_fraction: src.unit() * 2.0,
+        type_noise,
+        energy,
+        symmetry: src.pick(&[Symmetry::None, Symmetry::Mirror, Symmetry::Four, Symmetry::Eight]),
+        boundary: src.pick(&BOUNDARIES),
+        movement: src.pick(&[MovementMode::Greedy, MovementMode::Threshold, MovementMode::Centroid]),
+        move_threshold: src.unit(),
+        softmax_sharpness: src.chance().then(|| src.unit() * 8.0),
+        tile_size: src.chance().then(|| src.range(1, 16)),
+        ..SimulationConfig::default()
+    }
+}
+
+const BOUNDARIES: [BoundaryMode; 4] =
+    [BoundaryMode::Clamp, BoundaryMode::Wrap, BoundaryMode::Reflect, BoundaryMode::Absorb];
+
+// Up to `max_len` values in 0-`scale`
+fn units(src: &mut Source, max_len: usize, scale: f32) -> Vec<f32> {
+    let len = src.range(0, max_len);
+    (0..len).map(|_| src.unit() * scale).collect()
+}
+
+// The config and step count drawn from `bytes`
+pub fn case(bytes: &[u8]) -> (SimulationConfig, usize) {
+    let mut src = Source::new(bytes);
+    let config = config(&mut src);
+    (config, src.range(0, MAX_STEPS))
+}
+
+// Every property, for the case drawn from `bytes`
+pub fn check_bytes(bytes: &[u8]) -> Result<(), String> {
+    let (config, steps) = case(bytes);
+    check_all(&config, steps)
+}
+
+pub fn check_all(config: &SimulationConfig, steps: usize) -> Result<(), String> {
+    check_boundaries(config, steps)?;
+    check_replay(config, steps)?;
+    let before = ParticleGrid::from_config(config);
+    let after = stepped(config, steps)?;
+    check_round_trips(&after)?;
+    check_patch(&before, &after)
+}
+
+// `count` cases from byte strings drawn from `seed`, for running the
+// properties without a fuzzer
+pub fn check_random(count: usize, seed: u64) -> Result<(), String> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    for case in 0..count {
+        let mut bytes = vec![0u8; rng.gen_range(0..256)];
+        rng.fill_bytes(&mut bytes);
+        check_bytes(&bytes).map_err(|e| format!("case {} ({:02x?}): {}", case, bytes, e))?;
+    }
+    Ok(())
+}
+
+// A grid from `config` stepped `steps` times with invariant checks on:
+// every cell in range, grid and tables the right shape, and the particle
+// count conserved where the rules promise it
+pub fn stepped(config: &SimulationConfig, steps: usize) -> Result<ParticleGrid, String> {
+    let mut grid = ParticleGrid::from_config(config);
+    grid.check_invariants().map_err(|e| format!("fresh grid: {}", e))?;
+    // Hand-set reactions may create particles from empty space or turn
+    // them into it, so counts are only checked without those
+    let rules = &grid.rules;
+    let reacts_with_empty = rules.copy_type[1..].iter().chain(&rules.replace_type[1..]).any(|&t| t == 0);
+    grid.enable_invariant_checks(!reacts_with_empty);
+    for _ in 0..steps {
+        grid.try_step()?;
+    }
+    Ok(grid)
+}
+
+// The same run under every boundary mode, so windows and moves past each
+// edge are exercised whatever mode the config drew
+pub fn check_boundaries(config: &SimulationConfig, steps: usize) -> Result<(), String> {
+    for boundary in BOUNDARIES {
+        stepped(&SimulationConfig { boundary, ..config.clone() }, steps)
+            .map_err(|e| format!("{:?} boundary: {}", boundary, e))?;
+    }
+    Ok(())
+}
+
+// Runs are deterministic: replay_to twice, and stepping by hand, all land
+// on the same state
+pub fn check_replay(config: &SimulationConfig, steps: usize) -> Result<(), String> {
+    let seed = config.seed.unwrap_or_default();
+    let first = ParticleGrid::replay_to(config, seed, steps as u64)?;
+    let second = ParticleGrid::replay_to(config, seed, steps as u64)?;
+    let by_hand = stepped(&SimulationConfig { seed: Some(seed), ..config.clone() }, steps)?;
+    if first.state_hash() != second.state_hash() {
+        return Err("replaying the same seed gave different grids".to_string());
+    }
+    if first.state_hash() != by_hand.state_hash() || first.generation != by_hand.generation {
+        return Err("replay_to does not match stepping by hand".to_string());
+    }
+    Ok(())
+}
+
+// Every serialized form reads back to the grid, rules and config it was
+// written from
+pub fn check_round_trips(grid: &ParticleGrid) -> Result<(), String> {
+    let cells = grid.export_grid();
+    let config = grid.config();
+
+    let json = SimulationConfig::from_json(&config.to_json())?;
+    if json != config {
+        return Err("config changed across a JSON round trip".to_string());
+    }
+
+    let loaded = ParticleGrid::load_state(&grid.save_state())?;
+    if loaded.export_grid() != cells || loaded.generation != grid.generation || loaded.config() != config {
+        return Err("save_state / load_state changed the grid".to_string());
+    }
+
+    let mut copy = ParticleGrid::from_config(&config);
+    copy.reseed_from_string(&grid.export_pattern_string())?;
+    if copy.export_grid() != cells {
+        return Err("pattern string changed the grid".to_string());
+    }
+
+    let mut copy = ParticleGrid::from_config(&config);
+    copy.import_grid(vec![0; cells.len()]);
+    if !copy.import_grid(grid.export_grid_compressed()) || copy.export_grid() != cells {
+        return Err("compressed export changed the grid".to_string());
+    }
+
+    // Tables that mean the same can differ (a reaction replacing a type
+    // with itself is no reaction, and empty space never moves, so its
+    // affinities are unused), so compare the rule text and particle rows
+    let rules = grid.export_rules();
+    copy.load_rules(&rules)?;
+    if copy.export_rules() != rules || copy.rules.affinity[1..] != grid.rules.affinity[1..] {
+        return Err(format!("rule text changed across load_rules:\n{}", rules));
+    }
+    Ok(())
+}
+
+// A patch from `from` to `to` survives encoding and turns one into the other
+pub fn check_patch(from: &ParticleGrid, to: &ParticleGrid) -> Result<(), String> {
+    let patch = GridPatch::from_bytes(&from.diff(to)?.to_bytes())?;
+    let mut grid = ParticleGrid::from_config(&from.config());
+    grid.import_grid(from.export_grid());
+    if !grid.apply_patch(&patch) || grid.export_grid() != to.export_grid() {
+        return Err("diff / apply_patch did not reproduce the grid".to_string());
+    }
+    Ok(())
+}
+
+// Untrusted input to every parser: garbage must come back as an error,
+// never a panic or a grid that breaks its invariants
+pub fn check_parsers(bytes: &[u8]) -> Result<(), String> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut grid = ParticleGrid::from_config(&config(&mut Source::new(bytes)));
+
+    if let Ok(config) = SimulationConfig::from_json(&text) {
+        // Large grids are valid but too slow to build per input
+        let config = SimulationConfig { size: config.size.min(MAX_SIZE), ..config };
+        ParticleGrid::from_config(&config).check_invariants().map_err(|e| format!("from_json: {}", e))?;
+    }
+    if let Ok(loaded) = ParticleGrid::load_state(bytes) {
+        loaded.check_invariants().map_err(|e| format!("load_state: {}", e))?;
+    }
+    if let Ok(patch) = GridPatch::from_bytes(bytes) {
+        grid.apply_patch(&patch);
+    }
+    grid.import_grid(bytes.to_vec());
+    let _ = grid.reseed_from_string(&text);
+    let _ = grid.load_rules(&text);
+    grid.check_invariants()
+}
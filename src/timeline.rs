@@ -0,0 +1,300 @@
+// Scrubbable run history. A Timeline steps its grid and keeps a keyframe
+// every `interval` generations: the full simulation state, with the cells
+// and the other per-cell layers (energy reserves, particle ids, terrain)
+// LZ4-compressed (see compress.rs) to a fraction of their raw size.
+// seek() restores the nearest keyframe at or before the target and steps
+// forward from it; stepping is deterministic, so this lands on exactly the
+// state the run had. Seeking past the recorded history runs on, recording
+// as it goes.
+//
+// Editing the grid (through grid_mut) starts a new branch: keyframes after
+// the edit are dropped. Stepping forward from an earlier point checks each
+// keyframe it reaches against the grid and drops the rest of the history
+// at the first mismatch, so only one consistent line is ever kept.
+//
+// Only the simulation is rewound; trails, tracked trajectories and
+// auto-reseed bookkeeping carry on from where they were.
+
+use std::collections::BTreeMap;
+use std::mem;
+
+use wasm_bindgen::prelude::*;
+
+use crate::compress;
+use crate::core::Simulation;
+use crate::ParticleGrid;
+
+struct Keyframe {
+    // Everything but the per-cell layers, which are compressed below
+    sim: Simulation,
+    cells: Vec<u8>,
+    // Reserves, ids and speeds as little-endian words in [x][y] order;
+    // empty while the layer is off
+    energy: Vec<u8>,
+    ids: Vec<u8>,
+    terrain: Vec<u8>,
+    hash: u64,
+}
+
+impl Keyframe {
+    fn bytes(&self) -> usize {
+        self.cells.len() + self.energy.len() + self.ids.len() + self.terrain.len()
+    }
+}
+
+fn pack(words: impl Iterator<Item = u32>) -> Vec<u8> {
+    let bytes: Vec<u8> = words.flat_map(u32::to_le_bytes).collect();
+    compress::compress(&bytes)
+}
+
+// The `size` x `size` words of a packed layer, as columns
+fn unpack(bytes: &[u8], size: usize) -> Result<Vec<Vec<u32>>, String> {
+    let raw = compress::decompress(bytes, size * size * 4)?;
+    if raw.len() != size * size * 4 {
+        return Err(format!("keyframe layer holds {} of {} bytes", raw.len(), size * size * 4));
+    }
+    let words: Vec<u32> = raw.chunks_exact(4).map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]])).collect();
+    Ok(words.chunks(size).map(<[u32]>::to_vec).collect())
+}
+
+#[wasm_bindgen]
+pub struct Timeline {
+    grid: ParticleGrid,
+    interval: u64,
+    // By generation
+    keyframes: BTreeMap<u64, Keyframe>,
+    // The grid was handed out for editing since the last keyframe check
+    edited: bool,
+}
+
+#[wasm_bindgen]
+impl Timeline {
+    // Record `grid` from its current generation on, keeping a keyframe
+    // every `interval` generations (at least 1)
+    #[wasm_bindgen(constructor)]
+    pub fn new(grid: ParticleGrid, interval: u64) -> Timeline {
+        let mut timeline = Timeline { grid, interval: interval.max(1), keyframes: BTreeMap::new(), edited: false };
+        timeline.keep(true);
+        timeline
+    }
+
+    #[wasm_bindgen]
+    pub fn step(&mut self) {
+        self.advance(1);
+    }
+
+    #[wasm_bindgen]
+    pub fn step_n(&mut self, steps: u32) {
+        self.advance(steps as u64);
+    }
+
+    // Move to generation `step`. Generations before the first keyframe
+    // can't be reached and go to that keyframe instead. Fails, leaving the
+    // grid where it was, if the keyframe doesn't decompress.
+    #[wasm_bindgen]
+    pub fn seek(&mut self, step: u64) -> Result<(), String> {
+        self.settle_edits();
+        let generation = self.grid.generation;
+        let Some((&start, _)) = self.keyframes.range(..=step).next_back().or(self.keyframes.first_key_value()) else {
+            return Ok(());
+        };
+        // Stepping on from where the grid is beats restoring an older keyframe
+        if !(start..=step).contains(&generation) {
+            self.restore(start)?;
+        }
+        self.advance(step.saturating_sub(self.grid.generation));
+        Ok(())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn generation(&self) -> f64 {
+        self.grid.generation as f64
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn interval(&self) -> f64 {
+        self.interval as f64
+    }
+
+    // Number of keyframes held
+    #[wasm_bindgen(getter)]
+    pub fn keyframes(&self) -> usize {
+        self.keyframes.len()
+    }
+
+    // Latest generation with a keyframe
+    #[wasm_bindgen(getter)]
+    pub fn last_keyframe(&self) -> f64 {
+        self.keyframes.keys().next_back().map_or(0.0, |&g| g as f64)
+    }
+
+    // Bytes held by the compressed keyframe layers, for budgeting
+    #[wasm_bindgen(getter)]
+    pub fn keyframe_bytes(&self) -> usize {
+        self.keyframes.values().map(Keyframe::bytes).sum()
+    }
+
+    #[wasm_bindgen]
+    pub fn export_grid(&self) -> Vec<u8> {
+        self.grid.export_grid()
+    }
+
+    #[wasm_bindgen]
+    pub fn export_region_rgba(&self, x0: usize, y0: usize, w: usize, h: usize) -> Vec<u8> {
+        self.grid.export_region_rgba(x0, y0, w, h)
+    }
+
+    // Hand the grid back at its current generation, dropping the history
+    #[wasm_bindgen]
+    pub fn into_grid(self) -> ParticleGrid {
+        self.grid
+    }
+}
+
+impl Timeline {
+    pub fn grid(&self) -> &ParticleGrid {
+        &self.grid
+    }
+
+    // The grid for editing; history after the current generation is dropped
+    pub fn grid_mut(&mut self) -> &mut ParticleGrid {
+        self.keyframes.split_off(&(self.grid.generation + 1));
+        self.edited = true;
+        &mut self.grid
+    }
+
+    fn advance(&mut self, steps: u64) {
+        self.settle_edits();
+        for _ in 0..steps {
+            self.grid.step();
+            if self.grid.generation.is_multiple_of(self.interval) {
+                self.keep(false);
+            }
+        }
+    }
+
+    // An edited grid is the new state of its generation
+    fn settle_edits(&mut self) {
+        if self.edited {
+            self.edited = false;
+            self.keep(true);
+        }
+    }
+
+    // Keyframe the current state. An existing keyframe here is kept if it
+    // matches; if not, or with `replace`, the run has branched and it and
+    // everything after it are dropped first.
+    fn keep(&mut self, replace: bool) {
+        let generation = self.grid.generation;
+        let hash = self.grid.state_hash();
+        if !replace && self.keyframes.get(&generation).is_some_and(|k| k.hash == hash) {
+            return;
+        }
+        self.keyframes.split_off(&generation);
+        let cells = compress::compress(&self.grid.export_grid());
+        // Clone the rest of the state without copying the layers
+        let live = &mut self.grid.sim;
+        let types = mem::take(&mut live.type_grid);
+        let energy = live.energy.as_mut().map(|e| mem::take(&mut e.grid));
+        let ids = live.ids.as_mut().map(|i| mem::take(&mut i.grid));
+        let terrain = mem::take(&mut live.rules.terrain.speed);
+        let sim = live.clone();
+
+        let keyframe = Keyframe {
+            sim,
+            cells,
+            energy: energy.as_deref().map_or(Vec::new(), |g| pack(g.iter().flatten().map(|e| e.to_bits()))),
+            ids: ids.as_deref().map_or(Vec::new(), |g| pack(g.iter().flatten().copied())),
+            terrain: if terrain.is_empty() { Vec::new() } else { pack(terrain.iter().map(|s| s.to_bits())) },
+            hash,
+        };
+        live.type_grid = types;
+        if let (Some(e), Some(grid)) = (&mut live.energy, energy) {
+            e.grid = grid;
+        }
+        if let (Some(i), Some(grid)) = (&mut live.ids, ids) {
+            i.grid = grid;
+        }
+        live.rules.terrain.speed = terrain;
+        self.keyframes.insert(generation, keyframe);
+    }
+
+    fn restore(&mut self, generation: u64) -> Result<(), String> {
+        let Some(keyframe) = self.keyframes.get(&generation) else {
+            return Ok(());
+        };
+        let size = keyframe.sim.size;
+        let cells = compress::decompress_cells(&keyframe.cells, size)?;
+        let mut sim = keyframe.sim.clone();
+        if let Some(energy) = &mut sim.energy {
+            let words = unpack(&keyframe.energy, size)?;
+            energy.grid = words.iter().map(|column| column.iter().map(|&w| f32::from_bits(w)).collect()).collect();
+        }
+        if let Some(ids) = &mut sim.ids {
+            ids.grid = unpack(&keyframe.ids, size)?;
+        }
+        if !keyframe.terrain.is_empty() {
+            let words = unpack(&keyframe.terrain, size)?;
+            sim.rules.terrain.speed = words.iter().flatten().map(|&w| f32::from_bits(w)).collect();
+        }
+        sim.type_grid = vec![vec![0; size]; size];
+        self.grid.sim = sim;
+        self.grid.set_cells(&cells);
+        self.grid.refresh_output();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SimulationConfig;
+    use crate::core::EnergyConfig;
+
+    fn timeline() -> Timeline {
+        let mut grid = ParticleGrid::from_config(&SimulationConfig {
+            size: 24,
+            num_types: 3,
+            density: 0.3,
+            seed: Some(8),
+            ..Default::default()
+        });
+        grid.set_energy(Some(EnergyConfig { income: vec![0.0, 0.05], ..Default::default() }));
+        grid.enable_particle_ids();
+        grid.set_terrain((0..24 * 24).map(|i| (i % 4) as f32 * 0.5).collect());
+        Timeline::new(grid, 5)
+    }
+
+    fn layers(timeline: &Timeline) -> (Vec<Vec<f32>>, Vec<Vec<u32>>, Vec<f32>) {
+        let sim = &timeline.grid.sim;
+        (
+            sim.energy.as_ref().unwrap().grid.clone(),
+            sim.ids.as_ref().unwrap().grid.clone(),
+            sim.rules.terrain.speed.clone(),
+        )
+    }
+
+    #[test]
+    fn seek_restores_every_layer() {
+        let mut timeline = timeline();
+        timeline.step_n(12);
+        let at_twelve = (timeline.grid.state_hash(), layers(&timeline));
+        timeline.step_n(10);
+        timeline.seek(12).unwrap();
+        assert_eq!((timeline.grid.state_hash(), layers(&timeline)), at_twelve);
+        let cells: usize = timeline.keyframes.values().map(|k| k.cells.len()).sum();
+        assert!(timeline.keyframe_bytes() > cells);
+    }
+
+    #[test]
+    fn seek_reports_corrupt_keyframes() {
+        let mut timeline = timeline();
+        timeline.step_n(12);
+        timeline.keyframes.get_mut(&5).unwrap().energy.truncate(2);
+        assert!(timeline.seek(7).unwrap_err().contains("truncated"));
+        assert_eq!(timeline.grid.generation, 12);
+        // Later keyframes are still good
+        timeline.seek(10).unwrap();
+        assert_eq!(timeline.grid.generation, 10);
+    }
+}
@@ -0,0 +1,56 @@
+// Profiling spans for the `trace` feature. In the browser a span drops a
+// performance.mark at each end and a performance.measure between them, so
+// step phases show up on the devtools timeline; natively it is a `tracing`
+// span for whatever subscriber the host installs. Use the trace_span!
+// macro, which compiles to nothing without the feature.
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::JsCast;
+
+pub(crate) struct Span {
+    #[cfg(target_arch = "wasm32")]
+    name: &'static str,
+    #[cfg(target_arch = "wasm32")]
+    performance: Option<web_sys::Performance>,
+    #[cfg(not(target_arch = "wasm32"))]
+    _entered: tracing::span::EnteredSpan,
+}
+
+impl Span {
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn enter(name: &'static str) -> Span {
+        // Look performance up on the global object so spans also work in
+        // the worker threads of the parallel build, where there is no window
+        let performance = js_sys::Reflect::get(&js_sys::global(), &"performance".into())
+            .ok()
+            .and_then(|p| p.dyn_into::<web_sys::Performance>().ok());
+        if let Some(p) = &performance {
+            let _ = p.mark(&format!("{}-start", name));
+        }
+        Span { name, performance }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn enter(name: &'static str) -> Span {
+        Span {
+            _entered: tracing::trace_span!("particle_grid", phase = name).entered(),
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some(p) = &self.performance {
+            let start = format!("{}-start", self.name);
+            let end = format!("{}-end", self.name);
+            let _ = p.mark(&end);
+            // web-sys renames the binding when its unstable APIs (needed by
+            // the webgpu feature) are enabled
+            #[cfg(not(web_sys_unstable_apis))]
+            let _ = p.measure_with_start_mark_and_end_mark(self.name, &start, &end);
+            #[cfg(web_sys_unstable_apis)]
+            let _ = p.measure_with_str_and_end_mark(self.name, &start, &end);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+// Afterimage buffer for motion-blur rendering. Every occupied cell is
+// stamped at full strength after each step; once its particle leaves, the
+// imprint fades by the decay factor per step. Blending the imprints over
+// the empty color makes the slow lattice moves read as continuous motion
+// without any post-processing on the JS side.
+
+use wasm_bindgen::prelude::*;
+
+use crate::ParticleGrid;
+
+pub(crate) struct Trails {
+    // Fraction of an imprint left after each step
+    decay: f32,
+    // Per cell, indexed [x * size + y]: strength and the type that left it
+    strength: Vec<f32>,
+    kind: Vec<u8>,
+}
+
+impl Trails {
+    fn new(decay: f32, grid: &[Vec<u8>]) -> Trails {
+        let size = grid.len();
+        let mut trails = Trails { decay, strength: vec![0.0; size * size], kind: vec![0; size * size] };
+        trails.update(grid);
+        trails
+    }
+
+    pub(crate) fn update(&mut self, grid: &[Vec<u8>]) {
+        let size = grid.len();
+        if self.strength.len() != size * size {
+            *self = Trails { decay: self.decay, strength: vec![0.0; size * size], kind: vec![0; size * size] };
+        }
+        for (x, column) in grid.iter().enumerate() {
+            for (y, &t) in column.iter().enumerate() {
+                let i = x * size + y;
+                if t != 0 {
+                    self.strength[i] = 1.0;
+                    self.kind[i] = t;
+                } else {
+                    self.strength[i] *= self.decay;
+                }
+            }
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl ParticleGrid {
+    // Start recording trails; `decay` (0-1) is how much of an imprint is
+    // left after each step, so 0.9 fades over a few dozen steps
+    #[wasm_bindgen]
+    pub fn enable_trails(&mut self, decay: f32) {
+        let decay = if decay.is_finite() { decay.clamp(0.0, 1.0) } else { 0.0 };
+        self.trails = Some(Trails::new(decay, &self.sim.type_grid));
+    }
+
+    #[wasm_bindgen]
+    pub fn disable_trails(&mut self) {
+        self.trails = None;
+    }
+
+    // The grid as RGBA pixels in export_grid's layout, with empty cells
+    // blended towards the color of whatever last passed through them.
+    // Plain export when trails are off.
+    #[wasm_bindgen]
+    pub fn export_trails_rgba(&self) -> Vec<u8> {
+        let size = self.size;
+        let mut data = Vec::with_capacity(size * size * 4);
+        for y in 0..size {
+            for x in 0..size {
+                let t = self.type_grid[x][y];
+                let pixel = match &self.trails {
+                    Some(trails) if t == 0 => {
+                        let i = x * size + y;
+                        let (bg, fg) = (self.rgba(0), self.rgba(trails.kind[i]));
+                        let s = trails.strength[i];
+                        let mut pixel = bg;
+                        for c in 0..3 {
+                            pixel[c] = (bg[c] as f32 + (fg[c] as f32 - bg[c] as f32) * s).round() as u8;
+                        }
+                        pixel
+                    }
+                    _ => self.rgba(t),
+                };
+                data.extend_from_slice(&pixel);
+            }
+        }
+        data
+    }
+}
@@ -0,0 +1,186 @@
+// Position histories of selected particles, for plotting paths and
+// measuring diffusion. A particle moves at most one cell per step, so each
+// step it is looked up around where it was last seen rather than searched
+// for over the whole grid. Positions are unwrapped: on a wrapping grid a
+// particle crossing an edge keeps counting past it instead of jumping back,
+// so displacements stay meaningful.
+//
+// msd() turns the recorded paths into the usual diffusion observables: the
+// mean squared displacement over a lag of `window` generations, averaged
+// over every start point of every path, and the diffusion constant
+// D = MSD / (4 * window) of a 2D random walk with the same spread.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use wasm_bindgen::prelude::*;
+
+use crate::core::BoundaryMode;
+use crate::ParticleGrid;
+
+pub(crate) struct Path {
+    // Fixed for life, since a conversion makes a new particle
+    p_type: u8,
+    // Grid cell it was last seen in; None once it is gone
+    cell: Option<(usize, usize)>,
+    // (generation, unwrapped x, unwrapped y), oldest first
+    pub(crate) points: VecDeque<(u64, i64, i64)>,
+}
+
+pub(crate) struct Trajectories {
+    // Points kept per particle
+    max_len: usize,
+    pub(crate) paths: BTreeMap<u32, Path>,
+}
+
+impl Default for Trajectories {
+    fn default() -> Self {
+        Trajectories { max_len: 1000, paths: BTreeMap::new() }
+    }
+}
+
+impl Trajectories {
+    // Append the current position of every tracked particle still alive
+    pub(crate) fn record(&mut self, ids: &[Vec<u32>], generation: u64, wrap: bool) {
+        let size = ids.len() as isize;
+        for (&id, path) in self.paths.iter_mut() {
+            let Some((x, y)) = path.cell else {
+                continue;
+            };
+            let found = (-1..=1isize)
+                .flat_map(|dy| (-1..=1isize).map(move |dx| (dx, dy)))
+                .find_map(|(dx, dy)| {
+                    let (i, j) = (x as isize + dx, y as isize + dy);
+                    let (i, j) = if wrap { (i.rem_euclid(size), j.rem_euclid(size)) } else { (i, j) };
+                    let inside = (0..size).contains(&i) && (0..size).contains(&j);
+                    (inside && ids[i as usize][j as usize] == id).then_some((dx, dy, i as usize, j as usize))
+                });
+            let Some((dx, dy, i, j)) = found else {
+                path.cell = None;
+                continue;
+            };
+            let &(_, ux, uy) = path.points.back().expect("a tracked path starts with a point");
+            path.cell = Some((i, j));
+            path.points.push_back((generation, ux + dx as i64, uy + dy as i64));
+            while path.points.len() > self.max_len {
+                path.points.pop_front();
+            }
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl ParticleGrid {
+    // Start recording the path of particle `id` (see export_particle_ids)
+    // from its current cell. Returns false if ids are off or no particle
+    // has that id; tracking an already tracked particle starts it afresh.
+    #[wasm_bindgen]
+    pub fn track_particle(&mut self, id: u32) -> bool {
+        self.sim.sync_identities();
+        let Some(ids) = self.sim.ids.as_ref().filter(|_| id != 0) else {
+            return false;
+        };
+        let cell = ids.grid.iter().enumerate().find_map(|(x, column)| {
+            column.iter().position(|&i| i == id).map(|y| (x, y))
+        });
+        let Some((x, y)) = cell else {
+            return false;
+        };
+        let path = Path {
+            p_type: self.sim.type_grid[x][y],
+            cell: Some((x, y)),
+            points: VecDeque::from([(self.generation, x as i64, y as i64)]),
+        };
+        self.trajectories.paths.insert(id, path);
+        true
+    }
+
+    #[wasm_bindgen]
+    pub fn untrack_particle(&mut self, id: u32) {
+        self.trajectories.paths.remove(&id);
+    }
+
+    // Ids with a recorded path, including particles that have since gone
+    #[wasm_bindgen]
+    pub fn tracked_particles(&self) -> Vec<u32> {
+        self.trajectories.paths.keys().copied().collect()
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_trajectories(&mut self) {
+        self.trajectories.paths.clear();
+    }
+
+    // Points kept per particle (at least 1); longer paths lose their
+    // oldest points
+    #[wasm_bindgen]
+    pub fn set_trajectory_length(&mut self, points: usize) {
+        let trajectories = &mut self.trajectories;
+        trajectories.max_len = points.max(1);
+        for path in trajectories.paths.values_mut() {
+            while path.points.len() > trajectories.max_len {
+                path.points.pop_front();
+            }
+        }
+    }
+
+    // Recorded path of particle `id` as (generation, x, y) triples, oldest
+    // first; empty if it is not tracked. Recording stops when the particle
+    // is converted, starves or leaves the grid.
+    #[wasm_bindgen]
+    pub fn get_trajectory(&self, id: u32) -> Vec<f64> {
+        let Some(path) = self.trajectories.paths.get(&id) else {
+            return Vec::new();
+        };
+        path.points.iter().flat_map(|&(g, x, y)| [g as f64, x as f64, y as f64]).collect()
+    }
+
+    // Mean squared displacement and diffusion constant (in cells^2 per
+    // generation) over `window` generations as (msd, d) pairs: first over
+    // all tracked particles, then for each type in turn. Pairs without a
+    // path long enough to measure are NaN.
+    #[wasm_bindgen]
+    pub fn msd(&self, window: usize) -> Vec<f64> {
+        let window = window.max(1);
+        let mut sums = vec![(0.0f64, 0usize); self.num_types + 1];
+        for path in self.trajectories.paths.values() {
+            let points = &path.points;
+            for (&(g0, x0, y0), &(g1, x1, y1)) in points.iter().zip(points.iter().skip(window)) {
+                // Paths are recorded every step, but skip any gap all the same
+                if g1 - g0 != window as u64 {
+                    continue;
+                }
+                let d2 = ((x1 - x0).pow(2) + (y1 - y0).pow(2)) as f64;
+                for t in [0, path.p_type as usize] {
+                    if let Some(sum) = sums.get_mut(t) {
+                        sum.0 += d2;
+                        sum.1 += 1;
+                    }
+                }
+            }
+        }
+        sums.iter()
+            .flat_map(|&(sum, n)| {
+                let msd = if n > 0 { sum / n as f64 } else { f64::NAN };
+                [msd, msd / (4.0 * window as f64)]
+            })
+            .collect()
+    }
+}
+
+impl ParticleGrid {
+    // Called after every step
+    pub(crate) fn record_trajectories(&mut self) {
+        if self.trajectories.paths.is_empty() {
+            return;
+        }
+        let Some(ids) = &self.sim.ids else {
+            // Ids were switched off, so nothing can be followed any more
+            for path in self.trajectories.paths.values_mut() {
+                path.cell = None;
+            }
+            return;
+        };
+        let wrap = self.sim.rules.boundary == BoundaryMode::Wrap;
+        self.trajectories.record(&ids.grid, self.sim.generation, wrap);
+    }
+}
@@ -0,0 +1,90 @@
+// JS mirrors of the core's mode enums. The core keeps its enums free of
+// wasm-bindgen; each gets a #[wasm_bindgen] twin here under the same JS
+// name, with conversions both ways, plus the ParticleGrid methods that take
+// or return one.
+
+use wasm_bindgen::prelude::*;
+
+use crate::core::{BoundaryMode, InitPattern, MovementMode, Symmetry};
+use crate::ParticleGrid;
+
+// Declare a JS enum with the same variants (and discriminants) as a core
+// enum, and the conversions between them
+macro_rules! js_enum {
+    ($js:ident => $core:ident { $($variant:ident = $value:literal),* $(,)? }) => {
+        #[wasm_bindgen(js_name = $core)]
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub enum $js {
+            $($variant = $value),*
+        }
+
+        impl From<$js> for $core {
+            fn from(value: $js) -> $core {
+                match value {
+                    $($js::$variant => $core::$variant),*
+                }
+            }
+        }
+
+        impl From<$core> for $js {
+            fn from(value: $core) -> $js {
+                match value {
+                    $($core::$variant => $js::$variant),*
+                }
+            }
+        }
+    };
+}
+
+js_enum!(JsBoundaryMode => BoundaryMode { Clamp = 0, Wrap = 1, Reflect = 2, Absorb = 3 });
+js_enum!(JsMovementMode => MovementMode { Greedy = 0, Threshold = 1, Centroid = 2 });
+js_enum!(JsSymmetry => Symmetry { None = 0, Mirror = 1, Four = 2, Eight = 3 });
+js_enum!(JsInitPattern => InitPattern {
+    Uniform = 0,
+    Stripes = 1,
+    Rings = 2,
+    Blobs = 3,
+    CenterDisk = 4,
+    TwoPhase = 5,
+});
+
+#[wasm_bindgen]
+impl ParticleGrid {
+    #[wasm_bindgen(js_name = set_boundary_mode)]
+    pub fn js_set_boundary_mode(&mut self, mode: JsBoundaryMode) {
+        self.set_boundary_mode(mode.into());
+    }
+
+    #[wasm_bindgen(getter = boundary_mode)]
+    pub fn js_boundary_mode(&self) -> JsBoundaryMode {
+        self.boundary_mode().into()
+    }
+
+    // See Simulation::set_movement_mode
+    #[wasm_bindgen(js_name = set_movement_mode)]
+    pub fn js_set_movement_mode(&mut self, mode: JsMovementMode, threshold: f32) {
+        self.set_movement_mode(mode.into(), threshold);
+    }
+
+    #[wasm_bindgen(getter = movement_mode)]
+    pub fn js_movement_mode(&self) -> JsMovementMode {
+        self.movement_mode().into()
+    }
+
+    // See ParticleGrid::set_symmetry
+    #[wasm_bindgen(js_name = set_symmetry)]
+    pub fn js_set_symmetry(&mut self, symmetry: JsSymmetry) {
+        self.set_symmetry(symmetry.into());
+    }
+
+    #[wasm_bindgen(getter = symmetry)]
+    pub fn js_symmetry(&self) -> JsSymmetry {
+        self.symmetry().into()
+    }
+
+    // See ParticleGrid::reseed
+    #[wasm_bindgen(js_name = reseed)]
+    pub fn js_reseed(&mut self, pattern: JsInitPattern) {
+        self.reseed(pattern.into());
+    }
+}
@@ -0,0 +1,27 @@
+// The properties in testing.rs over a fixed batch of generated configs.
+// The fuzz targets in fuzz/ search the same space open-endedly; a failing
+// fuzz input can be replayed here with testing::check_bytes.
+#![cfg(feature = "testing")]
+
+use particle_affinity_wasm::testing::{self, Source};
+use particle_affinity_wasm::ParticleGrid;
+
+#[test]
+fn generated_configs_hold_every_property() {
+    testing::check_random(24, 1).unwrap();
+}
+
+#[test]
+fn empty_input_is_a_valid_case() {
+    testing::check_bytes(&[]).unwrap();
+    testing::check_parsers(&[]).unwrap();
+}
+
+#[test]
+fn truncated_saves_fail_cleanly() {
+    let config = testing::config(&mut Source::new(b"truncated saves"));
+    let save = ParticleGrid::from_config(&config).save_state();
+    for len in 0..=save.len() {
+        testing::check_parsers(&save[..len]).unwrap();
+    }
+}